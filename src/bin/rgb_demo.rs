@@ -1,7 +1,7 @@
 use std::ops::Range;
 
 use net_neurons::{
-    nnetwork::FloatType,
+    nnetwork::{FloatType, LogSpaced},
     recolor::ReColor,
 };
 
@@ -48,8 +48,11 @@ fn main() {
     const TRAINING_CYCLES: usize = 50000;
     // The number of data points to use before updating the network by back propagation
     const TRAINING_BATCH_SIZE: usize = 100;
-    // The range of learning rates to be used. Will be logspaced.
-    const LEARNING_RATE: Range<FloatType> = 0.1..0.01;
+    // The learning rate will be logspaced from start to end.
+    const LEARNING_RATE: LogSpaced = LogSpaced {
+        start: 0.1,
+        end: 0.01,
+    };
     // Limit the input coordinate space
     const X_RANGE: Range<FloatType> = -1. ..1.;
     const Y_RANGE: Range<FloatType> = -1. ..1.;
@@ -59,10 +62,11 @@ fn main() {
     recolor.train(
         TRAINING_CYCLES,
         TRAINING_BATCH_SIZE,
-        LEARNING_RATE,
+        &LEARNING_RATE,
         &X_RANGE,
         &Y_RANGE,
         VERBOSE,
+        None,
     );
 
     // Save the resulting network. Will overwrite any existing file!
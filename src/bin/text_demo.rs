@@ -1,4 +1,4 @@
-use net_neurons::nnetwork::FloatType;
+use net_neurons::nnetwork::{Constant, FloatType};
 use net_neurons::retext::CharSet;
 use net_neurons::retext::ReText;
 
@@ -50,12 +50,18 @@ fn main() {
     // The number of updates to the netowrk
     const TRAINING_CYCLES: usize = 100;
     // Step size will be this fraction times the calculated gradient
-    const LEARNING_RATE: FloatType = 0.1;
+    const LEARNING_RATE: Constant = Constant(0.1);
     // The number of data points to use before updating the network by back propagation
     const TRAINING_BATCH_SIZE: usize = 1000;
     // Lots of text...or not
     const VERBOSE: bool = true;
-    retext.train(TRAINING_CYCLES, LEARNING_RATE, TRAINING_BATCH_SIZE, VERBOSE);
+    retext.train(
+        TRAINING_CYCLES,
+        &LEARNING_RATE,
+        TRAINING_BATCH_SIZE,
+        VERBOSE,
+        None,
+    );
 
     // Save the resulting network. Will overwrite any existing file!
     const EXPORT_FILENAME: &str = "names.param";
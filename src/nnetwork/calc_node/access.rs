@@ -1,19 +1,19 @@
 use std::{cell::RefCell, fmt::Display, ops::Deref, rc::Rc};
 
-use super::{CalcNode, CalcNodeCore, FloatType, NodeShape, NodeType, VecOrientation};
+use super::{CalcNode, CalcNodeCore, Float, NodeShape, NodeType, VecOrientation};
 
 /// Read-only access to the private fields
-impl CalcNodeCore {
-    pub fn vals(&self) -> &[FloatType] {
+impl<T: Float> CalcNodeCore<T> {
+    pub fn vals(&self) -> &[T] {
         &self._vals
     }
-    pub fn grad(&self) -> &[FloatType] {
+    pub fn grad(&self) -> &[T] {
         &self._grad
     }
-    pub fn parents(&self) -> &[CalcNode]{
+    pub fn parents(&self) -> &[CalcNode<T>]{
         &self._parent_nodes
     }
-    pub fn back_propagation(&self) -> &Option<Box<dyn Fn(CalcNode)>>{
+    pub fn back_propagation(&self) -> &Option<Box<dyn Fn(CalcNode<T>)>>{
         &self._back_propagation
     }
     pub fn shape(&self) -> &NodeShape {
@@ -21,15 +21,15 @@ impl CalcNodeCore {
     }
 }
 
-impl Deref for CalcNode {
-    type Target = Rc<RefCell<CalcNodeCore>>;
+impl<T> Deref for CalcNode<T> {
+    type Target = Rc<RefCell<CalcNodeCore<T>>>;
 
     fn deref(&self) -> &Self::Target {
         &self._node
     }
 }
 
-impl CalcNode {
+impl<T: Float> CalcNode<T> {
     /// Returns the size, i.e., the number of values in the node
     pub fn len(&self) -> usize {
         {
@@ -43,7 +43,7 @@ impl CalcNode {
     }
 }
 
-impl CalcNode {
+impl<T: Float> CalcNode<T> {
     /// Returns the enum [NodeType] to easily categorize what kind of node we are dealing with.
     pub fn node_type(&self) -> NodeType {
         match self.borrow()._shape {
@@ -71,7 +71,7 @@ impl CalcNode {
     }
 }
 
-impl Display for CalcNode {
+impl<T: Float> Display for CalcNode<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.node_type() {
             NodeType::None => {
@@ -137,9 +137,9 @@ impl Display for NodeType {
 }
 
 // Access methods
-impl CalcNode {
+impl<T: Float> CalcNode<T> {
     /// The returned [CalcNode] copies are shallow. The underlying [CalcNodeCore]s are not copied.
-    pub fn copy_parents(&self) -> Vec<CalcNode> {
+    pub fn copy_parents(&self) -> Vec<CalcNode<T>> {
         self.borrow()._parent_nodes.clone()
     }
 
@@ -148,58 +148,116 @@ impl CalcNode {
     }
 
     /// Returns a [Vec] containing the raw float values.
-    pub fn copy_vals(&self) -> Vec<FloatType> {
+    pub fn copy_vals(&self) -> Vec<T> {
         self.borrow()._vals.clone()
     }
 
     /// Returns a [Vec] containing the raw float gradient values.
-    pub fn copy_grad(&self) -> Vec<FloatType> {
+    pub fn copy_grad(&self) -> Vec<T> {
         self.borrow()._grad.clone()
     }
 
+    /// Returns a copy of the values as one vector per row, the inverse of [CalcNode::from_rows].
+    pub fn to_rows(&self) -> Vec<Vec<T>> {
+        let (_, n_cols) = self.shape();
+        if n_cols == 0 {
+            return Vec::new();
+        }
+        self.borrow()._vals.chunks(n_cols).map(|row| row.to_vec()).collect()
+    }
+
     /// Returns a specific value by index
-    pub fn value_indexed(&self, i: usize) -> FloatType {
+    pub fn value_indexed(&self, i: usize) -> T {
         self.borrow()._vals[i]
     }
 
     /// Returns a specific gradient value by index
-    pub fn gradient_indexed(&self, i: usize) -> FloatType {
+    pub fn gradient_indexed(&self, i: usize) -> T {
         self.borrow()._grad[i]
     }
 
+    /// Returns the index of the largest value. Ties go to the first occurrence. Panics if the node is empty.
+    pub fn argmax(&self) -> usize {
+        assert!(!self.is_empty(), "Cannot find the maximum of an empty node.");
+        self.borrow()
+            ._vals
+            .iter()
+            .enumerate()
+            .fold((0, T::neg_infinity()), |(i_max, v_max), (i, &v)| {
+                if v > v_max {
+                    (i, v)
+                } else {
+                    (i_max, v_max)
+                }
+            })
+            .0
+    }
+
     /// Overwrites all values with the ones supplied. Will panic if a wrong number of values are given.
-    pub fn set_vals(&mut self, vals: &[FloatType]) {
+    pub fn set_vals(&mut self, vals: &[T]) {
         assert_eq!(vals.len(), self.borrow()._vals.len());
         self.borrow_mut()._vals = vals.to_vec();
     }
     
     /// Sets one specific value by index.
-    pub fn set_value_indexed(&mut self, i:usize, val: FloatType) {
+    pub fn set_value_indexed(&mut self, i:usize, val: T) {
         assert!( i < self.len());
         self.borrow_mut()._vals[i] = val;
     }
 
+    /// Sets the values at the given indices, e.g., to mask or prune some weights, leaving all others unchanged. Will panic if the number of indices and values differ or if an index is out of bounds.
+    pub fn set_vals_at(&mut self, indices: &[usize], vals: &[T]) {
+        assert_eq!(indices.len(), vals.len(), "Every index needs exactly one value.");
+        let len = self.len();
+        let mut node = self.borrow_mut();
+        for (&i, &val) in indices.iter().zip(vals) {
+            assert!(i < len, "Index {i} is out of bounds for {len} values.");
+            node._vals[i] = val;
+        }
+    }
+
     /// Increments all gradients element-wise with the values supplied.
-    pub fn add_grad(&mut self, grad: &[FloatType]) {
+    pub fn add_grad(&mut self, grad: &[T]) {
         assert_eq!(grad.len(), self.borrow()._grad.len());
         self.borrow_mut()._grad.iter_mut().zip(grad.iter()).for_each(|(target, &value)| {
             *target += value});
     }
     
-    /// Resets all gradient values to zero.
+    /// Resets all gradient values to zero. Nodes created in inference mode, see [CalcNode::without_gradients], get their gradients allocated here, which [CalcNode::back_propagation] does for every node it reaches.
     pub fn reset_grad(&mut self){
-        self.borrow_mut()._grad.iter_mut().for_each(|g| *g = 0.);
+        let mut node = self.borrow_mut();
+        let len = node._vals.len();
+        node._grad.clear();
+        node._grad.resize(len, T::zero());
     }
     
     /// Overwrites all gradient values with the ones supplied. Will panic if a wrong number of values are given.
-    pub fn set_grad(&mut self, grad: &[FloatType]) {
+    pub fn set_grad(&mut self, grad: &[T]) {
         assert_eq!(grad.len(), self.borrow()._grad.len());
         self.borrow_mut()._grad = grad.to_vec();
     }
     
     /// Sets one specific gradient by index.
-    pub fn set_gradient_indexed(&mut self, i:usize, val: FloatType) {
+    pub fn set_gradient_indexed(&mut self, i:usize, val: T) {
         assert!( i < self.len());
         self.borrow_mut()._grad[i] = val;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::nnetwork::CalcNode;
+
+    #[test]
+    fn partial_update_of_values() {
+        let mut node = CalcNode::new_col_vector(vec![1., 2., 3., 4.]);
+        node.set_vals_at(&[0, 2], &[0., -3.]);
+        assert_eq!(node.copy_vals(), &[0., 2., -3., 4.]);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn partial_update_out_of_bounds() {
+        CalcNode::new_col_vector(vec![1., 2.]).set_vals_at(&[2], &[0.]);
+    }
+}
@@ -1,9 +1,37 @@
 use std::{cell::RefCell, collections::HashSet};
 
-use super::{CalcNode, CalcNodeCore, FloatType};
+use super::{CalcNode, CalcNodeCore, Float, FloatType, ForwardFunc, INFERENCE_MODE};
 
-impl CalcNode {
+// Returns a sorted list of CalcNodes
+fn topo_sort<T>(root: &CalcNode<T>) -> Vec<CalcNode<T>> {
+    // Recursive function that does the actual sorting
+    fn topo_sort_recursive<T>(
+        node: &CalcNode<T>,
+        visited: &mut HashSet<usize>,
+        out: &mut Vec<CalcNode<T>>,
+    ) {
+        fn ptr_as_usize<T>(node: &CalcNode<T>) -> usize {
+            (node.as_ptr() as *const CalcNodeCore<T>) as usize
+        }
+        if !visited.contains(&ptr_as_usize(node)) {
+            visited.insert(ptr_as_usize(node));
+            node.borrow()._parent_nodes.iter().for_each(|parent| {
+                topo_sort_recursive(parent, visited, out);
+            });
+            out.push(node.clone());
+        }
+    }
+    // These container will be sent down the recursive calls
+    let mut visited: HashSet<usize> = HashSet::new();
+    let mut sorted: Vec<CalcNode<T>> = Vec::new();
+    // Finds all parents (and their parents) and adds them to the vector before adding the root
+    topo_sort_recursive(root, &mut visited, &mut sorted);
+    sorted
+}
+
+impl<T: Float> CalcNode<T> {
     /// Recalculates the gradients of all nodes leading up to the current one. The gradient of the current node is set to unity.
+    /// The order in which the nodes are visited is only calculated the first time and then cached, since the graph does not change.
     /// 
     /// # Example
     /// ```
@@ -16,43 +44,27 @@ impl CalcNode {
     /// assert_eq!(b.gradient_indexed(0), 1.);
     /// ```
     pub fn back_propagation(&mut self) {
-        // Returns a sorted list of CalcNodes
-        fn topo_sort(root: &CalcNode) -> Vec<CalcNode> {
-            // Recursive function that does the actual sorting
-            fn topo_sort_recursive(
-                node: &CalcNode,
-                visited: &mut HashSet<usize>,
-                out: &mut Vec<CalcNode>,
-            ) {
-                fn ptr_as_usize(node: &CalcNode) -> usize {
-                    (node.as_ptr() as *const CalcNodeCore) as usize
-                }
-                if !visited.contains(&ptr_as_usize(node)) {
-                    visited.insert(ptr_as_usize(node));
-                    node.borrow()._parent_nodes.iter().for_each(|parent| {
-                        topo_sort_recursive(parent, visited, out);
-                    });
-                    out.push(node.clone());
-                }
-            }
-            // These container will be sent down the recursive calls
-            let mut visited: HashSet<usize> = HashSet::new();
-            let mut sorted: Vec<CalcNode> = Vec::new();
-            // Finds all parents (and their parents) and adds them to the vector before adding the root
-            topo_sort_recursive(root, &mut visited, &mut sorted);
-            sorted
+        // Nodes created in inference mode have neither gradients nor parents
+        if self.borrow()._grad.is_empty() {
+            return;
         }
-
-        // The final result will be at the end of the vector
-        let mut sorted = topo_sort(self);
+        // The root is not cached together with its ancestors since that would create a reference cycle
+        let cached = self.borrow()._sorted_ancestors.clone();
+        let mut ancestors = cached.unwrap_or_else(|| {
+            let mut sorted = topo_sort(self);
+            // The root will be at the end of the vector
+            sorted.pop();
+            self.borrow_mut()._sorted_ancestors = Some(sorted.clone());
+            sorted
+        });
         // Initialise all gradients to zero
-        sorted
+        ancestors
             .iter_mut()
             .for_each(|node| node.reset_grad());
         // Initialise the root gradient to unity
-        self.set_grad(&vec![1.;self.len()]);
+        self.set_grad(&vec![T::one(); self.len()]);
         // Back propagate all other gradients
-        sorted.iter().rev().for_each(|node| {
+        std::iter::once(&*self).chain(ancestors.iter().rev()).for_each(|node| {
             // The original nodes will not have a differentiation function
             if let Some(f) = &node.borrow()._back_propagation {
                 (f)(node.clone())
@@ -60,8 +72,67 @@ impl CalcNode {
         });
     }
 
+    // Sets the nodes that the values were calculated from. Ignored in inference mode.
+    pub(super) fn set_parents(&self, parents: Vec<CalcNode<T>>) {
+        if !Self::gradients_disabled() {
+            self.borrow_mut()._parent_nodes = parents;
+        }
+    }
+
+    // Sets the function that calculates the gradients of the parents. Ignored in inference mode.
+    pub(super) fn set_back_propagation(&self, back_propagation: Box<dyn Fn(CalcNode<T>)>) {
+        if !Self::gradients_disabled() {
+            self.borrow_mut()._back_propagation = Some(back_propagation);
+        }
+    }
+
+    // True while inside [CalcNode::without_gradients]
+    pub(super) fn gradients_disabled() -> bool {
+        INFERENCE_MODE.with(|mode| mode.get())
+    }
+
+    /// Sets the function that recalculates the values of the node from its parents. Nodes created by an operation should set one if they are to be part of a recorded calculation, see [CalcNode::recalculate]. Ignored in inference mode, see [CalcNode::without_gradients].
+    pub fn set_forward(&mut self, forward: Box<ForwardFunc<T>>) {
+        if !Self::gradients_disabled() {
+            self.borrow_mut()._forward = Some(forward);
+        }
+    }
+
+    /// True if the node is a leaf or knows how to recalculate its values.
+    pub fn can_recalculate(&self) -> bool {
+        self.borrow()._parent_nodes.is_empty() || self.borrow()._forward.is_some()
+    }
+
+    /// Recalculates the values from the parents, which must already be up to date. Leaf nodes are left as they are. Panics if the node has parents but no way to recalculate its values.
+    pub fn recalculate(&mut self) {
+        if self.borrow()._parent_nodes.is_empty() {
+            return;
+        }
+        let vals = {
+            let node = self.borrow();
+            let forward = node
+                ._forward
+                .as_ref()
+                .expect("Node does not know how to recalculate its values.");
+            (forward)(self)
+        };
+        self.set_vals(&vals);
+    }
+
+    /// Returns all nodes leading up to this one, in the order they have to be calculated. The node itself is the last one.
+    pub fn topologically_sorted(&self) -> Vec<CalcNode<T>> {
+        let cached = self.borrow()._sorted_ancestors.clone();
+        match cached {
+            Some(mut ancestors) => {
+                ancestors.push(self.clone());
+                ancestors
+            }
+            None => topo_sort(self),
+        }
+    }
+
     /// Decends the gradient by a fraction of the calculated gradient.
-    pub fn decend_grad(&mut self, learning_rate: FloatType) {
+    pub fn decend_grad(&mut self, learning_rate: T) {
         let mut tmp = RefCell::new(CalcNodeCore::default());
         // Bring the node outside the RefCell since we need to borrow both values and gradients at the same time
         self.swap(&tmp);
@@ -69,7 +140,68 @@ impl CalcNode {
         t._vals
             .iter_mut()
             .zip(t._grad.iter())
-            .for_each(|(v, d)| *v -= learning_rate * d);
+            .for_each(|(v, &d)| *v -= learning_rate * d);
         self.swap(&tmp);
     }
 }
+
+impl CalcNode<FloatType> {
+    /// Runs the calculation in inference mode, where the created nodes keep only their values. No gradients are allocated and neither the parents nor the back propagation functions are stored, so the intermediate results are freed as soon as they are no longer used. Calling [CalcNode::back_propagation] on such a node does nothing. If the node is later used in a calculation with gradients it acts like a constant, i.e., its gradient is calculated but nothing is propagated further.
+    ///
+    /// # Example
+    /// ```
+    /// use net_neurons::nnetwork::CalcNode;
+    ///
+    /// let a = CalcNode::new_scalar(2.);
+    /// let b = CalcNode::without_gradients(|| &a * &a);
+    /// assert_eq!(b.value_indexed(0), 4.);
+    /// assert!(b.borrow().parents().is_empty());
+    /// ```
+    pub fn without_gradients<R>(calculation: impl FnOnce() -> R) -> R {
+        // Restores the previous mode also if the calculation panics
+        struct Restore(bool);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                INFERENCE_MODE.with(|mode| mode.set(self.0));
+            }
+        }
+        let _restore = Restore(INFERENCE_MODE.with(|mode| mode.replace(true)));
+        calculation()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wide_sum_of_shared_nodes() {
+        let a = CalcNode::new_scalar(2.);
+        let b = CalcNode::new_scalar(3.);
+        let mut sum = (0..1000)
+            .map(|i| if i % 2 == 0 { &a * &b } else { &a + &b })
+            .sum::<CalcNode>();
+        sum.back_propagation();
+        assert_eq!(a.gradient_indexed(0), 500. * 3. + 500.);
+        assert_eq!(b.gradient_indexed(0), 500. * 2. + 500.);
+        // The cached order must give the same result
+        let grads = (a.copy_grad(), b.copy_grad());
+        sum.back_propagation();
+        assert_eq!((a.copy_grad(), b.copy_grad()), grads);
+    }
+
+    #[test]
+    fn inference_nodes_get_gradients_when_used_for_training() {
+        let a = CalcNode::new_col_vector(vec![1., 2.]);
+        let scale =
+            CalcNode::without_gradients(|| CalcNode::new_scalar(2.) * CalcNode::new_scalar(1.5));
+        let doubled = CalcNode::without_gradients(|| &a * &CalcNode::new_scalar(2.));
+        assert!(doubled.copy_grad().is_empty());
+        let mut out = (&doubled * &scale).sum();
+        out.back_propagation();
+        assert_eq!(scale.copy_grad(), vec![6.]);
+        assert_eq!(doubled.copy_grad(), vec![3., 3.]);
+        // Nothing reaches through the inference node, the gradient of its input is never set
+        assert!(a.copy_grad().iter().all(|g| g.is_nan()));
+    }
+}
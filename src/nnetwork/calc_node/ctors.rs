@@ -4,12 +4,12 @@ use rand::{thread_rng, Rng};
 use rand_distr::StandardNormal;
 
 use super::{
-    types::{FloatType, NodeShape},
+    types::{Float, NodeShape, ReshapeError},
     CalcNode, CalcNodeCore,
 };
 
 // Ctors
-impl CalcNode {
+impl<T: Float> CalcNode<T> {
     fn size_of_shape(shape: &NodeShape) -> usize {
         shape.0 * shape.1
     }
@@ -40,40 +40,59 @@ impl CalcNode {
     /// ```
     pub fn new(
         shape: NodeShape,
-        vals: Vec<FloatType>,
-        parents: Vec<CalcNode>,
-        back_propagation: Option<Box<dyn Fn(CalcNode)>>,
+        vals: Vec<T>,
+        parents: Vec<CalcNode<T>>,
+        back_propagation: Option<Box<dyn Fn(CalcNode<T>)>>,
     ) -> Self {
         let size = Self::size_of_shape(&shape);
         assert_eq!(size, vals.len());
+        // Nothing is kept for the back propagation in inference mode
+        let (grad, parents, back_propagation) = if Self::gradients_disabled() {
+            (Vec::new(), Vec::new(), None)
+        } else {
+            (vec![T::nan(); size], parents, back_propagation)
+        };
         CalcNode {
             _node: Rc::new(RefCell::new(CalcNodeCore {
                 _shape: shape,
                 _vals: vals,
-                _grad: vec![FloatType::NAN; size],
+                _grad: grad,
                 _parent_nodes: parents,
                 _back_propagation: back_propagation,
+                _forward: None,
+                _sorted_ancestors: None,
             })),
         }
     }
 
     /// Shortcut for constructing scalar nodes.
-    pub fn new_scalar(value: FloatType) -> Self {
+    pub fn new_scalar(value: T) -> Self {
         Self::new_from_shape((1, 1), vec![value])
     }
 
     /// Shortcut for constructing column vector nodes.
-    pub fn new_col_vector(value: Vec<FloatType>) -> Self {
+    pub fn new_col_vector(value: Vec<T>) -> Self {
         let size = value.len();
         Self::new_from_shape((size, 1), value)
     }
 
     /// Shortcut for constructing row vector nodes.
-    pub fn new_row_vector(value: Vec<FloatType>) -> Self {
+    pub fn new_row_vector(value: Vec<T>) -> Self {
         let size = value.len();
         Self::new_from_shape((1, size), value)
     }
 
+    /// Constructs a matrix node from its rows. Will panic if the rows are not all of the same length.
+    pub fn from_rows(rows: Vec<Vec<T>>) -> Self {
+        let n_rows = rows.len();
+        let n_cols = rows.first().map_or(0, |row| row.len());
+        assert!(
+            rows.iter().all(|row| row.len() == n_cols),
+            "All rows must have the same length."
+        );
+        Self::new_from_shape((n_rows, n_cols), rows.into_iter().flatten().collect())
+    }
+
     /// Constructs a node with a specific shape and values. Will panic if the size of the shape and data are not equal.
     ///
     /// # Example
@@ -84,28 +103,80 @@ impl CalcNode {
     /// let vals = vec![1.,2.,3.,4.];
     /// let node = CalcNode::new_from_shape(shape, vals);
     /// ```
-    pub fn new_from_shape(shape: NodeShape, vals: Vec<FloatType>) -> Self {
+    pub fn new_from_shape(shape: NodeShape, vals: Vec<T>) -> Self {
         Self::new(shape, vals, vec![], None)
     }
 
+    /// Coerce the node into a new shape. Will panic if the size changes.
+    pub fn reshape(&mut self, shape: NodeShape) {
+        assert_eq!(
+            self._node.borrow()._vals.len(),
+            Self::size_of_shape(&shape),
+            "Size must not change when reshaping Tensor"
+        );
+        self.borrow_mut()._shape = shape;
+    }
+
+    /// Same as [CalcNode::reshape] but returns an error instead of panicking if the size changes. The node is left unchanged on failure.
+    pub fn try_reshape(&mut self, shape: NodeShape) -> Result<(), ReshapeError> {
+        let old_len = self.len();
+        let new_len = Self::size_of_shape(&shape);
+        if old_len != new_len {
+            return Err(ReshapeError { old_len, new_len });
+        }
+        self.borrow_mut()._shape = shape;
+        Ok(())
+    }
+}
+
+impl CalcNode {
     /// Similar to [CalcNode::new_from_shape] but randomizes all values from a normal distribution.
     pub fn rand_from_shape(shape: NodeShape) -> Self {
+        Self::rand_from_shape_seeded(shape, &mut thread_rng())
+    }
+
+    /// Same as [CalcNode::rand_from_shape] but draws the values from the supplied random number generator, e.g., a seeded one for reproducibility.
+    pub fn rand_from_shape_seeded<R: Rng + ?Sized>(shape: NodeShape, rng: &mut R) -> Self {
         let size = Self::size_of_shape(&shape);
         Self::new_from_shape(
             shape,
-            (0..size)
-                .map(|_| thread_rng().sample(StandardNormal))
-                .collect(),
+            (0..size).map(|_| rng.sample(StandardNormal)).collect(),
         )
     }
+}
 
-    /// Coerce the node into a new shape. Will panic if the size changes.
-    pub fn reshape(&mut self, shape: NodeShape) {
+#[cfg(test)]
+mod tests {
+    use crate::nnetwork::{CalcNode, ReshapeError};
+
+    #[test]
+    fn rows_round_trip() {
+        let rows = vec![vec![1., 2., 3.], vec![4., 5., 6.]];
+        let node = CalcNode::from_rows(rows.clone());
+        assert_eq!(node.shape(), (2, 3));
+        assert_eq!(node.copy_vals(), &[1., 2., 3., 4., 5., 6.]);
+        assert_eq!(node.to_rows(), rows);
+    }
+
+    #[test]
+    fn failed_reshape_reports_sizes() {
+        let mut node = CalcNode::new_col_vector(vec![1., 2., 3., 4., 5., 6.]);
+        assert_eq!(node.try_reshape((2, 3)), Ok(()));
+        assert_eq!(node.shape(), (2, 3));
+        let err = node.try_reshape((2, 2)).unwrap_err();
         assert_eq!(
-            self._node.borrow()._vals.len(),
-            Self::size_of_shape(&shape),
-            "Size must not change when reshaping Tensor"
+            err,
+            ReshapeError {
+                old_len: 6,
+                new_len: 4
+            }
         );
-        self.borrow_mut()._shape = shape;
+        assert_eq!(node.shape(), (2, 3));
+    }
+
+    #[test]
+    #[should_panic(expected = "same length")]
+    fn ragged_rows_panic() {
+        CalcNode::from_rows(vec![vec![1., 2.], vec![3.]]);
     }
 }
@@ -0,0 +1,100 @@
+use super::{CalcNode, FloatType};
+
+/// Compares the gradients found by back propagation with central finite differences. `build` must calculate a scalar from the inputs and is called once per perturbation. Returns the largest absolute discrepancy over all input values.
+pub(crate) fn gradient_check(
+    build: impl Fn(&[CalcNode]) -> CalcNode,
+    inputs: &[CalcNode],
+    epsilon: FloatType,
+) -> FloatType {
+    let mut out = build(inputs);
+    assert_eq!(
+        out.len(),
+        1,
+        "Gradients can only be checked for a scalar output."
+    );
+    out.back_propagation();
+    let analytic: Vec<Vec<FloatType>> = inputs.iter().map(|inp| inp.copy_grad()).collect();
+
+    let mut inputs = inputs.to_vec();
+    let mut max_discrepancy: FloatType = 0.;
+    for i in 0..inputs.len() {
+        for j in 0..inputs[i].len() {
+            let original = inputs[i].copy_vals();
+            let mut shifted = original.clone();
+            shifted[j] = original[j] + epsilon;
+            inputs[i].set_vals(&shifted);
+            let upper = build(&inputs).value_indexed(0);
+            shifted[j] = original[j] - epsilon;
+            inputs[i].set_vals(&shifted);
+            let lower = build(&inputs).value_indexed(0);
+            inputs[i].set_vals(&original);
+
+            let numerical = (upper - lower) / (2. * epsilon);
+            max_discrepancy = max_discrepancy.max((numerical - analytic[i][j]).abs());
+        }
+    }
+    max_discrepancy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: FloatType = 1e-6;
+    const TOLERANCE: FloatType = 1e-6;
+
+    #[test]
+    fn dot_product_gradient() {
+        let inputs = [
+            CalcNode::new_row_vector(vec![1., -2., 0.5]),
+            CalcNode::new_col_vector(vec![3., 0.25, -1.]),
+        ];
+        let discrepancy = gradient_check(|x| &x[0] * &x[1], &inputs, EPSILON);
+        assert!(discrepancy < TOLERANCE, "Discrepancy: {discrepancy}");
+    }
+
+    #[test]
+    fn pow_gradient() {
+        let inputs = [
+            CalcNode::new_col_vector(vec![0.5, 1.5, 2.]),
+            CalcNode::new_scalar(2.5),
+        ];
+        let discrepancy = gradient_check(|x| x[0].pow(&x[1]).sum(), &inputs, EPSILON);
+        assert!(discrepancy < TOLERANCE, "Discrepancy: {discrepancy}");
+    }
+
+    #[test]
+    fn variance_and_std_gradient() {
+        let inputs = [CalcNode::new_col_vector(vec![1., 2., 3., -0.5])];
+        let discrepancy = gradient_check(|x| x[0].variance(), &inputs, EPSILON);
+        assert!(discrepancy < TOLERANCE, "Discrepancy: {discrepancy}");
+        let discrepancy = gradient_check(|x| x[0].std(), &inputs, EPSILON);
+        assert!(discrepancy < TOLERANCE, "Discrepancy: {discrepancy}");
+    }
+
+    #[test]
+    fn log_sum_exp_gradient() {
+        let inputs = [CalcNode::new_col_vector(vec![1., 2., 3., -0.5])];
+        let discrepancy = gradient_check(|x| x[0].log_sum_exp(), &inputs, EPSILON);
+        assert!(discrepancy < TOLERANCE, "Discrepancy: {discrepancy}");
+    }
+
+    #[test]
+    fn element_wise_mul_gradient() {
+        let inputs = [
+            CalcNode::new_col_vector(vec![1., -2., 0.]),
+            CalcNode::new_col_vector(vec![0.5, 4., -3.]),
+        ];
+        // Squaring makes the gradient depend on the values of both factors
+        let discrepancy = gradient_check(
+            |x| {
+                x[0].element_wise_mul(&x[1])
+                    .pow(&CalcNode::new_scalar(2.))
+                    .sum()
+            },
+            &inputs,
+            EPSILON,
+        );
+        assert!(discrepancy < TOLERANCE, "Discrepancy: {discrepancy}");
+    }
+}
@@ -3,25 +3,66 @@ mod ctors;
 mod operators;
 mod types;
 mod back_propagation;
+#[cfg(test)]
+pub(crate) mod gradient_check;
 
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
 
 pub use types::*;
 
+thread_local! {
+    // Set while calculating without gradients, see [CalcNode::without_gradients]
+    static INFERENCE_MODE: Cell<bool> = const { Cell::new(false) };
+}
+
 /// Wrapper class for [CalcNodeCore]. This is the struct intended to be used.
-#[derive(Clone)]
-pub struct CalcNode {
-    _node: Rc<RefCell<CalcNodeCore>>,
+/// The values are [FloatType] unless another [Float] is chosen.
+pub struct CalcNode<T = FloatType> {
+    _node: Rc<RefCell<CalcNodeCore<T>>>,
+}
+
+/// [CalcNode] with double precision, the same as plain [CalcNode]
+pub type CalcNode64 = CalcNode<f64>;
+/// [CalcNode] with single precision
+pub type CalcNode32 = CalcNode<f32>;
+
+// Derive would require T: Clone even though only the pointer is cloned
+impl<T> Clone for CalcNode<T> {
+    fn clone(&self) -> Self {
+        CalcNode {
+            _node: self._node.clone(),
+        }
+    }
 }
 
 /// The struct that actually holds the data.
 /// Only a few member functions are implemented for this struct, use the wrapper struct [CalcNode] instead.
-#[derive(Default)]
-pub struct CalcNodeCore {
-    _parent_nodes: Vec<CalcNode>,
+pub struct CalcNodeCore<T = FloatType> {
+    _parent_nodes: Vec<CalcNode<T>>,
     _shape: NodeShape,
-    _vals: Vec<FloatType>,
-    _grad: Vec<FloatType>,
+    _vals: Vec<T>,
+    _grad: Vec<T>,
     // Function that calculates and updates the gradients for its parents.
-    _back_propagation: Option<Box<dyn Fn(CalcNode)>>,
+    _back_propagation: Option<Box<dyn Fn(CalcNode<T>)>>,
+    // Function that recalculates the values from the parents. Needed to replay a recorded calculation with new inputs.
+    _forward: Option<Box<ForwardFunc<T>>>,
+    // All nodes leading up to this one in topological order, cached by the first back propagation.
+    _sorted_ancestors: Option<Vec<CalcNode<T>>>,
+}
+
+impl<T> Default for CalcNodeCore<T> {
+    fn default() -> Self {
+        CalcNodeCore {
+            _parent_nodes: Vec::new(),
+            _shape: NodeShape::default(),
+            _vals: Vec::new(),
+            _grad: Vec::new(),
+            _back_propagation: None,
+            _forward: None,
+            _sorted_ancestors: None,
+        }
+    }
 }
@@ -5,7 +5,7 @@ use std::{
 
 use rand::Rng;
 
-use super::{CalcNode, NodeType};
+use super::{CalcNode, Float, FloatType, ForwardFunc, NodeType};
 
 impl Sum for CalcNode {
     fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
@@ -16,9 +16,12 @@ impl Sum for CalcNode {
 impl CalcNode {
     /// Returns the sum of all values in the [CalcNode], i.e., the result will be a scalar.
     pub fn sum(&self) -> CalcNode {
-        let result = Self::new_scalar(self.borrow()._vals.iter().sum());
-        result.borrow_mut()._parent_nodes = vec![self.clone()];
-        result.borrow_mut()._back_propagation = Some(Box::new(|child| {
+        let mut result = Self::new_scalar(self.borrow()._vals.iter().sum());
+        result.set_parents(vec![self.clone()]);
+        result.set_forward(Box::new(|node| {
+            vec![node.copy_parents()[0].borrow()._vals.iter().sum()]
+        }));
+        result.set_back_propagation(Box::new(|child| {
             let child_grad = child.gradient_indexed(0);
             child.copy_parents()[0]
                 .borrow_mut()
@@ -29,6 +32,82 @@ impl CalcNode {
         result
     }
 
+    /// Returns the mean of all values as a scalar. Every value receives `1/len` of the gradient.
+    pub fn mean(&self) -> CalcNode {
+        let len = self.len() as FloatType;
+        let mut result = Self::new_scalar(self.borrow()._vals.iter().sum::<FloatType>() / len);
+        result.set_parents(vec![self.clone()]);
+        result.set_forward(Box::new(move |node| {
+            vec![node.copy_parents()[0].borrow()._vals.iter().sum::<FloatType>() / len]
+        }));
+        result.set_back_propagation(Box::new(move |child| {
+            let child_grad = child.gradient_indexed(0) / len;
+            child.copy_parents()[0]
+                .borrow_mut()
+                ._grad
+                .iter_mut()
+                .for_each(|g| *g += child_grad);
+        }));
+        result
+    }
+
+    /// Returns the population variance of all values as a scalar, i.e., the mean of the squared deviations from the mean.
+    pub fn variance(&self) -> CalcNode {
+        let centered = self - self.mean();
+        centered.element_wise_mul(&centered).mean()
+    }
+
+    /// Returns the population standard deviation of all values as a scalar, i.e., the square root of [CalcNode::variance]. The gradient is not finite if all values are equal.
+    pub fn std(&self) -> CalcNode {
+        self.variance().pow(&Self::new_scalar(0.5))
+    }
+
+    /// Returns the largest value as a scalar. Only the element holding the maximum receives the gradient (the first one in case of ties).
+    pub fn max(&self) -> CalcNode {
+        let mut result = Self::new_scalar(self.value_indexed(self.argmax()));
+        result.set_parents(vec![self.clone()]);
+        result.set_forward(Box::new(|node| {
+            let parent = &node.copy_parents()[0];
+            vec![parent.value_indexed(parent.argmax())]
+        }));
+        result.set_back_propagation(Box::new(|child| {
+            let child_grad = child.gradient_indexed(0);
+            // The maximum is looked up again since the values may have been recalculated
+            let parent = &child.copy_parents()[0];
+            let index = parent.argmax();
+            parent.borrow_mut()._grad[index] += child_grad;
+        }));
+        result
+    }
+
+    /// Returns `log(sum(exp(x)))` of all values as a scalar. The largest value is subtracted before exponentiating, so the result stays finite for large inputs. Every value receives the gradient weighted by its softmax.
+    pub fn log_sum_exp(&self) -> CalcNode {
+        let mut result = Self::new_scalar(Self::log_sum_exp_val(&self.borrow()._vals));
+        result.set_parents(vec![self.clone()]);
+        result.set_forward(Box::new(|node| {
+            vec![Self::log_sum_exp_val(&node.copy_parents()[0].borrow()._vals)]
+        }));
+        result.set_back_propagation(Box::new(|child| {
+            let child_val = child.value_indexed(0);
+            let child_grad = child.gradient_indexed(0);
+            let parent = &child.borrow()._parent_nodes[0];
+            let mut parent = parent.borrow_mut();
+            let parent = &mut *parent;
+            parent
+                ._grad
+                .iter_mut()
+                .zip(parent._vals.iter())
+                .for_each(|(g, v)| *g += child_grad * (v - child_val).exp());
+        }));
+        result
+    }
+
+    fn log_sum_exp_val(vals: &[FloatType]) -> FloatType {
+        let max = vals.iter().fold(FloatType::NEG_INFINITY, |a, &b| a.max(b));
+        let sum: FloatType = vals.iter().map(|v| (v - max).exp()).sum();
+        max + sum.ln()
+    }
+
     /// Normalises the [CalcNode] so that its sum becomes unity.
     pub fn normalized(&self) -> CalcNode {
         self / self.sum()
@@ -36,8 +115,13 @@ impl CalcNode {
     
     /// Collapses the [CalcNode] to a one-hot vector. Only makes sense if you can interpret the values as a probabilities that sum to unity.
     pub fn collapse(&self) -> CalcNode {
+        self.collapse_with(&mut rand::thread_rng())
+    }
+
+    /// Same as [CalcNode::collapse] but uses the supplied random number generator, e.g., a seeded one for reproducibility.
+    pub fn collapse_with<R: Rng + ?Sized>(&self, rng: &mut R) -> CalcNode {
         let mut vec = vec![0.; self.len()];
-        let mut rnd = rand::thread_rng().gen_range(0. ..self.borrow()._vals.iter().sum());
+        let mut rnd = rng.gen_range(0. ..self.borrow()._vals.iter().sum());
         for (i, &v) in self.borrow()._vals.iter().enumerate() {
             rnd -= v;
             if rnd <= 0. || i + 1 == self.len() {
@@ -51,66 +135,250 @@ impl CalcNode {
 }
 
 /// Addition is implemented for all combinations of [CalcNode] and &[CalcNode].
-impl Add<&CalcNode> for CalcNode {
-    type Output = CalcNode;
+impl<T: Float> Add<&CalcNode<T>> for CalcNode<T> {
+    type Output = CalcNode<T>;
 
-    fn add(self, rhs: &CalcNode) -> Self::Output {
+    fn add(self, rhs: &CalcNode<T>) -> Self::Output {
         &self + rhs
     }
 }
-impl Add for CalcNode {
-    type Output = CalcNode;
+impl<T: Float> Add for CalcNode<T> {
+    type Output = CalcNode<T>;
 
-    fn add(self, rhs: CalcNode) -> Self::Output {
+    fn add(self, rhs: CalcNode<T>) -> Self::Output {
         &self + &rhs
     }
 }
-impl Add<CalcNode> for &CalcNode {
-    type Output = CalcNode;
+impl<T: Float> Add<CalcNode<T>> for &CalcNode<T> {
+    type Output = CalcNode<T>;
 
-    fn add(self, rhs: CalcNode) -> Self::Output {
+    fn add(self, rhs: CalcNode<T>) -> Self::Output {
         self + &rhs
     }
 }
-impl Add for &CalcNode {
-    type Output = CalcNode;
+impl<T: Float> Add for &CalcNode<T> {
+    type Output = CalcNode<T>;
 
     fn add(self, rhs: Self) -> Self::Output {
         // If self is a scalar make it the RHS
         let a = if self.len() == 1 { rhs } else { self };
         let b = if self.len() == 1 { self } else { rhs };
-        // Adding scalar
-        let result: Vec<_> = if b.len() == 1 {
-            let b = b.value_indexed(0);
-            a.borrow()._vals.iter().map(|a| a + b).collect()
+        // Adding scalar or compatible shapes
+        let result = if b.len() == 1 || a.len() == b.len() {
+            CalcNode::add_vals(a, b)
         }
-        // Compatible shapes
-        else if a.len() == b.len() {
+        // Column vector added to every column of a matrix, regardless of operand order
+        else if a.shape().0 == b.shape().0 && (a.shape().1 == 1 || b.shape().1 == 1) {
+            return if b.shape().1 == 1 {
+                CalcNode::broadcast_col_add(a, b)
+            } else {
+                CalcNode::broadcast_col_add(b, a)
+            };
+        } else {
+            panic!("Invalid operands for addition {a} and {b}.");
+        };
+        let mut result = CalcNode::new_from_shape(a.shape(), result);
+        result.set_parents(vec![a.clone(), b.clone()]);
+        result.set_forward(CalcNode::replay_binary(CalcNode::add_vals));
+        result.set_back_propagation(Box::new(|child| {
+            let parents = &child.borrow()._parent_nodes;
+            for p in parents {
+                if p.len() == 1 && child.len() > 1 {
+                    // A broadcasted scalar receives the sum of all gradients
+                    p.borrow_mut()._grad[0] += child.borrow()._grad.iter().fold(T::zero(), |acc, &g| acc + g);
+                } else {
+                    p.borrow_mut()
+                        ._grad
+                        .iter_mut()
+                        .zip(child.borrow()._grad.iter())
+                        .for_each(|(p, &c)| *p += c);
+                }
+            }
+        }));
+        result
+    }
+}
+
+impl<T: Float> CalcNode<T> {
+    // Recalculates the values of a node from its two parents
+    fn replay_binary(
+        vals: fn(&CalcNode<T>, &CalcNode<T>) -> Vec<T>,
+    ) -> Box<ForwardFunc<T>> {
+        Box::new(move |node| {
+            let parents = node.copy_parents();
+            vals(&parents[0], &parents[1])
+        })
+    }
+
+    // Element-wise sum where the second operand may also be a scalar
+    fn add_vals(a: &CalcNode<T>, b: &CalcNode<T>) -> Vec<T> {
+        if b.len() == 1 {
+            let b = b.value_indexed(0);
+            a.borrow()._vals.iter().map(|&a| a + b).collect()
+        } else {
             a.borrow()
                 ._vals
                 .iter()
                 .zip(b.borrow()._vals.iter())
-                .map(|(a, b)| a + b)
+                .map(|(&a, &b)| a + b)
                 .collect()
-        } else {
-            panic!("Invalid operands for addition {a} and {b}.");
-        };
-        let result = CalcNode::new_from_shape(a.shape(), result);
-        result.borrow_mut()._parent_nodes = vec![a.clone(), b.clone()];
-        result.borrow_mut()._back_propagation = Some(Box::new(|child| {
+        }
+    }
+
+    fn broadcast_col_add_vals(matrix: &CalcNode<T>, vector: &CalcNode<T>) -> Vec<T> {
+        let (_, n_cols) = matrix.shape();
+        matrix
+            .borrow()
+            ._vals
+            .iter()
+            .enumerate()
+            .map(|(i, &m)| m + vector.borrow()._vals[i / n_cols])
+            .collect()
+    }
+
+    // Adds the column vector to every column of the matrix
+    fn broadcast_col_add(matrix: &CalcNode<T>, vector: &CalcNode<T>) -> CalcNode<T> {
+        let result = CalcNode::broadcast_col_add_vals(matrix, vector);
+        let mut result = CalcNode::new_from_shape(matrix.shape(), result);
+        result.set_parents(vec![matrix.clone(), vector.clone()]);
+        result.set_forward(CalcNode::replay_binary(CalcNode::broadcast_col_add_vals));
+        result.set_back_propagation(Box::new(|child| {
             let parents = &child.borrow()._parent_nodes;
-            for p in parents {
-                p.borrow_mut()
-                    ._grad
-                    .iter_mut()
-                    .zip(child.borrow()._grad.iter())
-                    .for_each(|(p, c)| *p += c);
+            let (_, n_cols) = child.shape();
+            let mut vector_grad = vec![T::zero(); parents[1].len()];
+            for (i, &g) in child.borrow()._grad.iter().enumerate() {
+                parents[0].borrow_mut()._grad[i] += g;
+                // The vector receives the sum over all columns
+                vector_grad[i / n_cols] += g;
             }
+            parents[1].clone().add_grad(&vector_grad);
         }));
         result
     }
 }
 
+impl CalcNode {
+    /// Returns the transpose, i.e., rows become columns and vice versa.
+    pub fn transpose(&self) -> CalcNode {
+        let (n_rows, n_cols) = self.shape();
+        self.gather(
+            (n_cols, n_rows),
+            (0..n_rows * n_cols)
+                .map(|i| (i % n_rows) * n_cols + i / n_rows)
+                .collect(),
+        )
+    }
+
+    // Creates a node of the given shape from the values at the indices. The gradient is scattered back to the same indices.
+    fn gather(&self, shape: (usize, usize), indices: Vec<usize>) -> CalcNode {
+        let result = indices.iter().map(|&i| self.borrow()._vals[i]).collect();
+        let mut result = CalcNode::new_from_shape(shape, result);
+        result.set_parents(vec![self.clone()]);
+        let forward_indices = indices.clone();
+        result.set_forward(Box::new(move |node| {
+            let parent = &node.copy_parents()[0];
+            forward_indices.iter().map(|&i| parent.borrow()._vals[i]).collect()
+        }));
+        result.set_back_propagation(Box::new(move |child| {
+            let parent = &child.borrow()._parent_nodes[0];
+            for (&i, &g) in indices.iter().zip(child.borrow()._grad.iter()) {
+                parent.borrow_mut()._grad[i] += g;
+            }
+        }));
+        result
+    }
+
+    /// Returns the indexed column as a column vector.
+    pub fn column(&self, j: usize) -> CalcNode {
+        let (n_rows, n_cols) = self.shape();
+        assert!(
+            j < n_cols,
+            "Column index {j} out of bounds for node with {n_cols} columns."
+        );
+        self.gather((n_rows, 1), (0..n_rows).map(|i| i * n_cols + j).collect())
+    }
+
+    /// Returns the indexed row as a row vector.
+    pub fn row(&self, i: usize) -> CalcNode {
+        let (n_rows, n_cols) = self.shape();
+        assert!(
+            i < n_rows,
+            "Row index {i} out of bounds for node with {n_rows} rows."
+        );
+        self.gather((1, n_cols), (0..n_cols).map(|j| i * n_cols + j).collect())
+    }
+}
+
+impl CalcNode {
+    // Joins the nodes into one of the given shape. Every value is taken from (node index, value index) in the sources.
+    fn join(nodes: &[CalcNode], shape: (usize, usize), sources: Vec<(usize, usize)>) -> CalcNode {
+        let result = sources
+            .iter()
+            .map(|&(n, i)| nodes[n].borrow()._vals[i])
+            .collect();
+        let mut result = CalcNode::new_from_shape(shape, result);
+        result.set_parents(nodes.to_vec());
+        let forward_sources = sources.clone();
+        result.set_forward(Box::new(move |node| {
+            let parents = node.copy_parents();
+            forward_sources
+                .iter()
+                .map(|&(n, i)| parents[n].borrow()._vals[i])
+                .collect()
+        }));
+        result.set_back_propagation(Box::new(move |child| {
+            let parents = &child.borrow()._parent_nodes;
+            for (&(n, i), &g) in sources.iter().zip(child.borrow()._grad.iter()) {
+                parents[n].borrow_mut()._grad[i] += g;
+            }
+        }));
+        result
+    }
+
+    /// Stacks the nodes vertically. All nodes must have the same number of columns.
+    pub fn concat_rows(nodes: &[CalcNode]) -> CalcNode {
+        assert!(!nodes.is_empty(), "Cannot concatenate an empty list of nodes.");
+        let n_cols = nodes[0].shape().1;
+        if let Some(node) = nodes.iter().find(|n| n.shape().1 != n_cols) {
+            panic!(
+                "Cannot concatenate rows of nodes with {} and {} columns.",
+                n_cols,
+                node.shape().1
+            );
+        }
+        let n_rows = nodes.iter().map(|n| n.shape().0).sum();
+        let sources = nodes
+            .iter()
+            .enumerate()
+            .flat_map(|(n, node)| (0..node.len()).map(move |i| (n, i)))
+            .collect();
+        Self::join(nodes, (n_rows, n_cols), sources)
+    }
+
+    /// Stacks the nodes horizontally. All nodes must have the same number of rows.
+    pub fn concat_cols(nodes: &[CalcNode]) -> CalcNode {
+        assert!(!nodes.is_empty(), "Cannot concatenate an empty list of nodes.");
+        let n_rows = nodes[0].shape().0;
+        if let Some(node) = nodes.iter().find(|n| n.shape().0 != n_rows) {
+            panic!(
+                "Cannot concatenate columns of nodes with {} and {} rows.",
+                n_rows,
+                node.shape().0
+            );
+        }
+        let n_cols = nodes.iter().map(|n| n.shape().1).sum();
+        let sources = (0..n_rows)
+            .flat_map(|row| {
+                nodes.iter().enumerate().flat_map(move |(n, node)| {
+                    let width = node.shape().1;
+                    (0..width).map(move |col| (n, row * width + col))
+                })
+            })
+            .collect();
+        Self::join(nodes, (n_rows, n_cols), sources)
+    }
+}
+
 /// Multiplication is implemented for all combinations of [CalcNode] and &[CalcNode].
 /// 
 /// Two kinds of multiplications are recognised:
@@ -120,29 +388,29 @@ impl Add for &CalcNode {
 /// Element-wise multiplication is provided by [CalcNode::element_wise_mul].
 /// 
 /// Will panic if the dimensions of LHS and RHS does not match.
-impl Mul<&CalcNode> for CalcNode {
-    type Output = CalcNode;
+impl<T: Float> Mul<&CalcNode<T>> for CalcNode<T> {
+    type Output = CalcNode<T>;
 
-    fn mul(self, rhs: &CalcNode) -> Self::Output {
+    fn mul(self, rhs: &CalcNode<T>) -> Self::Output {
         &self * rhs
     }
 }
-impl Mul<CalcNode> for CalcNode {
-    type Output = CalcNode;
+impl<T: Float> Mul<CalcNode<T>> for CalcNode<T> {
+    type Output = CalcNode<T>;
 
-    fn mul(self, rhs: CalcNode) -> Self::Output {
+    fn mul(self, rhs: CalcNode<T>) -> Self::Output {
         &self * &rhs
     }
 }
-impl Mul<CalcNode> for &CalcNode {
-    type Output = CalcNode;
+impl<T: Float> Mul<CalcNode<T>> for &CalcNode<T> {
+    type Output = CalcNode<T>;
 
-    fn mul(self, rhs: CalcNode) -> Self::Output {
+    fn mul(self, rhs: CalcNode<T>) -> Self::Output {
         self * &rhs
     }
 }
-impl Mul for &CalcNode {
-    type Output = CalcNode;
+impl<T: Float> Mul for &CalcNode<T> {
+    type Output = CalcNode<T>;
 
     fn mul(self, other: Self) -> Self::Output {
         // If self is a scalar make it the RHS
@@ -150,11 +418,11 @@ impl Mul for &CalcNode {
         let b = if self.len() == 1 { self } else { other };
         // Multiplying with scalar
         if b.len() == 1 {
-            let scalar = b.value_indexed(0);
-            let result = a.borrow()._vals.iter().map(|a| a * scalar).collect();
-            let result = CalcNode::new_from_shape(a.shape(), result);
-            result.borrow_mut()._parent_nodes = vec![a.clone(), b.clone()];
-            result.borrow_mut()._back_propagation = Some(Box::new(|child| {
+            let result = CalcNode::scalar_mul_vals(a, b);
+            let mut result = CalcNode::new_from_shape(a.shape(), result);
+            result.set_parents(vec![a.clone(), b.clone()]);
+            result.set_forward(CalcNode::replay_binary(CalcNode::scalar_mul_vals));
+            result.set_back_propagation(Box::new(|child| {
                 let parents = &child.borrow()._parent_nodes;
                 let scalar_val = parents[1].borrow()._vals[0];
                 for (i, &child_grad) in child.borrow()._grad.iter().enumerate() {
@@ -168,25 +436,13 @@ impl Mul for &CalcNode {
         // Matrix multiplication
         else if self.shape().1 == b.shape().0 {
             // (m x n) * (n x p) = (m x p)
-            let (m, n) = self.shape();
+            let (m, _) = self.shape();
             let (_, p) = b.shape();
-
-            let lhs = &self.borrow()._vals;
-            let rhs = &b.borrow()._vals;
-
-            let result = (0..m * p)
-                .map(|i| {
-                    let row = i / p;
-                    let col = i % p;
-                    let lhs_row = lhs.iter().skip(row * n).take(n);
-                    let rhs_col = rhs.iter().skip(col).step_by(p);
-
-                    lhs_row.zip(rhs_col).map(|(&r, &c)| r * c).sum()
-                })
-                .collect();
-            let result = CalcNode::new_from_shape((m, p), result);
-            result.borrow_mut()._parent_nodes = vec![self.clone(), b.clone()];
-            result.borrow_mut()._back_propagation = Some(Box::new(|child| {
+            let result = CalcNode::matmul_vals(self, b);
+            let mut result = CalcNode::new_from_shape((m, p), result);
+            result.set_parents(vec![self.clone(), b.clone()]);
+            result.set_forward(CalcNode::replay_binary(CalcNode::matmul_vals));
+            result.set_back_propagation(Box::new(|child| {
                 let parents = &child.borrow()._parent_nodes;
                 let (_m, n) = parents[0].shape();
                 let (_, p) = parents[1].shape();
@@ -196,7 +452,7 @@ impl Mul for &CalcNode {
                     let col = i % p;
                     {
                         // Need to avoid borrowing mutables in the case when lhs = rhs
-                        let mut rhs = vec![0.; parents[1].len()];
+                        let mut rhs = vec![T::zero(); parents[1].len()];
                         {
                             // RHS derivative
                             let lhs = &parents[0].borrow()._vals;
@@ -211,13 +467,13 @@ impl Mul for &CalcNode {
                             .borrow_mut()
                             ._grad
                             .iter_mut()
-                            .zip(rhs.into_iter())
+                            .zip(rhs)
                             .for_each(|(target, new_value)| *target += new_value);
                     }
 
                     {
                         // Need to avoid borrowing mutables in the case when lhs = rhs
-                        let mut lhs = vec![0.; parents[0].len()];
+                        let mut lhs = vec![T::zero(); parents[0].len()];
                         {
                         // LHS derivative
                         let rhs = &parents[1].borrow()._vals;
@@ -231,7 +487,7 @@ impl Mul for &CalcNode {
                             .borrow_mut()
                             ._grad
                             .iter_mut()
-                            .zip(lhs.into_iter())
+                            .zip(lhs)
                             .for_each(|(target, new_value)| *target += new_value);
                     }
                 }
@@ -246,6 +502,32 @@ impl Mul for &CalcNode {
     }
 }
 
+impl<T: Float> CalcNode<T> {
+    fn scalar_mul_vals(a: &CalcNode<T>, scalar: &CalcNode<T>) -> Vec<T> {
+        let scalar = scalar.value_indexed(0);
+        a.borrow()._vals.iter().map(|&a| a * scalar).collect()
+    }
+
+    fn matmul_vals(lhs: &CalcNode<T>, rhs: &CalcNode<T>) -> Vec<T> {
+        let (m, n) = lhs.shape();
+        let (_, p) = rhs.shape();
+        let lhs = &lhs.borrow()._vals;
+        let rhs = &rhs.borrow()._vals;
+        (0..m * p)
+            .map(|i| {
+                let row = i / p;
+                let col = i % p;
+                let lhs_row = lhs.iter().skip(row * n).take(n);
+                let rhs_col = rhs.iter().skip(col).step_by(p);
+
+                lhs_row
+                    .zip(rhs_col)
+                    .fold(T::zero(), |acc, (&r, &c)| acc + r * c)
+            })
+            .collect()
+    }
+}
+
 /// Subtraction is implemented for all combinations of [CalcNode] and &[CalcNode].
 impl Sub<&CalcNode> for CalcNode {
     type Output = CalcNode;
@@ -338,12 +620,15 @@ impl CalcNode {
 impl CalcNode {
     /// Exponentiates all values.    
     pub fn exp(&self) -> CalcNode {
-        let result = Self::new_from_shape(
+        let mut result = Self::new_from_shape(
             self.borrow()._shape,
             self.borrow()._vals.iter().map(|v| v.exp()).collect(),
         );
-        result.borrow_mut()._parent_nodes = vec![self.clone()];
-        result.borrow_mut()._back_propagation = Some(Box::new(|child| {
+        result.set_parents(vec![self.clone()]);
+        result.set_forward(Box::new(|node| {
+            node.copy_parents()[0].borrow()._vals.iter().map(|v| v.exp()).collect()
+        }));
+        result.set_back_propagation(Box::new(|child| {
             child.copy_parents()[0]
                 .borrow_mut()
                 ._grad
@@ -355,16 +640,170 @@ impl CalcNode {
     }
 }
 
+impl CalcNode {
+    /// Takes the absolute value of all values. The sub-gradient at zero is taken to be zero.
+    pub fn abs(&self) -> CalcNode {
+        let mut result = Self::new_from_shape(
+            self.borrow()._shape,
+            self.borrow()._vals.iter().map(|v| v.abs()).collect(),
+        );
+        result.set_parents(vec![self.clone()]);
+        result.set_forward(Box::new(|node| {
+            node.copy_parents()[0].borrow()._vals.iter().map(|v| v.abs()).collect()
+        }));
+        result.set_back_propagation(Box::new(|child| {
+            let parent = &child.borrow()._parent_nodes[0];
+            for i in 0..parent.len() {
+                let val = parent.borrow()._vals[i];
+                // f64::signum gives 1 for +0, but the sub-gradient is chosen as 0 there
+                let sign = if val == 0. { 0. } else { val.signum() };
+                let gradient = child.borrow()._grad[i] * sign;
+                parent.borrow_mut()._grad[i] += gradient;
+            }
+        }));
+        result
+    }
+}
+
+impl CalcNode {
+    /// Squares all values. Unlike [CalcNode::pow] no logarithm is involved, so the gradient is finite for all values.
+    pub fn square(&self) -> CalcNode {
+        let mut result = Self::new_from_shape(
+            self.borrow()._shape,
+            self.borrow()._vals.iter().map(|v| v * v).collect(),
+        );
+        result.set_parents(vec![self.clone()]);
+        result.set_forward(Box::new(|node| {
+            node.copy_parents()[0].borrow()._vals.iter().map(|v| v * v).collect()
+        }));
+        result.set_back_propagation(Box::new(|child| {
+            let parent = &child.borrow()._parent_nodes[0];
+            for i in 0..parent.len() {
+                let gradient = child.borrow()._grad[i] * 2. * parent.borrow()._vals[i];
+                parent.borrow_mut()._grad[i] += gradient;
+            }
+        }));
+        result
+    }
+}
+
+impl CalcNode {
+    /// Clips all values into the range [min, max]. The gradient only passes through values strictly inside the range.
+    pub fn clamp(&self, min: FloatType, max: FloatType) -> CalcNode {
+        assert!(min <= max, "The lower bound cannot exceed the upper bound.");
+        let mut result = Self::new_from_shape(
+            self.borrow()._shape,
+            self.borrow()._vals.iter().map(|v| v.clamp(min, max)).collect(),
+        );
+        result.set_parents(vec![self.clone()]);
+        result.set_forward(Box::new(move |node| {
+            node.copy_parents()[0].borrow()._vals.iter().map(|v| v.clamp(min, max)).collect()
+        }));
+        result.set_back_propagation(Box::new(move |child| {
+            let parent = &child.borrow()._parent_nodes[0];
+            for i in 0..parent.len() {
+                let val = parent.borrow()._vals[i];
+                if min < val && val < max {
+                    let gradient = child.borrow()._grad[i];
+                    parent.borrow_mut()._grad[i] += gradient;
+                }
+            }
+        }));
+        result
+    }
+}
+
+impl CalcNode {
+    /// Takes the larger of the two values element-wise. The gradient of every element goes to the input holding the larger value, or is split evenly if the values are equal.
+    pub fn element_wise_max(&self, other: &Self) -> CalcNode {
+        self.element_wise_select(other, FloatType::max)
+    }
+
+    /// Takes the smaller of the two values element-wise. The gradient of every element goes to the input holding the smaller value, or is split evenly if the values are equal.
+    pub fn element_wise_min(&self, other: &Self) -> CalcNode {
+        self.element_wise_select(other, FloatType::min)
+    }
+
+    // Picks one of the two values element-wise and routes the gradient to the input it was picked from
+    fn element_wise_select(
+        &self,
+        other: &Self,
+        select: fn(FloatType, FloatType) -> FloatType,
+    ) -> CalcNode {
+        assert_eq!(
+            self.shape(),
+            other.shape(),
+            "Element-wise operations require nodes of the same shape."
+        );
+        let select_all = move |a: &[FloatType], b: &[FloatType]| -> Vec<FloatType> {
+            a.iter().zip(b).map(|(&a, &b)| select(a, b)).collect()
+        };
+        let vals = select_all(&self.borrow()._vals, &other.borrow()._vals);
+        let mut result = Self::new_from_shape(self.shape(), vals);
+        result.set_parents(vec![self.clone(), other.clone()]);
+        result.set_forward(Box::new(move |node| {
+            let parents = node.copy_parents();
+            let vals = select_all(&parents[0].borrow()._vals, &parents[1].borrow()._vals);
+            vals
+        }));
+        result.set_back_propagation(Box::new(|child| {
+            let parents = child.copy_parents();
+            for i in 0..child.len() {
+                let gradient = child.borrow()._grad[i];
+                let val0 = parents[0].borrow()._vals[i];
+                let val1 = parents[1].borrow()._vals[i];
+                let share0 = if val0 == val1 {
+                    0.5
+                } else if child.borrow()._vals[i] == val0 {
+                    1.
+                } else {
+                    0.
+                };
+                parents[0].borrow_mut()._grad[i] += gradient * share0;
+                parents[1].borrow_mut()._grad[i] += gradient * (1. - share0);
+            }
+        }));
+        result
+    }
+}
+
+impl<T: Float> CalcNode<T> {
+    /// Applies the hyperbolic tangent to all values.
+    pub fn tanh(&self) -> CalcNode<T> {
+        let mut result = Self::new_from_shape(
+            self.borrow()._shape,
+            self.borrow()._vals.iter().map(|v| v.tanh()).collect(),
+        );
+        result.set_parents(vec![self.clone()]);
+        result.set_forward(Box::new(|node| {
+            node.copy_parents()[0].borrow()._vals.iter().map(|v| v.tanh()).collect()
+        }));
+        result.set_back_propagation(Box::new(|child| {
+            // The derivative is expressed using the already calculated result
+            child.copy_parents()[0]
+                .borrow_mut()
+                ._grad
+                .iter_mut()
+                .zip(child.borrow()._vals.iter().zip(child.borrow()._grad.iter()))
+                .for_each(|(pg, (&cv, &cg))| *pg += cg * (T::one() - cv * cv));
+        }));
+        result
+    }
+}
+
 // Log
 impl CalcNode {
     /// Applies the natural logarithm to all values.    
     pub fn log(&self) -> CalcNode {
-        let result = Self::new_from_shape(
+        let mut result = Self::new_from_shape(
             self.borrow()._shape,
             self.borrow()._vals.iter().map(|v| v.ln()).collect(),
         );
-        result.borrow_mut()._parent_nodes = vec![self.clone()];
-        result.borrow_mut()._back_propagation = Some(Box::new(|child| {
+        result.set_parents(vec![self.clone()]);
+        result.set_forward(Box::new(|node| {
+            node.copy_parents()[0].borrow()._vals.iter().map(|v| v.ln()).collect()
+        }));
+        result.set_back_propagation(Box::new(|child| {
             let parent = &child.borrow()._parent_nodes[0];
             for i in 0..parent.len() {
                 let gradient = child.borrow()._grad[i] / parent.borrow()._vals[i];
@@ -376,16 +815,24 @@ impl CalcNode {
 }
 
 impl CalcNode {
-    /// Applies the power function to all values.    
+    /// Applies the power function to all values.
+    ///
+    /// The gradient of the power is only defined for positive values, e.g., a constant power can be used with bases of any sign. Zeros and negative values pass nothing on to the power.
     pub fn pow(&self, power: &CalcNode) -> CalcNode {
         assert!(power.len() == 1);
         let p = power.value_indexed(0);
-        let result = Self::new_from_shape(
+        let mut result = Self::new_from_shape(
             self.borrow()._shape,
             self.borrow()._vals.iter().map(|v| v.powf(p)).collect(),
         );
-        result.borrow_mut()._parent_nodes = vec![self.clone(), power.clone()];
-        result.borrow_mut()._back_propagation = Some(Box::new(|child| {
+        result.set_parents(vec![self.clone(), power.clone()]);
+        result.set_forward(Box::new(|node| {
+            let parents = node.copy_parents();
+            let p = parents[1].value_indexed(0);
+            let vals = parents[0].borrow()._vals.iter().map(|v| v.powf(p)).collect();
+            vals
+        }));
+        result.set_back_propagation(Box::new(|child| {
             let parents = &child.borrow()._parent_nodes;
             let base = &parents[0];
             let power = &parents[1];
@@ -396,8 +843,11 @@ impl CalcNode {
                 let child_grad = child.gradient_indexed(i);
                 let gradient = child_grad * power_val * base_val.powf(power_val - 1.);
                 base.borrow_mut()._grad[i] += gradient;
-                let gradient = child_grad * base_val.ln() * child_val;
-                power.borrow_mut()._grad[0] += gradient;
+                // The logarithm would give NaN, which would spread to everything sharing the power
+                if base_val > 0. {
+                    let gradient = child_grad * base_val.ln() * child_val;
+                    power.borrow_mut()._grad[0] += gradient;
+                }
             }
         }));
         result
@@ -409,16 +859,11 @@ impl CalcNode {
     /// 
     /// Multiplication by scalar and the dot product are provided by [CalcNode::mul].
     pub fn element_wise_mul(&self, other: &Self) -> CalcNode {
-        let result = self
-            .borrow()
-            ._vals
-            .iter()
-            .zip(other.borrow()._vals.iter())
-            .map(|(a, b)| a * b)
-            .collect();
-        let result = CalcNode::new_from_shape(self.shape(), result);
-        result.borrow_mut()._parent_nodes = vec![self.clone(), other.clone()];
-        result.borrow_mut()._back_propagation = Some(Box::new(|child| {
+        let result = CalcNode::element_wise_mul_vals(self, other);
+        let mut result = CalcNode::new_from_shape(self.shape(), result);
+        result.set_parents(vec![self.clone(), other.clone()]);
+        result.set_forward(CalcNode::replay_binary(CalcNode::element_wise_mul_vals));
+        result.set_back_propagation(Box::new(|child| {
             let parents = &child.borrow()._parent_nodes;
             for (i, &child_grad) in child.borrow()._grad.iter().enumerate() {
                 let val0 = parents[0].borrow()._vals[i];
@@ -430,15 +875,44 @@ impl CalcNode {
         result
     }
 
-    fn element_wise_div(&self, other: &Self) -> CalcNode {
-        self.element_wise_mul(&other.inv())
+    /// Divides the two objects element-wise. Will panic if the shapes differ.
+    ///
+    /// Division by a scalar is provided by [CalcNode::div].
+    pub fn element_wise_div(&self, other: &Self) -> CalcNode {
+        assert_eq!(self.shape(), other.shape(), "Element-wise division requires equal shapes.");
+        let result = CalcNode::element_wise_div_vals(self, other);
+        let mut result = CalcNode::new_from_shape(self.shape(), result);
+        result.set_parents(vec![self.clone(), other.clone()]);
+        result.set_forward(CalcNode::replay_binary(CalcNode::element_wise_div_vals));
+        result.set_back_propagation(Box::new(|child| {
+            let parents = &child.borrow()._parent_nodes;
+            for (i, &child_grad) in child.borrow()._grad.iter().enumerate() {
+                let num = parents[0].borrow()._vals[i];
+                let denom = parents[1].borrow()._vals[i];
+                // d(a/b)/da = 1/b and d(a/b)/db = -a/b²
+                parents[0].borrow_mut()._grad[i] += child_grad / denom;
+                parents[1].borrow_mut()._grad[i] -= child_grad * num / (denom * denom);
+            }
+        }));
+        result
+    }
+
+    fn element_wise_mul_vals(a: &CalcNode, b: &CalcNode) -> Vec<FloatType> {
+        let b = b.borrow();
+        a.borrow()._vals.iter().zip(b._vals.iter()).map(|(a, b)| a * b).collect()
+    }
+
+    fn element_wise_div_vals(a: &CalcNode, b: &CalcNode) -> Vec<FloatType> {
+        let b = b.borrow();
+        a.borrow()._vals.iter().zip(b._vals.iter()).map(|(a, b)| a / b).collect()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    //use assert_approx_eq::assert_approx_eq;
+    use crate::nnetwork::{CalcNode32, CalcNode64, VecOrientation};
+    use assert_approx_eq::assert_approx_eq;
 
     #[test]
     fn addition_of_two_scalars() {
@@ -481,7 +955,7 @@ mod tests {
         let inp2 = CalcNode::new_scalar(3.);
         let expected_value = &[4., 5.];
         let expected_derivative1 = &[1., 1.];
-        let expected_derivative2 = &[1.];
+        let expected_derivative2 = &[2.];
         let mut out = &inp1 + &inp2;
         assert_eq!(out.copy_vals(), expected_value);
         out.back_propagation();
@@ -621,4 +1095,297 @@ mod tests {
         assert_eq!(inp1.copy_grad(), expected_derivative1);
         assert_eq!(inp2.copy_grad(), expected_derivative2);
     }
+
+    #[test]
+    fn tanh_forward() {
+        let inp: CalcNode = CalcNode::new_col_vector(vec![-10., -2., -1., 0., 1., 2., 10.]);
+        let expected_value = &[-1., -0.9640276, -0.7615942, 0., 0.7615942, 0.9640276, 1.];
+        let out = inp.tanh();
+        for (value, expected_value) in out.copy_vals().iter().zip(expected_value) {
+            assert_approx_eq!(value, expected_value);
+        }
+    }
+
+    #[test]
+    fn forward_pass_in_single_and_double_precision() {
+        fn forward<T: Float>(w: &CalcNode<T>, b: &CalcNode<T>, x: &CalcNode<T>) -> CalcNode<T> {
+            (w * x + b).tanh()
+        }
+        let out32 = forward(
+            &CalcNode32::new_from_shape((2, 2), vec![0.1, 0.2, 0.3, 0.4]),
+            &CalcNode32::new_col_vector(vec![0.5, -0.5]),
+            &CalcNode32::new_col_vector(vec![1., -1.]),
+        );
+        let out64 = forward(
+            &CalcNode64::new_from_shape((2, 2), vec![0.1, 0.2, 0.3, 0.4]),
+            &CalcNode64::new_col_vector(vec![0.5, -0.5]),
+            &CalcNode64::new_col_vector(vec![1., -1.]),
+        );
+        let expected = [0.4f64.tanh(), (-0.6f64).tanh()];
+        for ((v32, v64), expected) in out32.copy_vals().iter().zip(out64.copy_vals()).zip(expected) {
+            assert_approx_eq!(*v32 as f64, expected, 1e-6);
+            assert_approx_eq!(v64, expected);
+        }
+    }
+
+    #[test]
+    fn tanh_backward_matches_finite_difference() {
+        const EPSILON: f64 = 1e-6;
+        let points = vec![-2., -0.5, 0., 0.3, 1.5];
+        let inp = CalcNode::new_col_vector(points.clone());
+        let mut out = inp.tanh();
+        out.back_propagation();
+        for (i, x) in points.into_iter().enumerate() {
+            let numerical = ((x + EPSILON).tanh() - (x - EPSILON).tanh()) / (2. * EPSILON);
+            assert_approx_eq!(inp.gradient_indexed(i), numerical, 1e-6);
+        }
+    }
+
+    #[test]
+    fn element_wise_mul_with_zero_factor() {
+        // The gradient is the other factor, never the product divided by the own value
+        let a = CalcNode::new_col_vector(vec![0., 2.]);
+        let b = CalcNode::new_col_vector(vec![3., 4.]);
+        let mut out = a.element_wise_mul(&b).sum();
+        out.back_propagation();
+        assert_eq!(a.copy_grad(), &[3., 4.]);
+        assert_eq!(b.copy_grad(), &[0., 2.]);
+    }
+
+    #[test]
+    fn mean_distributes_gradient() {
+        let inp = CalcNode::new_col_vector(vec![2., 4., 6.]);
+        let mut out = inp.mean();
+        assert_eq!(out.shape(), (1, 1));
+        assert_eq!(out.value_indexed(0), 4.);
+        out.back_propagation();
+        inp.copy_grad().iter().for_each(|&g| assert_approx_eq!(g, 1. / 3.));
+    }
+
+    #[test]
+    fn variance_and_std_of_values() {
+        let inp = CalcNode::new_col_vector(vec![1., 2., 3.]);
+        let mut variance = inp.variance();
+        assert_approx_eq!(variance.value_indexed(0), 2. / 3.);
+        assert_approx_eq!(inp.std().value_indexed(0), (2. / 3. as FloatType).sqrt());
+        variance.back_propagation();
+        // 2(x_i - mean)/n
+        let expected = [-2. / 3., 0., 2. / 3.];
+        for (g, e) in inp.copy_grad().iter().zip(expected) {
+            assert_approx_eq!(g, e);
+        }
+    }
+
+    #[test]
+    fn log_sum_exp_is_stable() {
+        let inp = CalcNode::new_col_vector(vec![0.5, -1., 2.]);
+        let mut out = inp.log_sum_exp();
+        assert_approx_eq!(out.value_indexed(0), inp.exp().sum().log().value_indexed(0));
+        out.back_propagation();
+        let softmax = inp.exp().normalized().copy_vals();
+        for (g, e) in inp.copy_grad().iter().zip(softmax) {
+            assert_approx_eq!(g, e);
+        }
+
+        let inp = CalcNode::new_col_vector(vec![1000., 1001.]);
+        assert!(inp.exp().sum().log().value_indexed(0).is_infinite());
+        let mut out = inp.log_sum_exp();
+        assert_approx_eq!(out.value_indexed(0), 1001. + (1. + (-1. as FloatType).exp()).ln());
+        out.back_propagation();
+        assert!(inp.copy_grad().iter().all(|g| g.is_finite()));
+        assert_approx_eq!(inp.copy_grad().iter().sum::<FloatType>(), 1.);
+    }
+
+    #[test]
+    fn pow_of_non_positive_values_has_finite_gradients() {
+        let inp = CalcNode::new_col_vector(vec![-1., 2., 0.]);
+        let power = CalcNode::new_scalar(2.);
+        let mut out = inp.pow(&power).sum();
+        out.back_propagation();
+        assert_eq!(inp.copy_grad(), &[-2., 4., 0.]);
+        // Only the positive value contributes to the gradient of the power
+        assert_approx_eq!(power.gradient_indexed(0), 4. * (2. as FloatType).ln());
+    }
+
+    #[test]
+    fn square_gradients() {
+        let inp = CalcNode::new_col_vector(vec![-2., 0., 3.]);
+        let mut out = inp.square();
+        assert_eq!(out.copy_vals(), &[4., 0., 9.]);
+        out.back_propagation();
+        assert_eq!(inp.copy_grad(), &[-4., 0., 6.]);
+    }
+
+    #[test]
+    fn element_wise_div_gradients() {
+        let num = CalcNode::new_col_vector(vec![6., 8.]);
+        let denom = CalcNode::new_col_vector(vec![2., 4.]);
+        let mut out = &num / &denom;
+        assert_eq!(out.copy_vals(), &[3., 2.]);
+        // Only the division itself is part of the graph
+        assert_eq!(out.borrow().parents().len(), 2);
+        out.back_propagation();
+        assert_eq!(num.copy_grad(), &[0.5, 0.25]);
+        assert_eq!(denom.copy_grad(), &[-1.5, -0.5]);
+    }
+
+    #[test]
+    fn clamp_only_passes_gradient_inside_bounds() {
+        let inp = CalcNode::new_col_vector(vec![-1., 0.5, 2.]);
+        let mut out = inp.clamp(0., 1.);
+        assert_eq!(out.copy_vals(), &[0., 0.5, 1.]);
+        out.back_propagation();
+        assert_eq!(inp.copy_grad(), &[0., 1., 0.]);
+    }
+
+    #[test]
+    fn element_wise_max_routes_gradient_to_larger_value() {
+        let inp1 = CalcNode::new_col_vector(vec![1., 5., 2.]);
+        let inp2 = CalcNode::new_col_vector(vec![3., 2., 2.]);
+        let mut out = inp1.element_wise_max(&inp2);
+        assert_eq!(out.copy_vals(), &[3., 5., 2.]);
+        out.back_propagation();
+        // The tie splits the gradient evenly
+        assert_eq!(inp1.copy_grad(), &[0., 1., 0.5]);
+        assert_eq!(inp2.copy_grad(), &[1., 0., 0.5]);
+    }
+
+    #[test]
+    fn element_wise_min_routes_gradient_to_smaller_value() {
+        let inp1 = CalcNode::new_col_vector(vec![1., 5.]);
+        let inp2 = CalcNode::new_col_vector(vec![3., 2.]);
+        let mut out = inp1.element_wise_min(&inp2);
+        assert_eq!(out.copy_vals(), &[1., 2.]);
+        out.back_propagation();
+        assert_eq!(inp1.copy_grad(), &[1., 0.]);
+        assert_eq!(inp2.copy_grad(), &[0., 1.]);
+    }
+
+    #[test]
+    fn abs_forward_and_sign_gradient() {
+        let mut inp = CalcNode::new_col_vector(vec![-2., 0., 3.]);
+        let mut out = inp.abs();
+        assert_eq!(out.copy_vals(), &[2., 0., 3.]);
+        out.back_propagation();
+        assert_eq!(inp.copy_grad(), &[-1., 0., 1.]);
+        // The values can be recalculated after the input changes
+        inp.set_vals(&[1., -4., 0.]);
+        out.recalculate();
+        assert_eq!(out.copy_vals(), &[1., 4., 0.]);
+    }
+
+    #[test]
+    fn addition_of_column_vector_to_matrix() {
+        let matrix = CalcNode::new_from_shape((2, 3), vec![1., 2., 3., 4., 5., 6.]);
+        let vector = CalcNode::new_col_vector(vec![1., 2.]);
+        let expected_value = &[2., 3., 4., 6., 7., 8.];
+        let mut out = &matrix + &vector;
+        assert_eq!(out.shape(), (2, 3));
+        assert_eq!(out.copy_vals(), expected_value);
+        out.back_propagation();
+        assert_eq!(matrix.copy_grad(), &[1., 1., 1., 1., 1., 1.]);
+        assert_eq!(vector.copy_grad(), &[3., 3.]);
+        // The operand order does not matter
+        let mut out = &vector + &matrix;
+        assert_eq!(out.copy_vals(), expected_value);
+        out.back_propagation();
+        assert_eq!(vector.copy_grad(), &[3., 3.]);
+    }
+
+    #[test]
+    fn transpose_of_matrix() {
+        let inp = CalcNode::new_from_shape((2, 3), vec![1., 2., 3., 4., 5., 6.]);
+        let weights = CalcNode::new_from_shape((2, 3), vec![1., 2., 3., 4., 5., 6.]);
+        let out = inp.transpose();
+        assert_eq!(out.shape(), (3, 2));
+        assert_eq!(out.copy_vals(), &[1., 4., 2., 5., 3., 6.]);
+        let mut loss = out.transpose().element_wise_mul(&weights).sum();
+        loss.back_propagation();
+        assert_eq!(inp.copy_grad(), weights.copy_vals());
+    }
+
+    #[test]
+    fn transpose_of_column_vector() {
+        let inp = CalcNode::new_col_vector(vec![1., 2., 3.]);
+        let out = inp.transpose();
+        assert_eq!(out.node_type(), NodeType::Vector(VecOrientation::Row));
+        assert_eq!(out.copy_vals(), inp.copy_vals());
+    }
+
+    #[test]
+    fn slicing_column_from_matrix() {
+        let inp = CalcNode::new_from_shape((2, 3), vec![1., 2., 3., 4., 5., 6.]);
+        let mut out = inp.column(1);
+        assert_eq!(out.shape(), (2, 1));
+        assert_eq!(out.copy_vals(), &[2., 5.]);
+        out.back_propagation();
+        assert_eq!(inp.copy_grad(), &[0., 1., 0., 0., 1., 0.]);
+    }
+
+    #[test]
+    fn slicing_row_from_matrix() {
+        let inp = CalcNode::new_from_shape((2, 3), vec![1., 2., 3., 4., 5., 6.]);
+        let mut out = inp.row(1);
+        assert_eq!(out.shape(), (1, 3));
+        assert_eq!(out.copy_vals(), &[4., 5., 6.]);
+        out.back_propagation();
+        assert_eq!(inp.copy_grad(), &[0., 0., 0., 1., 1., 1.]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Column index 3 out of bounds")]
+    fn slicing_column_out_of_bounds() {
+        CalcNode::new_from_shape((2, 3), vec![1., 2., 3., 4., 5., 6.]).column(3);
+    }
+
+    #[test]
+    fn concatenating_column_vectors() {
+        let inp1 = CalcNode::new_col_vector(vec![1., 2.]);
+        let inp2 = CalcNode::new_col_vector(vec![3., 4.]);
+        let weights = CalcNode::new_col_vector(vec![5., 6., 7., 8.]);
+        let out = CalcNode::concat_rows(&[inp1.clone(), inp2.clone()]);
+        assert_eq!(out.shape(), (4, 1));
+        assert_eq!(out.copy_vals(), &[1., 2., 3., 4.]);
+        let mut loss = out.element_wise_mul(&weights).sum();
+        loss.back_propagation();
+        assert_eq!(inp1.copy_grad(), &[5., 6.]);
+        assert_eq!(inp2.copy_grad(), &[7., 8.]);
+    }
+
+    #[test]
+    fn concatenating_matrices_horizontally() {
+        let inp1 = CalcNode::new_from_shape((2, 2), vec![1., 2., 3., 4.]);
+        let inp2 = CalcNode::new_col_vector(vec![5., 6.]);
+        let mut out = CalcNode::concat_cols(&[inp1.clone(), inp2.clone()]);
+        assert_eq!(out.shape(), (2, 3));
+        assert_eq!(out.copy_vals(), &[1., 2., 5., 3., 4., 6.]);
+        out.back_propagation();
+        assert_eq!(inp1.copy_grad(), &[1., 1., 1., 1.]);
+        assert_eq!(inp2.copy_grad(), &[1., 1.]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot concatenate rows")]
+    fn concatenating_incompatible_shapes() {
+        CalcNode::concat_rows(&[
+            CalcNode::new_col_vector(vec![1., 2.]),
+            CalcNode::new_row_vector(vec![3., 4.]),
+        ]);
+    }
+
+    #[test]
+    fn max_of_vector() {
+        let inp = CalcNode::new_col_vector(vec![1., 3., 2.]);
+        let mut out = inp.max();
+        assert_eq!(out.value_indexed(0), 3.);
+        out.back_propagation();
+        assert_eq!(inp.copy_grad(), &[0., 1., 0.]);
+        assert_eq!(inp.argmax(), 1);
+    }
+
+    #[test]
+    fn argmax_ties_go_to_first() {
+        let inp = CalcNode::new_col_vector(vec![3., 1., 3.]);
+        assert_eq!(inp.argmax(), 0);
+    }
 }
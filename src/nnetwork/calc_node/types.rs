@@ -1,8 +1,36 @@
-/// If we ever want to change to f32, it is easy
+use std::fmt::{Debug, Display};
+
+use num_traits::NumAssignOps;
+
+/// The float type used by the networks. [CalcNode](super::CalcNode) itself can also be used with other [Float]s.
 pub type FloatType = f64;
+
+/// The floating point types a [CalcNode](super::CalcNode) can hold, i.e., [f32] and [f64].
+pub trait Float: num_traits::Float + NumAssignOps + Debug + Display + Default + 'static {}
+
+impl<T: num_traits::Float + NumAssignOps + Debug + Display + Default + 'static> Float for T {}
 /// All nodes are treated as matrices of shape (rows, columns)
 pub type NodeShape = (usize, usize);
 
+/// Returned by [CalcNode::try_reshape](super::CalcNode::try_reshape) when the new shape holds a different number of values than the node.
+#[derive(Debug, PartialEq)]
+pub struct ReshapeError {
+    pub old_len: usize,
+    pub new_len: usize,
+}
+
+impl Display for ReshapeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Cannot reshape {} values into a shape holding {} values.",
+            self.old_len, self.new_len
+        )
+    }
+}
+
+impl std::error::Error for ReshapeError {}
+
 /// Mostly used for debug output
 #[derive(Debug, PartialEq)]
 pub enum VecOrientation {
@@ -18,3 +46,6 @@ pub enum NodeType {
     Vector(VecOrientation),
     Matrix,
 }
+
+/// Recalculates the values of a node from its parents, see [CalcNode::set_forward](super::CalcNode::set_forward).
+pub type ForwardFunc<T = FloatType> = dyn Fn(&super::CalcNode<T>) -> Vec<T>;
@@ -0,0 +1,83 @@
+use crate::nnetwork::{CalcNode, FloatType};
+
+use super::FunctionLayer;
+
+/// Scaled dot-product attention, `softmax(QKᵀ/sqrt(d))·V`, where `d` is the dimension of the keys. Every row of the query, key and value matrices is one position, so the query and key must have the same number of columns and the key and value the same number of rows. The softmax is taken over every row of the scores, i.e., over the keys attended to by one query. Composed of ordinary operations, so the gradient flows to all three inputs.
+///
+/// # Example
+/// ```
+/// use net_neurons::nnetwork::{attention, CalcNode};
+///
+/// let query = CalcNode::from_rows(vec![vec![1., 0.]]);
+/// let key = CalcNode::from_rows(vec![vec![1., 0.], vec![1., 0.]]);
+/// let value = CalcNode::from_rows(vec![vec![1., 2.], vec![3., 4.]]);
+/// // Both keys are equally similar to the query, so the values are averaged
+/// assert_eq!(attention(&query, &key, &value).copy_vals(), &[2., 3.]);
+/// ```
+pub fn attention(query: &CalcNode, key: &CalcNode, value: &CalcNode) -> CalcNode {
+    let (n_queries, dim) = query.shape();
+    assert_eq!(
+        dim,
+        key.shape().1,
+        "Query and key must have the same number of columns."
+    );
+    assert_eq!(
+        key.shape().0,
+        value.shape().0,
+        "Key and value must have the same number of rows."
+    );
+    let scale = CalcNode::new_scalar(1. / (dim as FloatType).sqrt());
+    let scores = query * &key.transpose() * scale;
+    let weights: Vec<CalcNode> = (0..n_queries)
+        .map(|i| {
+            let row = scores.row(i);
+            // Shifting by the largest score avoids overflow without changing the softmax
+            let max = row
+                .copy_vals()
+                .into_iter()
+                .fold(FloatType::MIN, FloatType::max);
+            FunctionLayer::softmax(&(row - CalcNode::new_scalar(max)))
+        })
+        .collect();
+    CalcNode::concat_rows(&weights) * value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nnetwork::calc_node::gradient_check::gradient_check;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn attention_of_fixed_matrices() {
+        let query = CalcNode::from_rows(vec![vec![1., 0.], vec![0., 1.]]);
+        let key = CalcNode::from_rows(vec![vec![1., 0.], vec![0., 1.]]);
+        let value = CalcNode::from_rows(vec![vec![1., 2.], vec![3., 4.]]);
+        let out = attention(&query, &key, &value);
+        assert_eq!(out.shape(), (2, 2));
+        // Every query attends to its own key with the weight e^(1/√2)/(e^(1/√2)+1)
+        let e = (1. / (2. as FloatType).sqrt()).exp();
+        let w = e / (e + 1.);
+        let expected = [
+            w + (1. - w) * 3.,
+            w * 2. + (1. - w) * 4.,
+            (1. - w) + w * 3.,
+            (1. - w) * 2. + w * 4.,
+        ];
+        for (v, e) in out.copy_vals().into_iter().zip(expected) {
+            assert_approx_eq!(v, e);
+        }
+
+        let inputs = [
+            CalcNode::from_rows(vec![vec![0.5, -1.], vec![2., 0.25]]),
+            CalcNode::from_rows(vec![vec![1., 0.5], vec![-0.5, 1.5], vec![0., 1.]]),
+            CalcNode::from_rows(vec![vec![1., 2.], vec![3., -4.], vec![0.5, 1.]]),
+        ];
+        let discrepancy = gradient_check(
+            |x| attention(&x[0], &x[1], &x[2]).row(1).column(0),
+            &inputs,
+            1e-6,
+        );
+        assert!(discrepancy < 1e-6, "Discrepancy: {discrepancy}");
+    }
+}
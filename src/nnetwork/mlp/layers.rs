@@ -1,18 +1,65 @@
 use std::{
+    cell::RefCell,
     fmt::Display,
     iter::{self, empty},
+    rc::Rc,
 };
 
-use crate::nnetwork::{CalcNode, FloatType, NodeShape};
+use rand::{thread_rng, Rng, RngCore};
+use rand_distr::Uniform;
+
+use crate::nnetwork::{CalcNode, FloatType, ForwardFunc, NodeShape};
 
 use crate::nnetwork::Parameters;
 
 use super::Layer;
 
+/// Decides how the weights of a [LinearLayer] are initialised. The scale is based on the number of inputs to the layer, i.e., the number of columns of the weight matrix.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InitScheme {
+    /// Unscaled values from a standard normal distribution.
+    StandardNormal,
+    /// Normal distribution scaled by `sqrt(1/n_cols)`, suitable for sigmoid and tanh.
+    Xavier,
+    /// Uniform distribution with a standard deviation of `sqrt(2/n_cols)`, suitable for ReLU.
+    KaimingUniform,
+}
+
+impl InitScheme {
+    // Creates a node of the given shape with values drawn according to the scheme
+    fn sample(&self, shape: NodeShape) -> CalcNode {
+        self.sample_with(shape, &mut thread_rng())
+    }
+
+    /// Creates a node of the given shape with values drawn according to the scheme from the supplied random number generator, e.g., a seeded one for reproducibility.
+    pub fn sample_with<R: Rng + ?Sized>(&self, shape: NodeShape, rng: &mut R) -> CalcNode {
+        let fan_in = shape.1 as FloatType;
+        match self {
+            InitScheme::StandardNormal => CalcNode::rand_from_shape_seeded(shape, rng),
+            InitScheme::Xavier => {
+                let scale = CalcNode::new_scalar((1. / fan_in).sqrt());
+                let vals = (CalcNode::rand_from_shape_seeded(shape, rng) * scale).copy_vals();
+                CalcNode::new_from_shape(shape, vals)
+            }
+            InitScheme::KaimingUniform => {
+                // The variance of U(-b,b) is b²/3
+                let bound = (6. / fan_in).sqrt();
+                let dist = Uniform::new_inclusive(-bound, bound);
+                CalcNode::new_from_shape(
+                    shape,
+                    (0..shape.0 * shape.1).map(|_| rng.sample(dist)).collect(),
+                )
+            }
+        }
+    }
+}
+
 /// Standard layer consisting of a matrix of weights and a column vector of biases. The biases are optional.
 pub struct LinearLayer {
     _w: CalcNode,
     _b: Option<CalcNode>,
+    // How the parameters are drawn when the layer is reinitialised
+    _init: InitScheme,
     _label: String,
 }
 
@@ -26,6 +73,30 @@ impl LinearLayer {
             } else {
                 None
             },
+            _init: InitScheme::StandardNormal,
+            _label: label.to_string(),
+        }
+    }
+
+    /// Creates a [LinearLayer] with weights initialised according to the [InitScheme]. The (optional) biases are random for [InitScheme::StandardNormal] and zero otherwise.
+    pub fn new_rand_with_init(
+        n_rows: usize,
+        n_cols: usize,
+        biased: bool,
+        label: &str,
+        init: InitScheme,
+    ) -> LinearLayer {
+        if init == InitScheme::StandardNormal {
+            return Self::new_rand(n_rows, n_cols, biased, label);
+        }
+        LinearLayer {
+            _w: init.sample((n_rows, n_cols)),
+            _b: if biased {
+                Some(CalcNode::new_col_vector(vec![0.; n_rows]))
+            } else {
+                None
+            },
+            _init: init,
             _label: label.to_string(),
         }
     }
@@ -48,9 +119,86 @@ impl LinearLayer {
         LinearLayer {
             _w: w,
             _b: b,
+            _init: InitScheme::StandardNormal,
             _label: label.to_string(),
         }
     }
+
+    // Translates (row, col) into the index of the flat weight values
+    fn weight_index(&self, row: usize, col: usize) -> usize {
+        let (n_rows, n_cols) = self._w.shape();
+        assert!(
+            row < n_rows && col < n_cols,
+            "Weight ({row}, {col}) is out of bounds for weights of shape ({n_rows}, {n_cols})."
+        );
+        row * n_cols + col
+    }
+
+    /// Returns the weight of the indexed row and column. Panics if out of bounds.
+    pub fn weight(&self, row: usize, col: usize) -> FloatType {
+        self._w.value_indexed(self.weight_index(row, col))
+    }
+
+    /// Overwrites the weight of the indexed row and column. Panics if out of bounds.
+    pub fn set_weight(&mut self, row: usize, col: usize, val: FloatType) {
+        let i = self.weight_index(row, col);
+        self._w.set_value_indexed(i, val);
+    }
+
+    /// Returns the bias of the indexed row, or `None` if the layer is unbiased. Panics if out of bounds.
+    pub fn bias(&self, row: usize) -> Option<FloatType> {
+        self._b.as_ref().map(|b| {
+            assert!(row < b.len(), "Bias {row} is out of bounds for {} rows.", b.len());
+            b.value_indexed(row)
+        })
+    }
+
+    /// Overwrites the bias of the indexed row. Panics if out of bounds or if the layer is unbiased.
+    pub fn set_bias(&mut self, row: usize, val: FloatType) {
+        let b = self._b.as_mut().expect("Cannot set the bias of an unbiased layer.");
+        assert!(row < b.len(), "Bias {row} is out of bounds for {} rows.", b.len());
+        b.set_value_indexed(row, val);
+    }
+
+    /// Adds output rows to the weights, and biases, keeping all existing parameters in place. The new weights are initialised according to the [InitScheme] and the new biases as in [LinearLayer::new_rand_with_init].
+    pub fn grow_rows(&mut self, additional: usize, init: InitScheme) {
+        let (n_rows, n_cols) = self._w.shape();
+        let new_rows = n_rows + additional;
+        self._w = grown(&self._w, init.sample((new_rows, n_cols)));
+        if let Some(b) = &self._b {
+            let new_b = if init == InitScheme::StandardNormal {
+                CalcNode::rand_from_shape((new_rows, 1))
+            } else {
+                CalcNode::new_col_vector(vec![0.; new_rows])
+            };
+            self._b = Some(grown(b, new_b));
+        }
+    }
+
+    /// Adds input columns to the weights, keeping all existing parameters in place. The new weights are initialised according to the [InitScheme].
+    pub fn grow_cols(&mut self, additional: usize, init: InitScheme) {
+        let (n_rows, n_cols) = self._w.shape();
+        self._w = grown(&self._w, init.sample((n_rows, n_cols + additional)));
+    }
+}
+
+// Copies the values of the node into the top left corner of the larger node
+fn grown(node: &CalcNode, larger: CalcNode) -> CalcNode {
+    let (n_rows, n_cols) = node.shape();
+    let (new_rows, new_cols) = larger.shape();
+    assert!(
+        new_rows >= n_rows && new_cols >= n_cols,
+        "Cannot grow shape {:?} into {:?}.",
+        node.shape(),
+        larger.shape()
+    );
+    let old = node.copy_vals();
+    let mut vals = larger.copy_vals();
+    for row in 0..n_rows {
+        vals[row * new_cols..row * new_cols + n_cols]
+            .copy_from_slice(&old[row * n_cols..(row + 1) * n_cols]);
+    }
+    CalcNode::new_from_shape((new_rows, new_cols), vals)
 }
 
 impl Display for LinearLayer {
@@ -66,8 +214,8 @@ impl Display for LinearLayer {
 impl Parameters for LinearLayer {
     fn param_iter(&self) -> Box<dyn Iterator<Item = &CalcNode> + '_> {
         let parameters = iter::once(&self._w);
-        if self._b.is_some() {
-            Box::new(parameters.chain(iter::once(self._b.as_ref().unwrap())))
+        if let Some(b) = &self._b {
+            Box::new(parameters.chain(iter::once(b)))
         } else {
             Box::new(parameters)
         }
@@ -75,18 +223,31 @@ impl Parameters for LinearLayer {
 
     fn param_iter_mut(&mut self) -> Box<dyn Iterator<Item = &mut CalcNode> + '_> {
         let parameters = iter::once(&mut self._w);
-        if self._b.is_some() {
-            Box::new(parameters.chain(iter::once(self._b.as_mut().unwrap())))
+        if let Some(b) = &mut self._b {
+            Box::new(parameters.chain(iter::once(b)))
         } else {
             Box::new(parameters)
         }
     }
+
+    fn weight_iter_mut(&mut self) -> Box<dyn Iterator<Item = &mut CalcNode> + '_> {
+        Box::new(iter::once(&mut self._w))
+    }
 }
 
 impl Layer for LinearLayer {
+    fn input_dim(&self) -> Option<usize> {
+        Some(self._w.shape().1)
+    }
+
+    fn output_dim(&self) -> Option<usize> {
+        Some(self._w.shape().0)
+    }
+
+    /// The input may be a matrix where every column is a separate example, in which case the biases are added to every column.
     fn forward(&self, prev: &CalcNode) -> CalcNode {
-        if self._b.is_some() {
-            &self._w * prev + self._b.as_ref().unwrap()
+        if let Some(b) = &self._b {
+            &self._w * prev + b
         } else {
             &self._w * prev
         }
@@ -95,6 +256,147 @@ impl Layer for LinearLayer {
     fn layer_name(&self) -> &str {
         &self._label
     }
+
+    /// The weights are drawn according to the [InitScheme] of the layer and the biases as in [LinearLayer::new_rand_with_init]. Layers created by [LinearLayer::from_nodes] use [InitScheme::StandardNormal].
+    fn reinit(&mut self, rng: &mut dyn RngCore) {
+        let w = self._init.sample_with(self._w.shape(), rng).copy_vals();
+        self._w.set_vals(&w);
+        if let Some(b) = &mut self._b {
+            let vals = if self._init == InitScheme::StandardNormal {
+                CalcNode::rand_from_shape_seeded(b.shape(), rng).copy_vals()
+            } else {
+                vec![0.; b.len()]
+            };
+            b.set_vals(&vals);
+        }
+    }
+}
+
+/// A [Layer] that maps one-hot encoded columns onto learnable embedding vectors. It is equivalent to multiplying by an unbiased weight matrix, but only looks up the columns of the hot indices instead.
+pub struct EmbeddingLayer {
+    _w: CalcNode,
+    _label: String,
+}
+
+impl EmbeddingLayer {
+    /// Creates an [EmbeddingLayer] with a random table of shape `(embed_dim, vocab)`.
+    pub fn new_rand(embed_dim: usize, vocab: usize, label: &str) -> EmbeddingLayer {
+        EmbeddingLayer {
+            _w: CalcNode::rand_from_shape((embed_dim, vocab)),
+            _label: label.to_string(),
+        }
+    }
+
+    /// Creates an [EmbeddingLayer] from a predetermined table. Every column is the embedding of one entry in the vocabulary.
+    pub fn from_nodes(w: CalcNode, label: &str) -> EmbeddingLayer {
+        assert!(!w.is_empty(), "Cannot create layer from empty tensor.");
+        EmbeddingLayer {
+            _w: w,
+            _label: label.to_string(),
+        }
+    }
+
+    // Adds random embeddings for new entries at the end of the vocabulary, keeping the existing ones
+    pub(crate) fn grow_vocabulary(&mut self, additional: usize) {
+        let (embed_dim, vocab) = self._w.shape();
+        self._w = grown(&self._w, CalcNode::rand_from_shape((embed_dim, vocab + additional)));
+    }
+
+    // The columns of the table selected by the indices, as a matrix of shape (embed_dim, indices.len())
+    fn look_up(table: &CalcNode, indices: &[usize]) -> Vec<FloatType> {
+        let (embed_dim, vocab) = table.shape();
+        let n_cols = indices.len();
+        let table = table.borrow();
+        (0..embed_dim * n_cols)
+            .map(|i| table.vals()[(i / n_cols) * vocab + indices[i % n_cols]])
+            .collect()
+    }
+
+    // Returns the row index of the single non-zero value in every column
+    fn hot_indices(inp: &CalcNode) -> Vec<usize> {
+        let (n_rows, n_cols) = inp.shape();
+        let vals = inp.borrow();
+        (0..n_cols)
+            .map(|col| {
+                let hot: Vec<usize> = (0..n_rows)
+                    .filter(|row| vals.vals()[row * n_cols + col] > 0.)
+                    .collect();
+                assert_eq!(
+                    hot.len(),
+                    1,
+                    "Input to embedding layer must consist of one-hot columns."
+                );
+                hot[0]
+            })
+            .collect()
+    }
+}
+
+impl Display for EmbeddingLayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "EmbeddingLayer ({}): [table: {}]", self._label, self._w)
+    }
+}
+
+impl Parameters for EmbeddingLayer {
+    fn param_iter(&self) -> Box<dyn Iterator<Item = &CalcNode> + '_> {
+        Box::new(iter::once(&self._w))
+    }
+
+    fn param_iter_mut(&mut self) -> Box<dyn Iterator<Item = &mut CalcNode> + '_> {
+        Box::new(iter::once(&mut self._w))
+    }
+}
+
+impl Layer for EmbeddingLayer {
+    fn input_dim(&self) -> Option<usize> {
+        Some(self._w.shape().1)
+    }
+
+    fn output_dim(&self) -> Option<usize> {
+        Some(self._w.shape().0)
+    }
+
+    /// The input is expected to be a matrix of one-hot columns with as many rows as the vocabulary. It receives no gradient.
+    fn forward(&self, inp: &CalcNode) -> CalcNode {
+        let (embed_dim, vocab) = self._w.shape();
+        assert_eq!(
+            inp.shape().0,
+            vocab,
+            "Input to embedding layer must have as many rows as the vocabulary."
+        );
+        let indices = Self::hot_indices(inp);
+        let n_cols = indices.len();
+        let vals = Self::look_up(&self._w, &indices);
+        let mut out = CalcNode::new(
+            (embed_dim, n_cols),
+            vals,
+            vec![self._w.clone()],
+            Some(Box::new(move |child: CalcNode| {
+                let mut table = child.copy_parents().remove(0);
+                let mut table_grad = vec![0.; table.len()];
+                for (i, &g) in child.borrow().grad().iter().enumerate() {
+                    table_grad[(i / n_cols) * vocab + indices[i % n_cols]] += g;
+                }
+                table.add_grad(&table_grad);
+            })),
+        );
+        // The input is not a parent since it receives no gradient, so it is kept here
+        let inp = inp.clone();
+        out.set_forward(Box::new(move |node| {
+            Self::look_up(&node.copy_parents()[0], &Self::hot_indices(&inp))
+        }));
+        out
+    }
+
+    fn layer_name(&self) -> &str {
+        &self._label
+    }
+
+    fn reinit(&mut self, rng: &mut dyn RngCore) {
+        let w = CalcNode::rand_from_shape_seeded(self._w.shape(), rng).copy_vals();
+        self._w.set_vals(&w);
+    }
 }
 
 /// A [Layer] type that only coerces the input into a new shape without changing its values.
@@ -130,9 +432,89 @@ impl Parameters for ReshapeLayer {
 }
 
 impl Layer for ReshapeLayer {
+    /// Any input of the right size is accepted, so only the rows of the output are known.
+    fn output_dim(&self) -> Option<usize> {
+        Some(self._shape.0)
+    }
+
+    fn forward(&self, inp: &CalcNode) -> CalcNode {
+        let mut out = inp.clone();
+        if let Err(err) = out.try_reshape(self._shape) {
+            panic!("{}: {err}", self._label);
+        }
+        out
+    }
+
+    fn layer_name(&self) -> &str {
+        &self._label
+    }
+}
+
+/// Flattens a matrix of a fixed shape into a column vector, row by row. Unlike a [ReshapeLayer] the input must have exactly the expected shape, not just the same size, so that a misconfigured network fails with a descriptive error.
+pub struct FlattenLayer {
+    _input_shape: NodeShape,
+    _label: String,
+}
+
+impl FlattenLayer {
+    /// Accepts inputs of `input_shape`, e.g., `(embed_dim, block_size)` for the output of an [EmbeddingLayer].
+    pub fn new(input_shape: NodeShape, label: &str) -> Self {
+        assert!(
+            input_shape.0 > 0 && input_shape.1 > 0,
+            "Cannot flatten the empty shape {input_shape:?}."
+        );
+        FlattenLayer {
+            _input_shape: input_shape,
+            _label: label.to_string(),
+        }
+    }
+
+    /// The length of the flattened column vector.
+    pub fn output_len(&self) -> usize {
+        self._input_shape.0 * self._input_shape.1
+    }
+}
+
+impl Display for FlattenLayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "FlattenLayer ({}): [{:?}]", self._label, self._input_shape)
+    }
+}
+
+impl Parameters for FlattenLayer {
+    fn param_iter(&self) -> Box<dyn Iterator<Item = &CalcNode> + '_> {
+        Box::new(empty())
+    }
+
+    fn param_iter_mut(&mut self) -> Box<dyn Iterator<Item = &mut CalcNode> + '_> {
+        Box::new(empty())
+    }
+}
+
+impl Layer for FlattenLayer {
+    fn shape(&self) -> Option<NodeShape> {
+        Some(self._input_shape)
+    }
+
+    fn input_dim(&self) -> Option<usize> {
+        Some(self._input_shape.0)
+    }
+
+    fn output_dim(&self) -> Option<usize> {
+        Some(self.output_len())
+    }
+
     fn forward(&self, inp: &CalcNode) -> CalcNode {
+        assert_eq!(
+            inp.shape(),
+            self._input_shape,
+            "{} expects input of shape {:?} but received {:?}.",
+            self._label,
+            self._input_shape,
+            inp.shape()
+        );
         let mut out = inp.clone();
-        out.reshape(self._shape);
+        out.reshape((self.output_len(), 1));
         out
     }
 
@@ -141,19 +523,383 @@ impl Layer for ReshapeLayer {
     }
 }
 
+/// A [Layer] that randomly zeroes values with probability `p` while training, scaling the survivors by `1/(1-p)`. In evaluation mode it does nothing.
+pub struct DropoutLayer {
+    _p: FloatType,
+    _training: bool,
+    _label: String,
+}
+
+impl DropoutLayer {
+    /// The layer starts out in training mode.
+    pub fn new(p: FloatType, label: &str) -> Self {
+        assert!(
+            (0. ..=1.).contains(&p),
+            "Dropout probability must be in the range [0,1]."
+        );
+        DropoutLayer {
+            _p: p,
+            _training: true,
+            _label: label.to_string(),
+        }
+    }
+}
+
+impl Display for DropoutLayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "DropoutLayer ({}): [p: {}, training: {}]",
+            self._label, self._p, self._training
+        )
+    }
+}
+
+impl Parameters for DropoutLayer {
+    fn param_iter(&self) -> Box<dyn Iterator<Item = &CalcNode> + '_> {
+        Box::new(empty())
+    }
+
+    fn param_iter_mut(&mut self) -> Box<dyn Iterator<Item = &mut CalcNode> + '_> {
+        Box::new(empty())
+    }
+}
+
+impl Layer for DropoutLayer {
+    fn forward(&self, inp: &CalcNode) -> CalcNode {
+        if !self._training || self._p == 0. {
+            return inp.clone();
+        }
+        let mut rng = thread_rng();
+        let scale = if self._p < 1. { 1. / (1. - self._p) } else { 0. };
+        let mask: Vec<FloatType> = (0..inp.len())
+            .map(|_| if rng.gen::<FloatType>() < self._p { 0. } else { scale })
+            .collect();
+        let vals = inp
+            .borrow()
+            .vals()
+            .iter()
+            .zip(mask.iter())
+            .map(|(v, m)| v * m)
+            .collect();
+        CalcNode::new(
+            inp.shape(),
+            vals,
+            vec![inp.clone()],
+            Some(Box::new(move |child: CalcNode| {
+                // Only the surviving values pass on the gradient
+                let grad: Vec<FloatType> = child
+                    .borrow()
+                    .grad()
+                    .iter()
+                    .zip(mask.iter())
+                    .map(|(g, m)| g * m)
+                    .collect();
+                child.copy_parents()[0].add_grad(&grad);
+            })),
+        )
+    }
+
+    fn layer_name(&self) -> &str {
+        &self._label
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self._training = training;
+    }
+}
+
+/// A [Layer] that normalises a column vector to zero mean and unit variance before applying a learnable element-wise gain and bias.
+pub struct LayerNormLayer {
+    _gain: CalcNode,
+    _bias: CalcNode,
+    _label: String,
+}
+
+impl LayerNormLayer {
+    /// Avoids division by zero for constant inputs
+    const EPSILON: FloatType = 1e-5;
+
+    /// The gain starts out as ones and the bias as zeros, i.e., a pure normalisation.
+    pub fn new(dim: usize, label: &str) -> Self {
+        LayerNormLayer {
+            _gain: CalcNode::new_col_vector(vec![1.; dim]),
+            _bias: CalcNode::new_col_vector(vec![0.; dim]),
+            _label: label.to_string(),
+        }
+    }
+}
+
+impl Display for LayerNormLayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "LayerNormLayer ({}): [gain: {}, bias: {}]",
+            self._label, self._gain, self._bias
+        )
+    }
+}
+
+impl Parameters for LayerNormLayer {
+    fn param_iter(&self) -> Box<dyn Iterator<Item = &CalcNode> + '_> {
+        Box::new(iter::once(&self._gain).chain(iter::once(&self._bias)))
+    }
+
+    fn param_iter_mut(&mut self) -> Box<dyn Iterator<Item = &mut CalcNode> + '_> {
+        Box::new(iter::once(&mut self._gain).chain(iter::once(&mut self._bias)))
+    }
+
+    fn weight_iter_mut(&mut self) -> Box<dyn Iterator<Item = &mut CalcNode> + '_> {
+        Box::new(iter::once(&mut self._gain))
+    }
+}
+
+impl Layer for LayerNormLayer {
+    fn input_dim(&self) -> Option<usize> {
+        Some(self._gain.len())
+    }
+
+    fn output_dim(&self) -> Option<usize> {
+        Some(self._gain.len())
+    }
+
+    fn forward(&self, inp: &CalcNode) -> CalcNode {
+        assert_eq!(
+            inp.shape(),
+            self._gain.shape(),
+            "Input to layer norm must be a column vector of the same size as the layer."
+        );
+        let centered = inp - inp.mean();
+        let variance = centered.element_wise_mul(&centered).mean();
+        let std = (variance + CalcNode::new_scalar(Self::EPSILON)).pow(&CalcNode::new_scalar(0.5));
+        let normalized = centered / std;
+        normalized.element_wise_mul(&self._gain) + &self._bias
+    }
+
+    fn layer_name(&self) -> &str {
+        &self._label
+    }
+
+    /// Nothing is random, the gain is reset to ones and the bias to zeros.
+    fn reinit(&mut self, _rng: &mut dyn RngCore) {
+        self._gain.set_vals(&vec![1.; self._gain.len()]);
+        self._bias.set_vals(&vec![0.; self._bias.len()]);
+    }
+}
+
+/// A recurrent (Elman) [Layer] that remembers a hidden state between calls. Every step calculates `h = tanh(W_xh*x + W_hh*h_prev + b)`.
+///
+/// The hidden state starts out as zeros and is kept until [Layer::reset_state] is called. Since the state is part of the calculation, back propagation reaches through all steps taken since the last reset. The training functions of [MultiLayer](super::MultiLayer) reset the state before every example.
+pub struct RecurrentLayer {
+    _w_xh: CalcNode,
+    _w_hh: CalcNode,
+    _b: CalcNode,
+    _state: RefCell<Option<CalcNode>>,
+    _label: String,
+}
+
+impl RecurrentLayer {
+    /// Creates a [RecurrentLayer] with random weights and biases, taking input columns of size `input_dim`.
+    pub fn new_rand(hidden_dim: usize, input_dim: usize, label: &str) -> RecurrentLayer {
+        RecurrentLayer {
+            _w_xh: CalcNode::rand_from_shape((hidden_dim, input_dim)),
+            _w_hh: CalcNode::rand_from_shape((hidden_dim, hidden_dim)),
+            _b: CalcNode::rand_from_shape((hidden_dim, 1)),
+            _state: RefCell::new(None),
+            _label: label.to_string(),
+        }
+    }
+
+    /// Calculates the next hidden state from the input and the previous hidden state without touching the stored state.
+    pub fn step(&self, x: &CalcNode, h: &CalcNode) -> CalcNode {
+        (&self._w_xh * x + &self._w_hh * h + &self._b).tanh()
+    }
+
+    /// Returns the current hidden state, if any step has been taken since the last reset.
+    pub fn state(&self) -> Option<CalcNode> {
+        self._state.borrow().clone()
+    }
+}
+
+impl Display for RecurrentLayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "RecurrentLayer ({}): [input weights: {}, hidden weights: {}, biases: {}]",
+            self._label, self._w_xh, self._w_hh, self._b
+        )
+    }
+}
+
+impl Parameters for RecurrentLayer {
+    fn param_iter(&self) -> Box<dyn Iterator<Item = &CalcNode> + '_> {
+        Box::new([&self._w_xh, &self._w_hh, &self._b].into_iter())
+    }
+
+    fn param_iter_mut(&mut self) -> Box<dyn Iterator<Item = &mut CalcNode> + '_> {
+        Box::new([&mut self._w_xh, &mut self._w_hh, &mut self._b].into_iter())
+    }
+
+    fn weight_iter_mut(&mut self) -> Box<dyn Iterator<Item = &mut CalcNode> + '_> {
+        Box::new([&mut self._w_xh, &mut self._w_hh].into_iter())
+    }
+}
+
+impl Layer for RecurrentLayer {
+    fn input_dim(&self) -> Option<usize> {
+        Some(self._w_xh.shape().1)
+    }
+
+    fn output_dim(&self) -> Option<usize> {
+        Some(self._b.len())
+    }
+
+    /// Takes one step with the input column and returns the new hidden state, which is also stored for the next step.
+    fn forward(&self, inp: &CalcNode) -> CalcNode {
+        let h = self
+            .state()
+            .unwrap_or_else(|| CalcNode::new_col_vector(vec![0.; self._b.len()]));
+        let h = self.step(inp, &h);
+        self._state.replace(Some(h.clone()));
+        h
+    }
+
+    fn layer_name(&self) -> &str {
+        &self._label
+    }
+
+    fn is_stateful(&self) -> bool {
+        true
+    }
+
+    /// Forgets the hidden state, the next step starts from zeros.
+    fn reset_state(&self) {
+        self._state.replace(None);
+    }
+
+    fn reinit(&mut self, rng: &mut dyn RngCore) {
+        for p in [&mut self._w_xh, &mut self._w_hh, &mut self._b] {
+            let vals = CalcNode::rand_from_shape_seeded(p.shape(), rng).copy_vals();
+            p.set_vals(&vals);
+        }
+    }
+}
+
+/// A [Layer] wrapping another [Layer] with a skip connection, i.e., the output is `inner(x) + x`. The inner [Layer] must keep the shape of the input. Its parameters are the parameters of this [Layer].
+pub struct ResidualLayer {
+    _inner: Box<dyn Layer>,
+    _label: String,
+}
+
+impl ResidualLayer {
+    /// Panics if the inner [Layer] is known to change the number of rows of the input.
+    pub fn new(inner: Box<dyn Layer>, label: &str) -> Self {
+        assert_eq!(
+            inner.input_dim(),
+            inner.output_dim(),
+            "Inner {} '{}' of a residual layer must keep the shape of the input.",
+            inner.layer_type(),
+            inner.layer_name()
+        );
+        ResidualLayer {
+            _inner: inner,
+            _label: label.to_string(),
+        }
+    }
+
+    /// The wrapped [Layer].
+    pub fn inner(&self) -> &dyn Layer {
+        self._inner.as_ref()
+    }
+}
+
+impl Display for ResidualLayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "ResidualLayer ({}): [inner layer below]", self._label)?;
+        write!(f, "{}", self._inner)
+    }
+}
+
+impl Parameters for ResidualLayer {
+    fn param_iter(&self) -> Box<dyn Iterator<Item = &CalcNode> + '_> {
+        self._inner.param_iter()
+    }
+
+    fn param_iter_mut(&mut self) -> Box<dyn Iterator<Item = &mut CalcNode> + '_> {
+        self._inner.param_iter_mut()
+    }
+
+    fn weight_iter_mut(&mut self) -> Box<dyn Iterator<Item = &mut CalcNode> + '_> {
+        self._inner.weight_iter_mut()
+    }
+}
+
+impl Layer for ResidualLayer {
+    fn shape(&self) -> Option<NodeShape> {
+        self._inner.shape()
+    }
+
+    fn input_dim(&self) -> Option<usize> {
+        self._inner.input_dim()
+    }
+
+    fn output_dim(&self) -> Option<usize> {
+        self._inner.output_dim()
+    }
+
+    fn forward(&self, inp: &CalcNode) -> CalcNode {
+        let out = self._inner.forward(inp);
+        assert_eq!(
+            out.shape(),
+            inp.shape(),
+            "Inner layer of residual layer {} changed the shape of the input.",
+            self._label
+        );
+        out + inp
+    }
+
+    fn layer_name(&self) -> &str {
+        &self._label
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self._inner.set_training(training);
+    }
+
+    fn reinit(&mut self, rng: &mut dyn RngCore) {
+        self._inner.reinit(rng);
+    }
+
+    fn is_stateful(&self) -> bool {
+        self._inner.is_stateful()
+    }
+
+    fn reset_state(&self) {
+        self._inner.reset_state();
+    }
+}
+
 /// A [Layer] type that applies a mathematical function to the input [CalcNode]. The function is responsible for also setting up the gradient calculation for back propagation to work.
 ///
 /// # Example
-/// Some commonly used functions are already implemented, e.g., [FunctionLayer::sigmoid], [FunctionLayer::tanh], [FunctionLayer::leaky_relu], [FunctionLayer::softmax].
+/// Some commonly used functions are already implemented, e.g., [FunctionLayer::sigmoid], [FunctionLayer::tanh], [FunctionLayer::relu], [FunctionLayer::leaky_relu], [FunctionLayer::gelu], [FunctionLayer::softmax].
 /// ```
 /// use net_neurons::nnetwork::{CalcNode, FunctionLayer};
 ///
 /// let non_linearity =
 ///     FunctionLayer::new(&FunctionLayer::sigmoid, "Sigmoid", "Non-linearity layer");
 /// ```
+/// Closures can also be used with [FunctionLayer::from_fn], e.g., to capture a parameter of the function.
+/// ```
+/// use net_neurons::nnetwork::{CalcNode, FunctionLayer};
+///
+/// let scale = CalcNode::new_scalar(0.5);
+/// let scaling = FunctionLayer::from_fn(move |x: &CalcNode| x * &scale, "0.5*x", "Scaling layer");
+/// ```
 #[derive(Clone)]
 pub struct FunctionLayer {
-    _func: &'static dyn Fn(&CalcNode) -> CalcNode,
+    // Shared between clones of the layer
+    _func: Rc<dyn Fn(&CalcNode) -> CalcNode>,
     _formula: String,
     _label: String,
 }
@@ -163,21 +909,35 @@ impl FunctionLayer {
         f: &'static dyn Fn(&CalcNode) -> CalcNode,
         formula: &str,
         label: &str,
+    ) -> FunctionLayer {
+        Self::from_fn(f, formula, label)
+    }
+
+    /// Same as [FunctionLayer::new] but the layer takes ownership of the function, so that closures capturing values can be used.
+    pub fn from_fn(
+        f: impl Fn(&CalcNode) -> CalcNode + 'static,
+        formula: &str,
+        label: &str,
     ) -> FunctionLayer {
         FunctionLayer {
-            _func: f,
+            _func: Rc::new(f),
             _formula: formula.into(),
             _label: label.into(),
         }
     }
 
+    /// The description of the function, shown when the layer is printed.
+    pub fn formula(&self) -> &str {
+        &self._formula
+    }
+
     // Helper function for implementations of functions b=f(a) that has derivatives that can be expressed as a function of the result, f'(b)
     fn function_layer_back_propagator(
         inp: &CalcNode,
-        func: &dyn Fn(FloatType) -> FloatType,
+        func: &'static dyn Fn(FloatType) -> FloatType,
         gfunc: &'static dyn Fn(FloatType) -> FloatType,
     ) -> CalcNode {
-        CalcNode::new(
+        let mut out = CalcNode::new(
             inp.shape(),
             inp.borrow().vals().iter().map(|&x| (func)(x)).collect(),
             vec![inp.clone()],
@@ -192,7 +952,49 @@ impl FunctionLayer {
                         .collect::<Vec<_>>(),
                 );
             })),
-        )
+        );
+        out.set_forward(Self::function_replay(func));
+        out
+    }
+
+    // Helper function for implementations of functions b=f(a) whose derivatives must be expressed as a function of the input, f'(a)
+    fn function_layer_input_back_propagator(
+        inp: &CalcNode,
+        func: &'static dyn Fn(FloatType) -> FloatType,
+        gfunc: &'static dyn Fn(FloatType) -> FloatType,
+    ) -> CalcNode {
+        let mut out = CalcNode::new(
+            inp.shape(),
+            inp.borrow().vals().iter().map(|&x| (func)(x)).collect(),
+            vec![inp.clone()],
+            Some(Box::new(|child: CalcNode| {
+                let mut parent = child.copy_parents().remove(0);
+                let grad = parent
+                    .borrow()
+                    .vals()
+                    .iter()
+                    .zip(child.borrow().grad().iter())
+                    .map(|(&x, &g)| (gfunc)(x) * g)
+                    .collect::<Vec<_>>();
+                parent.add_grad(&grad);
+            })),
+        );
+        out.set_forward(Self::function_replay(func));
+        out
+    }
+
+    // Recalculates the output from the input by applying the function again
+    fn function_replay(
+        func: &'static dyn Fn(FloatType) -> FloatType,
+    ) -> Box<ForwardFunc> {
+        Box::new(|node| {
+            node.copy_parents()[0]
+                .borrow()
+                .vals()
+                .iter()
+                .map(|&x| (func)(x))
+                .collect()
+        })
     }
 
     pub fn sigmoid(inp: &CalcNode) -> CalcNode {
@@ -200,7 +1002,7 @@ impl FunctionLayer {
     }
 
     pub fn tanh(inp: &CalcNode) -> CalcNode {
-        Self::function_layer_back_propagator(inp, &|x| x.tanh(), &|x| (1. - x * x))
+        inp.tanh()
     }
 
     pub fn leaky_relu(inp: &CalcNode) -> CalcNode {
@@ -212,6 +1014,29 @@ impl FunctionLayer {
         )
     }
 
+    pub fn relu(inp: &CalcNode) -> CalcNode {
+        Self::function_layer_input_back_propagator(
+            inp,
+            &|x| if x > 0. { x } else { 0. },
+            &|x| if x > 0. { 1. } else { 0. },
+        )
+    }
+
+    /// Uses the tanh approximation of the Gaussian error linear unit.
+    pub fn gelu(inp: &CalcNode) -> CalcNode {
+        const SQRT_2_OVER_PI: FloatType = 0.7978845608028654;
+        const COEFF: FloatType = 0.044715;
+        Self::function_layer_input_back_propagator(
+            inp,
+            &|x| 0.5 * x * (1. + (SQRT_2_OVER_PI * (x + COEFF * x * x * x)).tanh()),
+            &|x| {
+                let t = (SQRT_2_OVER_PI * (x + COEFF * x * x * x)).tanh();
+                0.5 * (1. + t)
+                    + 0.5 * x * (1. - t * t) * SQRT_2_OVER_PI * (1. + 3. * COEFF * x * x)
+            },
+        )
+    }
+
     pub fn softmax(inp: &CalcNode) -> CalcNode {
         inp.exp().normalized()
     }
@@ -247,6 +1072,65 @@ mod tests {
     use super::*;
     use assert_approx_eq::assert_approx_eq;
 
+    #[test]
+    fn grown_layer_preserves_parameters() {
+        let w = vec![1., 2., 3., 4., 5., 6.];
+        let b = vec![0.5, -0.5];
+        let mut layer = LinearLayer::from_nodes(
+            CalcNode::new_from_shape((2, 3), w.clone()),
+            Some(CalcNode::new_col_vector(b.clone())),
+            "TestLayer",
+        );
+        layer.grow_rows(2, InitScheme::Xavier);
+        layer.grow_cols(1, InitScheme::KaimingUniform);
+        let params: Vec<_> = layer.param_iter().collect();
+        assert_eq!(params[0].shape(), (4, 4));
+        assert_eq!(params[1].shape(), (4, 1));
+        let grown_w = params[0].copy_vals();
+        for row in 0..2 {
+            let preserved = &grown_w[row * 4..row * 4 + 3];
+            let original = &w[row * 3..row * 3 + 3];
+            assert!(preserved.iter().zip(original).all(|(a, b)| a.to_bits() == b.to_bits()));
+        }
+        assert_eq!(params[1].copy_vals(), vec![0.5, -0.5, 0., 0.]);
+    }
+
+    #[test]
+    fn grown_layer_reproduces_output_on_padded_input() {
+        let mut layer = LinearLayer::new_rand(3, 2, true, "TestLayer");
+        let old_out = layer.forward(&CalcNode::new_col_vector(vec![0.3, -1.2])).copy_vals();
+        layer.grow_cols(2, InitScheme::StandardNormal);
+        layer.grow_rows(1, InitScheme::StandardNormal);
+        let new_out = layer
+            .forward(&CalcNode::new_col_vector(vec![0.3, -1.2, 0., 0.]))
+            .copy_vals();
+        assert_eq!(new_out.len(), 4);
+        assert_eq!(new_out[..3], old_out[..]);
+    }
+
+    #[test]
+    fn edited_weight_shows_in_output() {
+        let mut layer = LinearLayer::from_nodes(
+            CalcNode::new_from_shape((2, 2), vec![1., 2., 3., 4.]),
+            Some(CalcNode::new_col_vector(vec![0., 0.])),
+            "TestLayer",
+        );
+        layer.set_weight(1, 0, 7.);
+        layer.set_bias(0, 0.5);
+        assert_eq!(layer.weight(1, 0), 7.);
+        assert_eq!(layer.bias(0), Some(0.5));
+        // The first basis vector picks out the first column
+        let out = layer.forward(&CalcNode::new_col_vector(vec![1., 0.]));
+        assert_eq!(out.copy_vals(), &[1.5, 7.]);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn weight_out_of_bounds_panics() {
+        let layer = LinearLayer::new_rand(2, 3, false, "TestLayer");
+        layer.weight(0, 3);
+    }
+
     #[test]
     fn unbiased_layer_forward() {
         let layer = LinearLayer::from_nodes(
@@ -297,6 +1181,53 @@ mod tests {
         assert_eq!(inp.copy_grad(), expected_derivative2);
     }
 
+    #[test]
+    fn batched_forward_matches_single_forward() {
+        let layer = LinearLayer::from_nodes(
+            CalcNode::new_from_shape((2, 3), vec![1., 2., 3., 4., 5., 6.]),
+            Some(CalcNode::new_col_vector(vec![7., 8.])),
+            "TestLayer",
+        );
+        let examples = [vec![1., 0., -1.], vec![0.5, 2., 3.], vec![-2., 1., 0.]];
+        let batch = CalcNode::new_from_shape(
+            (3, 3),
+            (0..9).map(|i| examples[i % 3][i / 3]).collect(),
+        );
+        let batched = layer.forward(&batch);
+        assert_eq!(batched.shape(), (2, 3));
+        for (col, example) in examples.iter().enumerate() {
+            let single = layer.forward(&CalcNode::new_col_vector(example.clone()));
+            assert_eq!(
+                single.copy_vals(),
+                &[batched.value_indexed(col), batched.value_indexed(3 + col)]
+            );
+        }
+    }
+
+    #[test]
+    fn embedding_layer_matches_unbiased_linear_layer() {
+        let w = vec![1., 2., 3., 4., 5., 6.];
+        let embedding =
+            EmbeddingLayer::from_nodes(CalcNode::new_from_shape((2, 3), w.clone()), "Embedding");
+        let linear = LinearLayer::from_nodes(CalcNode::new_from_shape((2, 3), w), None, "Linear");
+        let inp = CalcNode::new_from_shape((3, 2), vec![0., 1., 0., 0., 1., 0.]);
+        let mut out1 = embedding.forward(&inp);
+        let mut out2 = linear.forward(&inp);
+        assert_eq!(out1.shape(), out2.shape());
+        assert_eq!(out1.copy_vals(), out2.copy_vals());
+        out1.back_propagation();
+        out2.back_propagation();
+        assert_eq!(
+            embedding.param_iter().next().unwrap().copy_grad(),
+            linear.param_iter().next().unwrap().copy_grad()
+        );
+        // Only the looked up columns receive gradients
+        assert_eq!(
+            embedding.param_iter().next().unwrap().copy_grad(),
+            &[1., 0., 1., 1., 0., 1.]
+        );
+    }
+
     #[test]
     fn tanh_forward() {
         let layer = FunctionLayer::new(&FunctionLayer::tanh, "tanh", "TestLayer");
@@ -307,4 +1238,175 @@ mod tests {
             assert_approx_eq!(value, expected_value);
         }
     }
+
+    #[test]
+    fn function_layer_from_capturing_closure() {
+        // Leaky ReLU with a custom slope for negative values
+        let slope = CalcNode::new_scalar(0.1);
+        let layer = FunctionLayer::from_fn(
+            move |x: &CalcNode| FunctionLayer::relu(x) - &slope * FunctionLayer::relu(&-x),
+            "Leaky ReLU (0.1)",
+            "TestLayer",
+        );
+        let inp = CalcNode::new_col_vector(vec![-2., 0.5]);
+        let out = layer.clone().forward(&inp);
+        assert_approx_eq!(out.value_indexed(0), -0.2);
+        assert_approx_eq!(out.value_indexed(1), 0.5);
+    }
+
+    #[test]
+    fn relu_forward_and_backward() {
+        let layer = FunctionLayer::new(&FunctionLayer::relu, "ReLU", "TestLayer");
+        let inp = CalcNode::new_col_vector(vec![-2., 0., 3.]);
+        let mut out = layer.forward(&inp);
+        assert_eq!(out.copy_vals(), &[0., 0., 3.]);
+        out.back_propagation();
+        assert_eq!(inp.copy_grad(), &[0., 0., 1.]);
+    }
+
+    #[test]
+    fn gelu_backward_matches_finite_difference() {
+        const EPSILON: FloatType = 1e-6;
+        let layer = FunctionLayer::new(&FunctionLayer::gelu, "GELU", "TestLayer");
+        let points = vec![-3., -0.5, 0., 0.7, 2.];
+        let inp = CalcNode::new_col_vector(points.clone());
+        let mut out = layer.forward(&inp);
+        assert_approx_eq!(out.value_indexed(2), 0.);
+        assert_approx_eq!(out.value_indexed(4), 1.9545977, 1e-6);
+        out.back_propagation();
+        for (i, x) in points.into_iter().enumerate() {
+            let f = |x| FunctionLayer::gelu(&CalcNode::new_scalar(x)).value_indexed(0);
+            let numerical = (f(x + EPSILON) - f(x - EPSILON)) / (2. * EPSILON);
+            assert_approx_eq!(inp.gradient_indexed(i), numerical, 1e-6);
+        }
+    }
+
+    #[test]
+    fn xavier_initialisation_has_expected_spread() {
+        let layer =
+            LinearLayer::new_rand_with_init(100, 100, true, "TestLayer", InitScheme::Xavier);
+        let mut params = layer.param_iter();
+        let weights = params.next().unwrap().copy_vals();
+        let n = weights.len() as FloatType;
+        let mean = weights.iter().sum::<FloatType>() / n;
+        let std = (weights.iter().map(|w| (w - mean).powi(2)).sum::<FloatType>() / n).sqrt();
+        assert_approx_eq!(std, 0.1, 0.01);
+        assert!(params.next().unwrap().copy_vals().iter().all(|&b| b == 0.));
+    }
+
+    #[test]
+    fn flatten_layer_checks_input_shape() {
+        let layer = FlattenLayer::new((2, 3), "TestLayer");
+        let inp = CalcNode::new_from_shape((2, 3), vec![1., 2., 3., 4., 5., 6.]);
+        let out = layer.forward(&inp);
+        assert_eq!(out.shape(), (6, 1));
+        assert_eq!(out.copy_vals(), &[1., 2., 3., 4., 5., 6.]);
+    }
+
+    #[test]
+    #[should_panic(expected = "TestLayer expects input of shape (2, 3) but received (3, 2).")]
+    fn flatten_layer_rejects_transposed_input() {
+        let layer = FlattenLayer::new((2, 3), "TestLayer");
+        layer.forward(&CalcNode::new_from_shape((3, 2), vec![0.; 6]));
+    }
+
+    #[test]
+    fn dropout_is_identity_in_evaluation_mode() {
+        let mut layer = DropoutLayer::new(0.5, "TestLayer");
+        layer.set_training(false);
+        let inp = CalcNode::new_col_vector(vec![1., 2., 3., 4.]);
+        let out = layer.forward(&inp);
+        assert_eq!(out.copy_vals(), inp.copy_vals());
+    }
+
+    #[test]
+    fn dropout_with_certain_probability_zeroes_everything() {
+        let layer = DropoutLayer::new(1., "TestLayer");
+        let inp = CalcNode::new_col_vector(vec![1., 2., 3., 4.]);
+        let mut out = layer.forward(&inp);
+        assert_eq!(out.copy_vals(), &[0., 0., 0., 0.]);
+        out.back_propagation();
+        assert_eq!(inp.copy_grad(), &[0., 0., 0., 0.]);
+    }
+
+    #[test]
+    fn layer_norm_normalizes_and_propagates_gradients() {
+        const EPSILON: FloatType = 1e-6;
+        let layer = LayerNormLayer::new(4, "TestLayer");
+        let vals = vec![1., 2., 4., 9.];
+        let weights = CalcNode::new_col_vector(vec![1., -2., 3., 0.5]);
+        let inp = CalcNode::new_col_vector(vals.clone());
+        let out = layer.forward(&inp);
+        let out_vals = out.copy_vals();
+        let mean = out_vals.iter().sum::<FloatType>() / 4.;
+        let variance = out_vals.iter().map(|v| (v - mean).powi(2)).sum::<FloatType>() / 4.;
+        assert_approx_eq!(mean, 0.);
+        assert_approx_eq!(variance, 1., 1e-4);
+
+        let mut loss = out.element_wise_mul(&weights).sum();
+        loss.back_propagation();
+        for p in layer.param_iter() {
+            assert!(p.copy_grad().iter().any(|&g| g != 0.));
+        }
+        // Compare the input gradient to finite differences
+        for i in 0..vals.len() {
+            let f = |delta| {
+                let mut vals = vals.clone();
+                vals[i] += delta;
+                layer
+                    .forward(&CalcNode::new_col_vector(vals))
+                    .element_wise_mul(&weights)
+                    .sum()
+                    .value_indexed(0)
+            };
+            let numerical = (f(EPSILON) - f(-EPSILON)) / (2. * EPSILON);
+            assert_approx_eq!(inp.gradient_indexed(i), numerical, 1e-5);
+        }
+    }
+
+    #[test]
+    fn recurrent_layer_gradients_reach_all_parameters() {
+        let layer = RecurrentLayer::new_rand(3, 2, "TestLayer");
+        layer.forward(&CalcNode::new_col_vector(vec![1., -1.]));
+        let mut out = layer.forward(&CalcNode::new_col_vector(vec![0.5, 2.])).sum();
+        out.back_propagation();
+        for param in layer.param_iter() {
+            assert!(param.copy_grad().iter().any(|&g| g != 0.));
+        }
+        layer.reset_state();
+        assert!(layer.state().is_none());
+    }
+
+    #[test]
+    fn residual_layer_adds_the_input() {
+        let weights = vec![1., 0.5, 0., 1.];
+        let layer = ResidualLayer::new(
+            Box::new(LinearLayer::from_nodes(
+                CalcNode::new_from_shape((2, 2), weights),
+                Some(CalcNode::new_col_vector(vec![0.25, -0.25])),
+                "Inner",
+            )),
+            "TestLayer",
+        );
+        assert_eq!(layer.param_iter().count(), 2);
+        let inp = CalcNode::new_col_vector(vec![2., -1.]);
+        let inner_out = layer.inner().forward(&inp).copy_vals();
+        let mut out = layer.forward(&inp);
+        // inner: [2 - 0.5 + 0.25, -1 - 0.25]
+        assert_eq!(inner_out, vec![1.75, -1.25]);
+        assert_eq!(out.copy_vals(), vec![3.75, -2.25]);
+
+        out.back_propagation();
+        // One from the skip connection plus the column sums of the weights
+        assert_eq!(inp.copy_grad(), vec![2., 2.5]);
+        let params: Vec<_> = layer.param_iter().collect();
+        assert_eq!(params[0].copy_grad(), vec![2., -1., 2., -1.]);
+        assert_eq!(params[1].copy_grad(), vec![1., 1.]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Inner LinearLayer 'Inner' of a residual layer must keep the shape")]
+    fn residual_layer_rejects_resizing_inner_layer() {
+        ResidualLayer::new(Box::new(LinearLayer::new_rand(3, 2, true, "Inner")), "TestLayer");
+    }
 }
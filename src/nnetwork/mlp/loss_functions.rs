@@ -1,14 +1,195 @@
-use crate::nnetwork::CalcNode;
+use crate::nnetwork::{CalcNode, FloatType};
 
 /// Takes the prediction as one argument and the truth as the other, and calcualted a number representing the loss. The lower the loss, the better.
 pub type LossFuncType = dyn Fn(&CalcNode, &CalcNode) -> CalcNode;
 
 /// Calculates the sum of the squares of the diviations from the truth.
 pub fn least_squares(inp: &CalcNode, truth: &CalcNode) -> CalcNode {
-    (inp - truth).pow(&CalcNode::new_scalar(2.)).sum()
+    (inp - truth).square().sum()
 }
 
-/// Assumes the input can be treated as a probability distribution and that the truth is a one-hot vector
+/// Calculates the mean of the absolute values of the deviations from the truth. Less sensitive to outliers than [least_squares].
+pub fn mean_absolute_error(inp: &CalcNode, truth: &CalcNode) -> CalcNode {
+    (inp - truth).abs().sum() / CalcNode::new_scalar(inp.len() as FloatType)
+}
+
+/// The Huber (smooth L1) loss averaged over the elements. Deviations `e` from the truth within `delta` contribute `0.5*e²`, like [least_squares], while larger ones contribute `delta*(|e| - 0.5*delta)`, growing linearly like [mean_absolute_error]. Pass it as, e.g., `&|inp, truth| huber(inp, truth, 1.)` to [MultiLayer::set_loss_function](crate::nnetwork::MultiLayer::set_loss_function).
+pub fn huber(inp: &CalcNode, truth: &CalcNode, delta: FloatType) -> CalcNode {
+    assert_eq!(
+        inp.shape(),
+        truth.shape(),
+        "Prediction and truth must have the same shape."
+    );
+    assert!(delta > 0., "The delta of the Huber loss must be positive.");
+    let n = inp.len() as FloatType;
+    let deviations: Vec<FloatType> = inp
+        .copy_vals()
+        .iter()
+        .zip(truth.copy_vals())
+        .map(|(x, t)| x - t)
+        .collect();
+    let loss = deviations
+        .iter()
+        .map(|e| {
+            if e.abs() <= delta {
+                0.5 * e * e
+            } else {
+                delta * (e.abs() - 0.5 * delta)
+            }
+        })
+        .sum::<FloatType>()
+        / n;
+    CalcNode::new(
+        (1, 1),
+        vec![loss],
+        vec![inp.clone(), truth.clone()],
+        Some(Box::new(move |child: CalcNode| {
+            // The derivative is the deviation, clamped to [-delta, delta]
+            let child_grad = child.gradient_indexed(0) / n;
+            let grad: Vec<FloatType> = deviations
+                .iter()
+                .map(|e| child_grad * e.clamp(-delta, delta))
+                .collect();
+            let neg_grad: Vec<FloatType> = grad.iter().map(|g| -g).collect();
+            let mut parents = child.copy_parents();
+            parents[0].add_grad(&grad);
+            parents[1].add_grad(&neg_grad);
+        })),
+    )
+}
+
+/// Added to the probability by [neg_log_likelihood] before taking the logarithm.
+pub const NLL_EPSILON: FloatType = 1e-12;
+
+/// Assumes the input can be treated as a probability distribution and that the truth is a one-hot vector. [NLL_EPSILON] is added to the probability of the true class, keeping the loss finite even if the probability is zero.
 pub fn neg_log_likelihood(inp: &CalcNode, truth: &CalcNode) -> CalcNode {
-    -(inp.element_wise_mul(truth)).sum().log()
+    neg_log_likelihood_with_epsilon(inp, truth, NLL_EPSILON)
+}
+
+/// Same as [neg_log_likelihood] but with a custom `epsilon`, e.g., `&|inp, truth| neg_log_likelihood_with_epsilon(inp, truth, 1e-9)` can be passed to [MultiLayer::set_loss_function](crate::nnetwork::MultiLayer::set_loss_function).
+pub fn neg_log_likelihood_with_epsilon(
+    inp: &CalcNode,
+    truth: &CalcNode,
+    epsilon: FloatType,
+) -> CalcNode {
+    -(inp.element_wise_mul(truth).sum() + CalcNode::new_scalar(epsilon)).log()
+}
+
+/// Fuses softmax and [neg_log_likelihood]. The input is treated as unnormalised logits and the truth as a one-hot vector. Uses the log-sum-exp trick so that large logits do not overflow.
+pub fn cross_entropy_from_logits(logits: &CalcNode, truth: &CalcNode) -> CalcNode {
+    assert_eq!(
+        logits.len(),
+        truth.len(),
+        "Logits and truth must have the same size."
+    );
+    let logit_vals = logits.copy_vals();
+    let truth_vals = truth.copy_vals();
+    let max = logit_vals.iter().copied().fold(FloatType::MIN, FloatType::max);
+    let exps: Vec<FloatType> = logit_vals.iter().map(|x| (x - max).exp()).collect();
+    let exp_sum: FloatType = exps.iter().sum();
+    let log_sum_exp = max + exp_sum.ln();
+    let truth_sum: FloatType = truth_vals.iter().sum();
+    let loss = log_sum_exp * truth_sum
+        - logit_vals
+            .iter()
+            .zip(truth_vals.iter())
+            .map(|(x, t)| x * t)
+            .sum::<FloatType>();
+    let softmax: Vec<FloatType> = exps.into_iter().map(|e| e / exp_sum).collect();
+    CalcNode::new(
+        (1, 1),
+        vec![loss],
+        vec![logits.clone()],
+        Some(Box::new(move |child: CalcNode| {
+            // The derivative w.r.t. the logits is softmax(logits) - truth
+            let child_grad = child.gradient_indexed(0);
+            let grad: Vec<FloatType> = softmax
+                .iter()
+                .zip(truth_vals.iter())
+                .map(|(s, t)| child_grad * (s * truth_sum - t))
+                .collect();
+            child.copy_parents()[0].add_grad(&grad);
+        })),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nnetwork::FunctionLayer;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn cross_entropy_matches_softmax_and_neg_log_likelihood() {
+        let logits1 = CalcNode::new_col_vector(vec![0.5, -1., 2.]);
+        let logits2 = CalcNode::new_col_vector(vec![0.5, -1., 2.]);
+        let truth = CalcNode::new_col_vector(vec![0., 1., 0.]);
+        let mut fused = cross_entropy_from_logits(&logits1, &truth);
+        let mut two_step = neg_log_likelihood(&FunctionLayer::softmax(&logits2), &truth);
+        assert_approx_eq!(fused.value_indexed(0), two_step.value_indexed(0));
+        fused.back_propagation();
+        two_step.back_propagation();
+        for (g1, g2) in logits1.copy_grad().iter().zip(logits2.copy_grad()) {
+            assert_approx_eq!(g1, g2);
+        }
+    }
+
+    #[test]
+    fn cross_entropy_is_finite_for_large_logits() {
+        let logits = CalcNode::new_col_vector(vec![1000., 0., -5.]);
+        let truth = CalcNode::new_col_vector(vec![0., 1., 0.]);
+        let mut loss = cross_entropy_from_logits(&logits, &truth);
+        assert_approx_eq!(loss.value_indexed(0), 1000.);
+        loss.back_propagation();
+        assert!(logits.copy_grad().iter().all(|g| g.is_finite()));
+        assert_approx_eq!(logits.gradient_indexed(0), 1.);
+        assert_approx_eq!(logits.gradient_indexed(1), -1.);
+    }
+
+    #[test]
+    fn mean_absolute_error_value_and_gradient() {
+        let pred = CalcNode::new_col_vector(vec![1., -2., 0.5, 3.]);
+        let truth = CalcNode::new_col_vector(vec![0., 1., 0.5, 5.]);
+        let mut loss = mean_absolute_error(&pred, &truth);
+        // (1 + 3 + 0 + 2) / 4
+        assert_approx_eq!(loss.value_indexed(0), 1.5);
+        loss.back_propagation();
+        let expected = [0.25, -0.25, 0., -0.25];
+        for (g, e) in pred.copy_grad().into_iter().zip(expected) {
+            assert_approx_eq!(g, e);
+        }
+    }
+
+    #[test]
+    fn huber_is_quadratic_near_and_linear_far_from_the_truth() {
+        let truth = CalcNode::new_col_vector(vec![1., -1.]);
+        // Small errors give half the squared error
+        let pred = CalcNode::new_col_vector(vec![1.5, -1.2]);
+        let mut loss = huber(&pred, &truth, 1.);
+        let squares = least_squares(&pred, &truth).value_indexed(0);
+        assert_approx_eq!(loss.value_indexed(0), 0.5 * squares / 2.);
+        loss.back_propagation();
+        assert_approx_eq!(pred.gradient_indexed(0), 0.5 / 2.);
+        assert_approx_eq!(pred.gradient_indexed(1), -0.2 / 2.);
+        assert_approx_eq!(truth.gradient_indexed(0), -0.5 / 2.);
+
+        // Large errors grow like the absolute error
+        let pred = CalcNode::new_col_vector(vec![5., -7.]);
+        let mut loss = huber(&pred, &truth, 1.);
+        let mae = mean_absolute_error(&pred, &truth).value_indexed(0);
+        assert_approx_eq!(loss.value_indexed(0), mae - 0.5);
+        loss.back_propagation();
+        assert_approx_eq!(pred.gradient_indexed(0), 1. / 2.);
+        assert_approx_eq!(pred.gradient_indexed(1), -1. / 2.);
+    }
+
+    #[test]
+    fn neg_log_likelihood_is_finite_for_zero_probability() {
+        let prediction = CalcNode::new_col_vector(vec![1., 0., 0.]);
+        let truth = CalcNode::new_col_vector(vec![0., 1., 0.]);
+        let mut loss = neg_log_likelihood(&prediction, &truth);
+        assert_approx_eq!(loss.value_indexed(0), -NLL_EPSILON.ln());
+        loss.back_propagation();
+        assert!(prediction.copy_grad().iter().all(|g| g.is_finite()));
+    }
 }
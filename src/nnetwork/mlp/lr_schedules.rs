@@ -0,0 +1,117 @@
+use std::f64::consts::PI;
+
+use crate::nnetwork::FloatType;
+
+/// Decides the learning rate for every cycle of a training run.
+pub trait LrSchedule {
+    /// Returns the learning rate of the cycle, counting from zero, out of the total number of cycles.
+    fn lr(&self, cycle: usize, total: usize) -> FloatType;
+}
+
+// The fraction of the training run that has passed, from 0 at the first cycle to 1 at the last
+fn progress(cycle: usize, total: usize) -> FloatType {
+    if total < 2 {
+        0.
+    } else {
+        cycle as FloatType / (total - 1) as FloatType
+    }
+}
+
+/// The same learning rate for all cycles.
+pub struct Constant(pub FloatType);
+
+impl LrSchedule for Constant {
+    fn lr(&self, _cycle: usize, _total: usize) -> FloatType {
+        self.0
+    }
+}
+
+/// Goes from `start` to `end` with logarithmically spaced steps.
+pub struct LogSpaced {
+    pub start: FloatType,
+    pub end: FloatType,
+}
+
+impl LrSchedule for LogSpaced {
+    fn lr(&self, cycle: usize, total: usize) -> FloatType {
+        let log_start = self.start.ln();
+        let log_end = self.end.ln();
+        (log_start + (log_end - log_start) * progress(cycle, total)).exp()
+    }
+}
+
+/// Starts at `start` and is multiplied by `gamma` every `every` cycles.
+pub struct StepDecay {
+    pub start: FloatType,
+    pub gamma: FloatType,
+    pub every: usize,
+}
+
+impl LrSchedule for StepDecay {
+    fn lr(&self, cycle: usize, _total: usize) -> FloatType {
+        assert!(self.every > 0, "Step decay interval must be positive.");
+        self.start * self.gamma.powi((cycle / self.every) as i32)
+    }
+}
+
+/// Follows half a cosine period from `start` to `end`.
+pub struct CosineAnnealing {
+    pub start: FloatType,
+    pub end: FloatType,
+}
+
+impl LrSchedule for CosineAnnealing {
+    fn lr(&self, cycle: usize, total: usize) -> FloatType {
+        self.end + 0.5 * (self.start - self.end) * (1. + (PI * progress(cycle, total)).cos())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    const TOTAL: usize = 11;
+
+    #[test]
+    fn constant_schedule() {
+        let schedule = Constant(0.1);
+        assert_eq!(schedule.lr(0, TOTAL), 0.1);
+        assert_eq!(schedule.lr(5, TOTAL), 0.1);
+        assert_eq!(schedule.lr(10, TOTAL), 0.1);
+    }
+
+    #[test]
+    fn log_spaced_schedule() {
+        let schedule = LogSpaced {
+            start: 0.1,
+            end: 0.001,
+        };
+        assert_approx_eq!(schedule.lr(0, TOTAL), 0.1);
+        assert_approx_eq!(schedule.lr(5, TOTAL), 0.01);
+        assert_approx_eq!(schedule.lr(10, TOTAL), 0.001);
+    }
+
+    #[test]
+    fn step_decay_schedule() {
+        let schedule = StepDecay {
+            start: 0.1,
+            gamma: 0.5,
+            every: 4,
+        };
+        assert_approx_eq!(schedule.lr(0, TOTAL), 0.1);
+        assert_approx_eq!(schedule.lr(5, TOTAL), 0.05);
+        assert_approx_eq!(schedule.lr(10, TOTAL), 0.025);
+    }
+
+    #[test]
+    fn cosine_annealing_schedule() {
+        let schedule = CosineAnnealing {
+            start: 0.1,
+            end: 0.02,
+        };
+        assert_approx_eq!(schedule.lr(0, TOTAL), 0.1);
+        assert_approx_eq!(schedule.lr(5, TOTAL), 0.06);
+        assert_approx_eq!(schedule.lr(10, TOTAL), 0.02);
+    }
+}
@@ -1,8 +1,29 @@
+mod attention;
 mod layers;
 pub mod loss_functions;
+mod lr_schedules;
+mod model_archive;
 mod multilayer;
+mod optimizers;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod parameter_bundle;
+mod regularization;
+mod training_record;
 mod traits;
 
-pub use layers::{FunctionLayer, LinearLayer, ReshapeLayer};
+pub use attention::attention;
+pub use layers::{
+    DropoutLayer, EmbeddingLayer, FlattenLayer, FunctionLayer, InitScheme, LayerNormLayer,
+    LinearLayer, RecurrentLayer, ReshapeLayer, ResidualLayer,
+};
+pub use lr_schedules::{Constant, CosineAnnealing, LogSpaced, LrSchedule, StepDecay};
+pub use model_archive::{LayerDescription, ModelArchive};
 pub use multilayer::MultiLayer;
+pub use optimizers::{Adam, Optimizer, Sgd};
+#[cfg(feature = "rayon")]
+pub use parallel::ReplicaFactory;
+pub use parameter_bundle::ParameterBundle;
+pub use regularization::Regularization;
+pub use training_record::TrainingRecord;
 pub use traits::{Layer, Parameters};
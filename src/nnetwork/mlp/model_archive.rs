@@ -0,0 +1,230 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Write},
+};
+
+use crate::nnetwork::NodeShape;
+
+use super::{MultiLayer, ParameterBundle};
+
+/// Starts the manifest of files written by [ModelArchive::save], followed by the format version
+const MANIFEST_HEADER: &str = "NNMA";
+const FORMAT_VERSION: u32 = 1;
+/// Ends the manifest, the parameter data follows directly after
+const MANIFEST_END: &str = "end";
+
+/// The type, name and (optional) shape of a [Layer](super::Layer) as recorded in a [ModelArchive].
+#[derive(Clone, Debug, PartialEq)]
+pub struct LayerDescription {
+    pub layer_type: String,
+    pub name: String,
+    pub shape: Option<NodeShape>,
+}
+
+/// The architecture of a network together with the values of its parameters, so that the network can be rebuilt without knowing its setup in advance.
+///
+/// The architecture consists of named settings, e.g., the block size of a [ReText](crate::retext::ReText), from which the owner of the network rebuilds the [Layer](super::Layer)s, and a description of every layer used to check that the rebuilt network matches. The file starts with a small text manifest holding the architecture, one entry per line, followed by the parameters in the format of [ParameterBundle::export_binary].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModelArchive {
+    _settings: Vec<(String, usize)>,
+    _layers: Vec<LayerDescription>,
+    _parameters: ParameterBundle,
+}
+
+impl ModelArchive {
+    /// Records the layers and current parameter values of the network together with the settings. Setting names must not contain whitespace.
+    pub fn new(settings: &[(&str, usize)], mlp: &MultiLayer) -> Self {
+        ModelArchive {
+            _settings: settings
+                .iter()
+                .map(|(name, value)| {
+                    assert!(
+                        !name.is_empty() && !name.contains(char::is_whitespace),
+                        "Invalid setting name: '{name}'"
+                    );
+                    (name.to_string(), *value)
+                })
+                .collect(),
+            _layers: (0..mlp.len())
+                .map(|i| {
+                    let layer = mlp.get_layer(i);
+                    LayerDescription {
+                        layer_type: layer.layer_type().to_string(),
+                        name: layer.layer_name().to_string(),
+                        shape: layer.shape(),
+                    }
+                })
+                .collect(),
+            _parameters: mlp.get_parameter_bundle(),
+        }
+    }
+
+    /// Returns the value of the named setting, if recorded.
+    pub fn setting(&self, name: &str) -> Option<usize> {
+        self._settings
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| *value)
+    }
+
+    pub fn layers(&self) -> &[LayerDescription] {
+        &self._layers
+    }
+
+    pub fn parameters(&self) -> &ParameterBundle {
+        &self._parameters
+    }
+
+    /// Checks that the network has the recorded layers and loads the parameters into it. Nothing is changed if the network differs from the archive.
+    pub fn load_into(&self, mlp: &mut MultiLayer) -> Result<(), String> {
+        if mlp.len() != self._layers.len() {
+            return Err(format!(
+                "Archive describes {} layers but the network has {}.",
+                self._layers.len(),
+                mlp.len()
+            ));
+        }
+        for (n, description) in self._layers.iter().enumerate() {
+            let layer = mlp.get_layer(n);
+            if layer.layer_type() != description.layer_type || layer.shape() != description.shape
+            {
+                return Err(format!(
+                    "Layer {n} is a {} of shape {:?} but the archive describes a {} of shape {:?}.",
+                    layer.layer_type(),
+                    layer.shape(),
+                    description.layer_type,
+                    description.shape
+                ));
+            }
+        }
+        mlp.load_parameter_bundle(&self._parameters)
+    }
+
+    /// Writes the archive to a file. Will overwrite any existing file.
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{MANIFEST_HEADER} {FORMAT_VERSION}")?;
+        for (name, value) in &self._settings {
+            writeln!(writer, "setting {name} {value}")?;
+        }
+        for layer in &self._layers {
+            let shape = match layer.shape {
+                Some((rows, cols)) => format!("{rows}x{cols}"),
+                None => "-".to_string(),
+            };
+            writeln!(writer, "layer {} {shape} {}", layer.layer_type, layer.name)?;
+        }
+        writeln!(writer, "{MANIFEST_END}")?;
+        self._parameters.write_binary(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Reads an archive written by [ModelArchive::save].
+    pub fn load(path: &str) -> std::io::Result<Self> {
+        let invalid = |msg: String| Error::new(ErrorKind::InvalidData, msg);
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        if line.trim_end() != format!("{MANIFEST_HEADER} {FORMAT_VERSION}") {
+            return Err(invalid(format!(
+                "{path} is not a model archive of version {FORMAT_VERSION}."
+            )));
+        }
+
+        let mut settings = Vec::new();
+        let mut layers = Vec::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(invalid(format!("The manifest of {path} is not terminated.")));
+            }
+            let line = line.trim_end_matches('\n');
+            if line == MANIFEST_END {
+                break;
+            }
+            let bad_line = || invalid(format!("Cannot parse manifest line: '{line}'"));
+            match line.split_once(' ') {
+                Some(("setting", entry)) => {
+                    let (name, value) = entry.split_once(' ').ok_or_else(bad_line)?;
+                    let value = value.parse().map_err(|_| bad_line())?;
+                    settings.push((name.to_string(), value));
+                }
+                Some(("layer", entry)) => {
+                    let mut parts = entry.splitn(3, ' ');
+                    let (Some(layer_type), Some(shape), Some(name)) =
+                        (parts.next(), parts.next(), parts.next())
+                    else {
+                        return Err(bad_line());
+                    };
+                    let shape = match shape {
+                        "-" => None,
+                        _ => {
+                            let (rows, cols) = shape.split_once('x').ok_or_else(bad_line)?;
+                            Some((
+                                rows.parse().map_err(|_| bad_line())?,
+                                cols.parse().map_err(|_| bad_line())?,
+                            ))
+                        }
+                    };
+                    layers.push(LayerDescription {
+                        layer_type: layer_type.to_string(),
+                        name: name.to_string(),
+                        shape,
+                    });
+                }
+                _ => return Err(bad_line()),
+            }
+        }
+
+        // The parameters are read from memory so that their lengths can be checked against the size
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Ok(ModelArchive {
+            _settings: settings,
+            _layers: layers,
+            _parameters: ParameterBundle::read_binary(&mut bytes.as_slice(), path)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nnetwork::{CalcNode, FloatType, FunctionLayer, LinearLayer};
+
+    fn network(w: Vec<FloatType>) -> MultiLayer {
+        MultiLayer::new(vec![
+            Box::new(LinearLayer::from_nodes(
+                CalcNode::new_from_shape((2, 2), w),
+                Some(CalcNode::new_col_vector(vec![0.5, -0.5])),
+                "Linear layer",
+            )),
+            Box::new(FunctionLayer::new(&FunctionLayer::tanh, "tanh", "Non-linearity")),
+        ])
+    }
+
+    #[test]
+    fn archive_round_trip() {
+        let archive = ModelArchive::new(&[("width", 2)], &network(vec![1., 2., 3., 4.]));
+        let path = std::env::temp_dir()
+            .join(format!("archive_round_trip_{}.bin", std::process::id()))
+            .to_str()
+            .unwrap()
+            .to_string();
+        archive.save(&path).unwrap();
+        let loaded = ModelArchive::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded, archive);
+        assert_eq!(loaded.setting("width"), Some(2));
+        assert_eq!(loaded.setting("depth"), None);
+        assert_eq!(loaded.layers()[1].layer_type, "FunctionLayer");
+
+        let mut target = network(vec![0.; 4]);
+        loaded.load_into(&mut target).unwrap();
+        assert_eq!(target.get_parameter_bundle(), *loaded.parameters());
+        // A network with other layers is rejected
+        let mut wrong = MultiLayer::new(vec![Box::new(LinearLayer::new_rand(2, 2, true, "Only"))]);
+        assert!(loaded.load_into(&mut wrong).is_err());
+    }
+}
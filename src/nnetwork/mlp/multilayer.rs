@@ -1,29 +1,98 @@
 use std::{
     fmt::Display,
     fs::{read_to_string, File},
-    io::{Error, Write},
+    io::{BufWriter, Error, ErrorKind, Write},
 };
 
-use crate::nnetwork::{calc_node::FloatType, CalcNode, Layer, Parameters};
+use rand::{rngs::StdRng, SeedableRng};
 
-use super::loss_functions::{neg_log_likelihood, LossFuncType};
+use crate::nnetwork::{calc_node::FloatType, CalcNode, Layer, NodeShape, Parameters};
 
+use super::{
+    loss_functions::{neg_log_likelihood, LossFuncType},
+    Optimizer, ParameterBundle, Regularization, Sgd,
+};
+
+// A recorded forward calculation that can be replayed with new input values
+struct ForwardPlan {
+    _input: CalcNode,
+    _input_shape: NodeShape,
+    // Every node in the calculation in the order they are calculated, ending with the output
+    _nodes: Vec<CalcNode>,
+}
 
 /// This struct is just a stack of [Layer]s with some convenience attached.
 pub struct MultiLayer {
     _layers: Vec<Box<dyn Layer>>,
-    _regularization: Option<FloatType>,
+    _regularization: Regularization,
+    _grad_clip: Option<FloatType>,
     _loss_func: Box<LossFuncType>,
+    _optimizer: Box<dyn Optimizer>,
+    _forward_plan: Option<ForwardPlan>,
+    // Scales the learning rate of the parameters of each layer
+    _lr_multipliers: Vec<FloatType>,
+    // Frozen layers keep their parameters during training
+    _trainable: Vec<bool>,
+    _weight_decay: FloatType,
+    // The gradient and parameter norms of the last step, if tracked
+    _track_norms: bool,
+    _norms: Option<(FloatType, FloatType)>,
 }
 
 impl MultiLayer {
-    /// All [Layer]s are constructed beforehand and then put into the [MultiLayer].
+    /// All [Layer]s are constructed beforehand and then put into the [MultiLayer]. Panics if the output of a [Layer] does not fit the input of the next one, see [MultiLayer::validate_shapes].
     pub fn new(layers: Vec<Box<dyn Layer>>) -> Self {
-        MultiLayer {
+        Self::try_new(layers).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Same as [MultiLayer::new] but fails with the error of [MultiLayer::validate_shapes] instead of panicking.
+    pub fn try_new(layers: Vec<Box<dyn Layer>>) -> Result<Self, String> {
+        let mlp = MultiLayer {
+            _lr_multipliers: vec![1.; layers.len()],
+            _trainable: vec![true; layers.len()],
+            _weight_decay: 0.,
             _layers: layers,
-            _regularization: None,
+            _regularization: Regularization::None,
+            _grad_clip: None,
             _loss_func: Box::new(&neg_log_likelihood),
+            _optimizer: Box::new(Sgd::new()),
+            _forward_plan: None,
+            _track_norms: false,
+            _norms: None,
+        };
+        mlp.validate_shapes()?;
+        Ok(mlp)
+    }
+
+    /// Checks that the number of output rows of every [Layer] matches the number of input rows of the next [Layer] that requires a specific number, see [Layer::input_dim] and [Layer::output_dim]. [Layer]s that keep the rows of their input are skipped. The error names the two [Layer]s that do not fit together.
+    pub fn validate_shapes(&self) -> Result<(), String> {
+        // The number of rows passed on and the layer that decided it
+        let mut rows: Option<(usize, &dyn Layer)> = None;
+        for layer in &self._layers {
+            if let (Some(expected), Some((actual, prev))) = (layer.input_dim(), rows) {
+                if expected != actual {
+                    return Err(format!(
+                        "{} '{}' outputs {actual} rows but {} '{}' expects {expected}.",
+                        prev.layer_type(),
+                        prev.layer_name(),
+                        layer.layer_type(),
+                        layer.layer_name()
+                    ));
+                }
+            }
+            if let Some(out) = layer.output_dim() {
+                rows = Some((out, layer.as_ref()));
+            }
         }
+        Ok(())
+    }
+
+    /// Same as [MultiLayer::new] but reinitialises all parameters using a seeded random number generator, see [Layer::reinit]. Every [Layer] keeps its own way of initialising, e.g., its [InitScheme](super::InitScheme). Two networks with the same layers and seed will be identical.
+    pub fn new_seeded(layers: Vec<Box<dyn Layer>>, seed: u64) -> Self {
+        let mut mlp = Self::new(layers);
+        let mut rng = StdRng::seed_from_u64(seed);
+        mlp._layers.iter_mut().for_each(|l| l.reinit(&mut rng));
+        mlp
     }
 
     /// Use your own or one of the ones provided in the module [crate::nnetwork::mlp::loss_functions].
@@ -31,11 +100,93 @@ impl MultiLayer {
         self._loss_func = Box::new(f);
     }
     
-    /// Set to `Some(float)` to punish non-zero parameters.
-    pub fn set_regularization(&mut self, reg: Option<FloatType>) {
-        self._regularization = reg;
+    /// Replaces the [Optimizer] used to update the parameters. Defaults to [Sgd].
+    pub fn set_optimizer(&mut self, optimizer: Box<dyn Optimizer>) {
+        self._optimizer = optimizer;
+    }
+
+    /// Scales the learning rate used for the parameters of the layer with the given index, e.g., to let an embedding learn slower than the rest of the network. All layers start with a multiplier of one.
+    pub fn set_param_group_lr(&mut self, layer_index: usize, multiplier: FloatType) {
+        assert!(
+            layer_index < self._layers.len(),
+            "Layer index {layer_index} is out of bounds."
+        );
+        assert!(multiplier >= 0., "Learning rate multiplier cannot be negative.");
+        self._lr_multipliers[layer_index] = multiplier;
+    }
+
+    /// Freezes (`false`) or unfreezes (`true`) the parameters of the layer with the given index, e.g., to only fine-tune the last layers of a pretrained network. All layers start out trainable.
+    pub fn set_layer_trainable(&mut self, layer_index: usize, trainable: bool) {
+        assert!(
+            layer_index < self._layers.len(),
+            "Layer index {layer_index} is out of bounds."
+        );
+        self._trainable[layer_index] = trainable;
+    }
+
+    /// Uses [Sgd] with the given momentum as [Optimizer], replacing the current one. Any velocities accumulated so far are discarded.
+    pub fn set_momentum(&mut self, momentum: FloatType) {
+        self._optimizer = Box::new(Sgd::with_momentum(momentum));
+    }
+
+    /// Resets any state the [Optimizer] has accumulated, e.g., before a new training run.
+    pub fn reset_optimizer(&mut self) {
+        self._optimizer.reset();
+    }
+
+    /// Returns the state the [Optimizer] has accumulated for the parameters, see [Optimizer::save_state]. Store it with [ParameterBundle::set_optimizer_state] to continue training from a checkpoint without losing, e.g., the moments of [Adam](super::Adam).
+    pub fn save_optimizer_state(&self) -> Vec<u8> {
+        let params: Vec<&CalcNode> = self._layers.iter().flat_map(|l| l.param_iter()).collect();
+        self._optimizer.save_state(&params)
+    }
+
+    /// Restores a state returned by [MultiLayer::save_optimizer_state], possibly from another network with the same setup of parameters. The [Optimizer] must be of the same type as when the state was saved.
+    pub fn load_optimizer_state(&mut self, state: &[u8]) -> Result<(), String> {
+        let params: Vec<&CalcNode> = self._layers.iter().flat_map(|l| l.param_iter()).collect();
+        self._optimizer.load_state(&params, state)
+    }
+
+    /// Punishes non-zero parameters, see [Regularization]. `Some(float)` is accepted as [Regularization::L2].
+    pub fn set_regularization(&mut self, reg: impl Into<Regularization>) {
+        self._regularization = reg.into();
     }
     
+    /// Shrinks every parameter by `learning_rate * weight_decay * value` before each descent step. Unlike L2 [Regularization] the decay is not part of the loss, and is thus not rescaled by adaptive optimizers such as [crate::nnetwork::Adam]. Zero (default) disables it.
+    pub fn set_weight_decay(&mut self, weight_decay: FloatType) {
+        assert!(weight_decay >= 0., "Weight decay cannot be negative.");
+        self._weight_decay = weight_decay;
+    }
+
+    /// Set to `Some(float)` to limit the norm of the gradient, taken over all trainable parameters jointly, before every descent step. Frozen layers, see [MultiLayer::set_layer_trainable], neither count towards the norm nor are clipped.
+    pub fn set_grad_clip(&mut self, threshold: Option<FloatType>) {
+        if let Some(threshold) = threshold {
+            assert!(threshold > 0., "Gradient clipping threshold must be positive.");
+        }
+        self._grad_clip = threshold;
+    }
+
+    /// If true, the joint L2 norms of the gradients and of the values of all trainable parameters are computed at every descent step, see [MultiLayer::last_norms]. Off by default since it costs an extra pass over all parameters.
+    pub fn set_track_norms(&mut self, track: bool) {
+        self._track_norms = track;
+        if !track {
+            self._norms = None;
+        }
+    }
+
+    /// Returns the norm of the gradient, before any clipping, and the norm of the parameters after the update, both taken over all trainable parameters jointly, of the last descent step. Only available if enabled by [MultiLayer::set_track_norms].
+    pub fn last_norms(&self) -> Option<(FloatType, FloatType)> {
+        self._norms
+    }
+
+    /// Switches all [Layer]s between training and evaluation mode, e.g., to disable dropout when predicting.
+    pub fn set_training(&mut self, training: bool) {
+        // The recorded forward calculation may depend on the mode, e.g., for dropout
+        self._forward_plan = None;
+        self._layers
+            .iter_mut()
+            .for_each(|l| l.set_training(training));
+    }
+
     /// Returns the number of [Layer]s
     #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> usize {
@@ -48,6 +199,31 @@ impl MultiLayer {
         self._layers[i].as_ref()
     }
 
+    /// Returns the first layer with the given name, see [Layer::layer_name], or None if there is no such layer. Unlike [MultiLayer::get_layer] it keeps working when layers are inserted before it.
+    pub fn get_layer_by_name(&self, name: &str) -> Option<&dyn Layer> {
+        self._layers
+            .iter()
+            .find(|l| l.layer_name() == name)
+            .map(|l| l.as_ref())
+    }
+
+    /// Same as [MultiLayer::get_layer_by_name] but the layer can be modified, e.g., to switch it to evaluation mode.
+    pub fn get_layer_by_name_mut(&mut self, name: &str) -> Option<&mut dyn Layer> {
+        let layer = self._layers.iter_mut().find(|l| l.layer_name() == name)?;
+        Some(layer.as_mut())
+    }
+
+    /// Replaces the indexed layer and returns the old one, e.g., to swap in a resized layer. The learning rate multiplier and trainability of the index are kept. Panics if the index is invalid.
+    ///
+    /// The state of the [Optimizer] is reset since it may belong to parameters that are gone.
+    pub fn replace_layer(&mut self, i: usize, layer: Box<dyn Layer>) -> Box<dyn Layer> {
+        assert!(i < self._layers.len(), "Layer index {i} is out of bounds.");
+        // A recorded forward calculation refers to the parameters of the old layer
+        self._forward_plan = None;
+        self._optimizer.reset();
+        std::mem::replace(&mut self._layers[i], layer)
+    }
+
     /// Forwards the input through all [Layer]s and returns the final result.
     pub fn forward(&self, inp: &CalcNode) -> CalcNode {
         self._layers
@@ -55,51 +231,347 @@ impl MultiLayer {
             .fold(inp.clone(), |out, layer| layer.forward(&out))
     }
 
-    // Helps calculate the loss
-    fn calc_regularization(&self) -> CalcNode {
-        if let Some(regularization) = self._regularization {
-            if regularization <= 0. {
-                panic!("Regularization coefficient must be positive.");
-            }
-            let regularization = CalcNode::new_scalar(regularization);
-            let n_param = self.param_iter().count();
-            let n_param = CalcNode::new_scalar(n_param as FloatType);
-            // Mean of the sum of the squares of all parameters
-            let param = self.param_iter();
-            param
-                .map(|p| p.pow(&CalcNode::new_scalar(2.)).sum())
-                .sum::<CalcNode>()
-                * regularization
-                / n_param
-        } else {
-            CalcNode::new_scalar(0.)
+    /// Forgets the state of all stateful [Layer]s, see [Layer::is_stateful], so that the next call to [MultiLayer::forward] starts a new sequence. The training functions do this before every example.
+    pub fn reset_state(&self) {
+        self._layers.iter().for_each(|layer| layer.reset_state());
+    }
+
+    /// Same result as [MultiLayer::forward] but calculated in inference mode, see [CalcNode::without_gradients]. Nothing is stored for back propagation, which saves both memory and time when the network is only used for predictions.
+    pub fn forward_inference(&self, inp: &CalcNode) -> CalcNode {
+        CalcNode::without_gradients(|| self.forward(inp))
+    }
+
+    /// Same as [MultiLayer::forward] but also returns the output of every [Layer], e.g., to look for saturated non-linearities.
+    pub fn forward_with_activations(&self, inp: &CalcNode) -> (CalcNode, Vec<CalcNode>) {
+        let mut activations = Vec::with_capacity(self._layers.len());
+        let out = self._layers.iter().fold(inp.clone(), |out, layer| {
+            let out = layer.forward(&out);
+            activations.push(out.clone());
+            out
+        });
+        (out, activations)
+    }
+
+    /// Returns the derivative of every output element with respect to every input element, i.e., the Jacobian with one row per output element and one column per input element, both in the order of the values. The output of [MultiLayer::forward] is calculated once and back propagated once per output element. Overwrites the gradients of the parameters.
+    pub fn input_jacobian(&self, inp: &CalcNode) -> Vec<Vec<FloatType>> {
+        // A copy of the input so that the gradients of the caller's node are left alone
+        let inp = CalcNode::new_from_shape(inp.shape(), inp.copy_vals());
+        let out = self.forward(&inp);
+        (0..out.len())
+            .map(|i| {
+                let mut seed = vec![0.; out.len()];
+                seed[i] = 1.;
+                // Only the chosen output element contributes to the gradient
+                let mut selected = out
+                    .element_wise_mul(&CalcNode::new_from_shape(out.shape(), seed))
+                    .sum();
+                selected.back_propagation();
+                inp.copy_grad()
+            })
+            .collect()
+    }
+
+    /// Records the calculation made by [MultiLayer::forward] for inputs of the given shape, so that it can be replayed by [MultiLayer::forward_fast] without building a new graph. The recording is made with an input where the first row of every column is one and all others zero. Fails if any [Layer] uses an operation that cannot be replayed, e.g., a [DropoutLayer](super::DropoutLayer) in training mode, or if any [Layer] is stateful, see [Layer::is_stateful], since the recording would replay a frozen state.
+    pub fn compile_forward(&mut self, input_shape: NodeShape) -> Result<(), String> {
+        if let Some(layer) = self._layers.iter().find(|layer| layer.is_stateful()) {
+            return Err(format!(
+                "{} '{}' keeps a state between calls and cannot be replayed.",
+                layer.layer_type(),
+                layer.layer_name()
+            ));
         }
+        let (n_rows, n_cols) = input_shape;
+        let input = CalcNode::new_from_shape(
+            input_shape,
+            (0..n_rows * n_cols)
+                .map(|i| if i < n_cols { 1. } else { 0. })
+                .collect(),
+        );
+        let nodes = self.forward(&input).topologically_sorted();
+        if let Some(node) = nodes.iter().find(|node| !node.can_recalculate()) {
+            return Err(format!(
+                "Forward calculation contains an operation that cannot be replayed: {node}"
+            ));
+        }
+        self._forward_plan = Some(ForwardPlan {
+            _input: input,
+            _input_shape: input_shape,
+            _nodes: nodes,
+        });
+        Ok(())
     }
 
-    /// Calculates the average loss of the list of (prediction, truth) tuples.
-    pub fn loss(&self, inp: &[(CalcNode, CalcNode)]) -> CalcNode {
-        let loss = inp
+    /// The input shape of the calculation recorded by [MultiLayer::compile_forward], if there is one. The recording is dropped when the network is changed by [MultiLayer::replace_layer], [MultiLayer::set_training] or by loading parameters.
+    pub fn compiled_input_shape(&self) -> Option<NodeShape> {
+        self._forward_plan.as_ref().map(|plan| plan._input_shape)
+    }
+
+    /// Same result as [MultiLayer::forward] but replays the calculation recorded by [MultiLayer::compile_forward]. The output is detached from the calculation and cannot be used for back propagation. Panics if nothing has been recorded or the input has the wrong shape.
+    pub fn forward_fast(&self, inp: &CalcNode) -> CalcNode {
+        let plan = self
+            ._forward_plan
+            .as_ref()
+            .expect("Forward calculation must be compiled before it can be replayed.");
+        assert_eq!(
+            inp.shape(),
+            plan._input_shape,
+            "Input has a different shape than the compiled forward calculation."
+        );
+        plan._input.clone().set_vals(&inp.copy_vals());
+        for node in &plan._nodes {
+            node.clone().recalculate();
+        }
+        let out = plan._nodes.last().unwrap();
+        CalcNode::new_from_shape(out.shape(), out.copy_vals())
+    }
+
+    /// Sets the `fraction` of the weights with the smallest magnitudes to zero, e.g., to compress a trained network, and returns the number of weights pruned. The magnitudes are compared across all layers jointly. Biases are left untouched, see [MultiLayer::prune_including_biases]. Will panic if the fraction is not within [0,1].
+    pub fn prune(&mut self, fraction: FloatType) -> usize {
+        let weights: Vec<_> = self.weight_iter_mut().collect();
+        Self::prune_smallest(weights, fraction)
+    }
+
+    /// Same as [MultiLayer::prune] but the biases are pruned together with the weights.
+    pub fn prune_including_biases(&mut self, fraction: FloatType) -> usize {
+        let params: Vec<_> = self.param_iter_mut().collect();
+        Self::prune_smallest(params, fraction)
+    }
+
+    // Zeroes the fraction of all values with the smallest magnitudes. Ties are broken by the order of the parameters.
+    fn prune_smallest(mut params: Vec<&mut CalcNode>, fraction: FloatType) -> usize {
+        assert!(
+            (0. ..=1.).contains(&fraction),
+            "The fraction to prune must be within [0,1], not {fraction}."
+        );
+        let mut magnitudes: Vec<(FloatType, usize, usize)> = params
             .iter()
-            .map(|(inp, truth)| (self._loss_func)(&self.forward(inp), truth))
+            .enumerate()
+            .flat_map(|(p, node)| {
+                node.copy_vals()
+                    .into_iter()
+                    .enumerate()
+                    .map(move |(i, val)| (val.abs(), p, i))
+            })
+            .collect();
+        let n_pruned = (fraction * magnitudes.len() as FloatType).round() as usize;
+        magnitudes.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut indices = vec![Vec::new(); params.len()];
+        for &(_, p, i) in &magnitudes[..n_pruned] {
+            indices[p].push(i);
+        }
+        for (node, indices) in params.iter_mut().zip(indices) {
+            node.set_vals_at(&indices, &vec![0.; indices.len()]);
+        }
+        n_pruned
+    }
+
+    // Helps calculate the loss
+    pub(super) fn calc_regularization(&self) -> CalcNode {
+        self._regularization.penalty(self.param_iter())
+    }
+
+    /// The summed loss of all examples, without regularization. The state of stateful [Layer]s is reset before every example, so the examples do not depend on each other.
+    pub fn loss_sum(&self, inp: &[(CalcNode, CalcNode)]) -> CalcNode {
+        inp.iter()
+            .map(|(inp, truth)| {
+                self.reset_state();
+                (self._loss_func)(&self.forward(inp), truth)
+            })
             .sum::<CalcNode>()
-            * CalcNode::new_scalar(1. / inp.len() as FloatType);
+    }
+
+    /// Calculates the average loss of the list of (prediction, truth) tuples.
+    pub fn loss(&self, inp: &[(CalcNode, CalcNode)]) -> CalcNode {
+        let loss = self.loss_sum(inp) * CalcNode::new_scalar(1. / inp.len() as FloatType);
         let reg = self.calc_regularization();
         loss + reg
     }
 
+    /// Sets the gradients of all parameters to zero.
+    /// [MultiLayer::train] does this implicitly. Back propagation also clears the gradients of all nodes in the graph, intermediate ones included, so gradients never carry over between steps.
+    pub fn zero_grad(&mut self) {
+        self.param_iter_mut().for_each(|p| p.reset_grad());
+    }
+
     /// Trains the network on the supplied training data and returns the average loss.
+    ///
+    /// Equivalent to calling [MultiLayer::compute_loss], [MultiLayer::backward] and [MultiLayer::step] in sequence, which can be done instead to insert custom logic between them.
     pub fn train(&mut self, inp: &[(CalcNode, CalcNode)], learning_rate: FloatType) -> FloatType {
-        let mut loss = self.loss(inp);
+        let loss = self.compute_loss(inp);
+        self.descend_loss(loss, learning_rate)
+    }
+
+    /// Same as [MultiLayer::train] for a single example, e.g., for online learning, without having to collect it in a slice first.
+    pub fn train_one(
+        &mut self,
+        inp: &CalcNode,
+        truth: &CalcNode,
+        learning_rate: FloatType,
+    ) -> FloatType {
+        self.reset_state();
+        let loss = (self._loss_func)(&self.forward(inp), truth) + self.calc_regularization();
+        self.descend_loss(loss, learning_rate)
+    }
+
+    // Back propagates the loss and takes a step, returns the value of the loss
+    fn descend_loss(&mut self, mut loss: CalcNode, learning_rate: FloatType) -> FloatType {
+        self.backward(&mut loss);
+        self.step(learning_rate);
+
+        loss.value_indexed(0)
+    }
+
+    /// Same as [MultiLayer::loss], the first step of [MultiLayer::train].
+    pub fn compute_loss(&self, batch: &[(CalcNode, CalcNode)]) -> CalcNode {
+        self.loss(batch)
+    }
+
+    /// Calculates the gradients of all parameters with respect to the loss. Afterwards the gradients can be inspected using [Parameters::param_iter].
+    pub fn backward(&mut self, loss: &mut CalcNode) {
+        // Parameters that do not take part in the calculation would otherwise keep their old gradients
+        self.zero_grad();
         loss.back_propagation();
+    }
+
+    /// Updates the parameters according to their current gradients, see [MultiLayer::backward].
+    pub fn step(&mut self, learning_rate: FloatType) {
         self.decend_grad(learning_rate);
+    }
 
-        loss.value_indexed(0)
+    /// Trains the network on several micro-batches but only takes a single step, using the gradient averaged over all micro-batches. This gives the same update as training on all data at once (if the batches are of equal size) without building one large graph. Returns the average loss.
+    pub fn train_accumulated(
+        &mut self,
+        batches: &[&[(CalcNode, CalcNode)]],
+        learning_rate: FloatType,
+    ) -> FloatType {
+        assert!(!batches.is_empty(), "Need at least one batch to train on.");
+        // Back propagation starts by clearing all gradients so the sums are kept here
+        let mut grad_sums: Vec<Vec<FloatType>> =
+            self.param_iter().map(|p| vec![0.; p.len()]).collect();
+        let mut loss_sum = 0.;
+        for batch in batches {
+            let mut loss = self.loss(batch);
+            loss.back_propagation();
+            loss_sum += loss.value_indexed(0);
+            grad_sums.iter_mut().zip(self.param_iter()).for_each(|(sum, p)| {
+                sum.iter_mut()
+                    .zip(p.borrow().grad())
+                    .for_each(|(s, g)| *s += g)
+            });
+        }
+        let scale = 1. / batches.len() as FloatType;
+        grad_sums
+            .iter()
+            .zip(self.param_iter_mut())
+            .for_each(|(sum, p)| p.set_grad(&sum.iter().map(|g| g * scale).collect::<Vec<_>>()));
+        self.decend_grad(learning_rate);
+
+        loss_sum * scale
     }
 
-    /// Lets every parameter decend its respective gradient.
-    fn decend_grad(&mut self, learning_rate: FloatType) {
-        self.param_iter_mut()
-            .for_each(|p| p.decend_grad(learning_rate));
+    // The parameters of all layers that are not frozen
+    fn trainable_params(&self) -> impl Iterator<Item = &CalcNode> {
+        self._layers
+            .iter()
+            .zip(&self._trainable)
+            .filter(|(_, &trainable)| trainable)
+            .flat_map(|(layer, _)| layer.param_iter())
+    }
+
+    // The L2 norm of the gradients of all trainable parameters combined
+    fn grad_norm(&self) -> FloatType {
+        self.trainable_params()
+            .map(|p| p.borrow().grad().iter().map(|g| g * g).sum::<FloatType>())
+            .sum::<FloatType>()
+            .sqrt()
+    }
+
+    // The L2 norm of the values of all trainable parameters combined
+    fn param_norm(&self) -> FloatType {
+        self.trainable_params()
+            .map(|p| p.borrow().vals().iter().map(|v| v * v).sum::<FloatType>())
+            .sum::<FloatType>()
+            .sqrt()
+    }
+
+    // Scales all gradients equally so that their joint norm does not exceed the threshold
+    fn clip_grad(&mut self) {
+        if let Some(threshold) = self._grad_clip {
+            let norm = self.grad_norm();
+            if norm > threshold {
+                let scale = threshold / norm;
+                // Gradients of frozen layers are never used, so they are left alone
+                let layers = self._layers.iter_mut().zip(&self._trainable);
+                for (layer, _) in layers.filter(|(_, &trainable)| trainable) {
+                    layer.param_iter_mut().for_each(|p| {
+                        let grad: Vec<_> = p.borrow().grad().iter().map(|g| g * scale).collect();
+                        p.set_grad(&grad);
+                    });
+                }
+            }
+        }
+    }
+
+    /// Lets the [Optimizer] update every parameter according to its respective gradient.
+    pub(super) fn decend_grad(&mut self, learning_rate: FloatType) {
+        let grad_norm = if self._track_norms {
+            Some(self.grad_norm())
+        } else {
+            None
+        };
+        self.clip_grad();
+        // Every layer is a parameter group with its own learning rate
+        let groups = self._layers.iter_mut().zip(&self._lr_multipliers).zip(&self._trainable);
+        for ((layer, multiplier), _) in groups.filter(|(_, &trainable)| trainable) {
+            let learning_rate = learning_rate * multiplier;
+            if self._weight_decay > 0. {
+                let decay = 1. - learning_rate * self._weight_decay;
+                layer.param_iter_mut().for_each(|p| {
+                    let vals: Vec<_> = p.copy_vals().iter().map(|v| v * decay).collect();
+                    p.set_vals(&vals);
+                });
+            }
+            let mut params = layer.param_iter_mut();
+            self._optimizer.step(&mut params, learning_rate);
+        }
+        self._norms = grad_norm.map(|grad_norm| (grad_norm, self.param_norm()));
+    }
+
+    /// Returns a table of all layers with their names, types, shapes (if they have one) and number of parameters, followed by the total number of parameters. Unlike the [Display] implementation no parameter values are included.
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "{:<24} {:<16} {:<12} {:>10}\n",
+            "Layer", "Type", "Shape", "Parameters"
+        );
+        let mut total = 0;
+        for layer in &self._layers {
+            let n_param: usize = layer.param_iter().map(|p| p.len()).sum();
+            total += n_param;
+            let shape = match layer.shape() {
+                Some((rows, cols)) => format!("({rows}, {cols})"),
+                None => "-".to_string(),
+            };
+            summary += &format!(
+                "{:<24} {:<16} {:<12} {:>10}\n",
+                layer.layer_name(),
+                layer.layer_type(),
+                shape,
+                n_param
+            );
+        }
+        summary += &format!("Total parameters: {total}\n");
+        summary
+    }
+
+    /// Returns a copy of the current values of all parameters.
+    pub fn get_parameter_bundle(&self) -> ParameterBundle {
+        ParameterBundle::from_layers(self._layers.iter().map(|l| l.as_ref()))
+    }
+
+    /// Overwrites all parameters with the values from the bundle. Fails without changing anything if the network does not have the same setup of parameters.
+    pub fn load_parameter_bundle(&mut self, bundle: &ParameterBundle) -> Result<(), String> {
+        self._forward_plan = None;
+        bundle.load_into(self._layers.iter_mut())
     }
 
     /// Exports all parameters to a text file.
@@ -108,72 +580,115 @@ impl MultiLayer {
     /// 
     /// Adds a numerical suffix if the wanted filename is taken.
     pub fn export_parameters(&self, filename: &str) -> std::io::Result<String> {
+        self.export_with(filename, false)
+    }
+
+    /// Same as [MultiLayer::export_parameters] but replaces the file if it exists, e.g., to keep a single checkpoint up to date.
+    pub fn export_parameters_overwrite(&self, filename: &str) -> std::io::Result<String> {
+        self.export_with(filename, true)
+    }
+
+    /// Exports all parameters to a text file. If `overwrite` is false a numerical suffix is added when the wanted filename is taken, otherwise the file is replaced.
+    pub fn export_with(&self, filename: &str, overwrite: bool) -> std::io::Result<String> {
         let mut fn_string = filename.to_string();
         let mut counter: usize = 0;
-        let mut file = loop {
-            let file = File::create_new(&fn_string);
-            match file {
-                Ok(file) => {
-                    if counter > 0 {
-                        eprintln!("Changing export filename to; {fn_string}");
+        let file = if overwrite {
+            File::create(&fn_string)?
+        } else {
+            loop {
+                let file = File::create_new(&fn_string);
+                match file {
+                    Ok(file) => {
+                        if counter > 0 {
+                            eprintln!("Changing export filename to; {fn_string}");
+                        }
+                        break file;
                     }
-                    break file;
+                    Err(err) => match err.kind() {
+                        std::io::ErrorKind::AlreadyExists => (),
+                        _ => {
+                            eprintln!("Export parameters failed: {}", err)
+                        }
+                    },
                 }
-                Err(err) => match err.kind() {
-                    std::io::ErrorKind::AlreadyExists => (),
-                    _ => {
-                        eprintln!("Export parameters failed: {}", err)
-                    }
-                },
+                fn_string = filename.to_string() + "." + &counter.to_string();
+                counter += 1;
             }
-            fn_string = filename.to_string() + "." + &counter.to_string();
-            counter += 1;
         };
+        // Every value is a separate line, so the writes must be buffered
+        let mut file = BufWriter::new(file);
         for (n, param) in self.param_iter().enumerate() {
             writeln!(file, "Parameter BEGIN: {n}")?;
+            let (rows, cols) = param.shape();
+            writeln!(file, "Shape: {rows} {cols}")?;
             for i in 0..param.len() {
                 writeln!(file, "{}", param.value_indexed(i))?;
             }
             writeln!(file, "Parameter END: {n}")?;
         }
+        file.flush()?;
         Ok(fn_string)
     }
 
-    /// Imports previously exported parameters. Expect errors if the current network does not have the same setup of layers.
+    /// Imports previously exported parameters. Expect errors if the current network does not have the same setup of layers. The whole file is parsed and checked against the network before any parameter is changed, so a corrupt or mismatching file leaves the network untouched.
     pub fn import_parameters(&mut self, filename: &str) -> Result<(), Error> {
+        let invalid = |msg: String| Error::new(ErrorKind::InvalidData, msg);
+        let content = read_to_string(filename)?;
+        // Files from older versions do not record the shape, then only the length is checked
+        let mut imported: Vec<(Option<NodeShape>, Vec<FloatType>)> = Vec::new();
         let mut param_vals: Vec<FloatType> = Vec::new();
-        let file_content = read_to_string(filename);
-        match file_content {
-            Ok(content) => {
-                let mut imported_parameters = 0;
-                let target_parameters = self.param_iter().count();
-                let mut target_iter = self.param_iter_mut();
-                for line in content.lines() {
-                    if line.starts_with("Parameter BEGIN") {
-                        // Do nothing
-                    } else if line.starts_with("Parameter END") {
-                        if let Some(target) = target_iter.next() {
-                            imported_parameters += 1;
-                            assert_eq!(
-                                target.len(),
-                                param_vals.len(),
-                                "Wrong size of parameter {} from file.",
-                                imported_parameters
-                            );
-                            target.set_vals(&param_vals);
-                        }
-                        param_vals.clear();
-                    } else {
-                        param_vals.push(line.parse().unwrap())
-                    }
+        let mut param_shape: Option<NodeShape> = None;
+        for line in content.lines() {
+            if line.starts_with("Parameter BEGIN") {
+                // Do nothing
+            } else if let Some(shape) = line.strip_prefix("Shape:") {
+                let dims: Vec<usize> = shape
+                    .split_whitespace()
+                    .map(|d| d.parse().map_err(|e| Error::new(ErrorKind::InvalidData, e)))
+                    .collect::<Result<_, _>>()?;
+                if dims.len() != 2 {
+                    return Err(invalid(format!("Invalid shape in parameter file: {line}")));
                 }
-                if imported_parameters < target_parameters {
-                    eprintln!("Parameter file contained too few parameters, only the first {imported_parameters} were set.");
+                param_shape = Some((dims[0], dims[1]));
+            } else if line.starts_with("Parameter END") {
+                imported.push((param_shape.take(), std::mem::take(&mut param_vals)));
+            } else {
+                let val = line
+                    .parse()
+                    .map_err(|_| invalid(format!("Invalid value in parameter file: {line}")))?;
+                param_vals.push(val);
+            }
+        }
+        for (n, ((shape, vals), target)) in imported.iter().zip(self.param_iter()).enumerate() {
+            let n = n + 1;
+            if let Some(shape) = shape {
+                if *shape != target.shape() {
+                    return Err(invalid(format!(
+                        "Parameter {n} from file has shape {shape:?} but the network expects {:?}.",
+                        target.shape()
+                    )));
                 }
-                Ok(())
             }
-            Err(err) => Err(err),
+            if vals.len() != target.len() {
+                return Err(invalid(format!(
+                    "Parameter {n} from file has {} values but the network expects {}.",
+                    vals.len(),
+                    target.len()
+                )));
+            }
+        }
+        let target_parameters = self.param_iter().count();
+        if imported.len() < target_parameters {
+            eprintln!(
+                "Parameter file contained too few parameters, only the first {} were set.",
+                imported.len()
+            );
         }
+        self._forward_plan = None;
+        self.param_iter_mut()
+            .zip(&imported)
+            .for_each(|(target, (_, vals))| target.set_vals(vals));
+        Ok(())
     }
 }
 
@@ -194,4 +709,632 @@ impl Parameters for MultiLayer {
     fn param_iter_mut(&mut self) -> Box<dyn Iterator<Item = &mut CalcNode> + '_> {
         Box::new(self._layers.iter_mut().flat_map(|l| l.param_iter_mut()))
     }
+    fn weight_iter_mut(&mut self) -> Box<dyn Iterator<Item = &mut CalcNode> + '_> {
+        Box::new(self._layers.iter_mut().flat_map(|l| l.weight_iter_mut()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nnetwork::{
+        loss_functions::least_squares, EmbeddingLayer, FlattenLayer, FunctionLayer, InitScheme,
+        Adam, LayerNormLayer, LinearLayer, RecurrentLayer,
+    };
+    use assert_approx_eq::assert_approx_eq;
+
+    fn two_layer_network() -> MultiLayer {
+        let mut mlp = MultiLayer::new(vec![
+            Box::new(LinearLayer::from_nodes(
+                CalcNode::new_from_shape((2, 2), vec![1., 2., 3., 4.]),
+                Some(CalcNode::new_col_vector(vec![0.5, -0.5])),
+                "Layer 1",
+            )),
+            Box::new(FunctionLayer::new(&FunctionLayer::tanh, "tanh", "Non-linearity")),
+            Box::new(LinearLayer::from_nodes(
+                CalcNode::new_from_shape((1, 2), vec![-1., 2.]),
+                Some(CalcNode::new_scalar(0.)),
+                "Layer 2",
+            )),
+        ]);
+        mlp.set_loss_function(&least_squares);
+        mlp
+    }
+
+    #[test]
+    fn parameter_bundle_round_trip() {
+        let mut mlp = two_layer_network();
+        let bundle = mlp.get_parameter_bundle();
+        assert_eq!(bundle.len(), 2);
+        assert_eq!(bundle.layer_names().collect::<Vec<_>>(), &["Layer 1", "Layer 2"]);
+        assert_eq!(bundle.layer_parameters(1), &[vec![-1., 2.], vec![0.]]);
+        mlp.param_iter_mut().for_each(|p| p.set_vals(&vec![0.; p.len()]));
+        assert_ne!(mlp.get_parameter_bundle(), bundle);
+        mlp.load_parameter_bundle(&bundle).unwrap();
+        assert_eq!(mlp.get_parameter_bundle(), bundle);
+    }
+
+    #[test]
+    fn accumulated_micro_batches_equal_full_batch() {
+        let data: Vec<(CalcNode, CalcNode)> = [[0.5, -1.], [1., 2.], [-0.3, 0.2], [2., -2.]]
+            .iter()
+            .zip([0.1, -0.4, 0.9, 0.3])
+            .map(|(x, y)| (CalcNode::new_col_vector(x.to_vec()), CalcNode::new_scalar(y)))
+            .collect();
+        let mut full = two_layer_network();
+        let full_loss = full.train(&data, 0.1);
+        let mut accumulated = two_layer_network();
+        let micro_batches: Vec<&[(CalcNode, CalcNode)]> = data.chunks(1).collect();
+        let accumulated_loss = accumulated.train_accumulated(&micro_batches, 0.1);
+        assert_approx_eq!(full_loss, accumulated_loss);
+        for (a, b) in full.param_iter().zip(accumulated.param_iter()) {
+            for (x, y) in a.copy_vals().iter().zip(b.copy_vals()) {
+                assert_approx_eq!(x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn gradients_do_not_carry_over_between_steps() {
+        let first = [(CalcNode::new_col_vector(vec![1., -1.]), CalcNode::new_scalar(0.5))];
+        let second = [(CalcNode::new_col_vector(vec![0.2, 0.3]), CalcNode::new_scalar(-1.))];
+        let mut mlp = two_layer_network();
+        mlp.train(&first, 0.1);
+        let mut fresh = two_layer_network();
+        fresh
+            .load_parameter_bundle(&mlp.get_parameter_bundle())
+            .unwrap();
+        mlp.train(&second, 0.1);
+        fresh.train(&second, 0.1);
+        for (a, b) in mlp.param_iter().zip(fresh.param_iter()) {
+            assert_eq!(a.copy_grad(), b.copy_grad());
+        }
+    }
+
+    #[test]
+    fn jacobian_of_linear_layer_is_the_weights() {
+        let weights = vec![1., -2., 0.5, 3., 0., -1.];
+        let mlp = MultiLayer::new(vec![Box::new(LinearLayer::from_nodes(
+            CalcNode::new_from_shape((2, 3), weights.clone()),
+            Some(CalcNode::new_col_vector(vec![0.5, -0.5])),
+            "Layer",
+        ))]);
+        let inp = CalcNode::new_col_vector(vec![0.2, -0.7, 1.5]);
+        let jacobian = mlp.input_jacobian(&inp);
+        assert_eq!(jacobian, vec![weights[..3].to_vec(), weights[3..].to_vec()]);
+        // The gradients of the input are left untouched, i.e., never calculated
+        assert!(inp.copy_grad().iter().all(|g| g.is_nan()));
+    }
+
+    #[test]
+    fn layers_are_found_by_name() {
+        let mut mlp = MultiLayer::new(vec![
+            Box::new(EmbeddingLayer::new_rand(2, 4, "Embedding layer")),
+            Box::new(FlattenLayer::new((2, 3), "Flattening layer")),
+            Box::new(LinearLayer::new_rand(5, 6, true, "Output layer")),
+        ]);
+        let embedding = mlp.get_layer_by_name("Embedding layer").unwrap();
+        assert!(std::ptr::addr_eq(embedding, mlp.get_layer(0)));
+        assert_eq!(embedding.layer_type(), "EmbeddingLayer");
+        assert_eq!(mlp.get_layer_by_name("Output layer").unwrap().output_dim(), Some(5));
+        assert!(mlp.get_layer_by_name("Hidden layer").is_none());
+
+        let output = mlp.get_layer_by_name_mut("Output layer").unwrap();
+        output.param_iter_mut().for_each(|p| p.set_vals(&vec![0.; p.len()]));
+        assert!(mlp.get_layer(2).param_iter().all(|p| p.copy_vals().iter().all(|&v| v == 0.)));
+    }
+
+    #[test]
+    fn replayed_forward_equals_forward() {
+        let mut mlp = two_layer_network();
+        mlp.compile_forward((2, 1)).unwrap();
+        for inp in [vec![1., -1.], vec![0.3, 0.2], vec![-2., 0.5]] {
+            let inp = CalcNode::new_col_vector(inp);
+            assert_eq!(mlp.forward_fast(&inp).copy_vals(), mlp.forward(&inp).copy_vals());
+        }
+    }
+
+    #[test]
+    fn normalised_softmax_network_can_be_replayed() {
+        let mut mlp = MultiLayer::new(vec![
+            Box::new(LinearLayer::new_rand(3, 2, true, "Layer 1")),
+            Box::new(LayerNormLayer::new(3, "Layer norm")),
+            Box::new(FunctionLayer::new(&FunctionLayer::softmax, "softmax", "Softmax")),
+        ]);
+        mlp.compile_forward((2, 1)).unwrap();
+        for inp in [vec![1., -1.], vec![0.3, 0.2], vec![-2., 0.5]] {
+            let inp = CalcNode::new_col_vector(inp);
+            let expected = mlp.forward(&inp).copy_vals();
+            for (fast, slow) in mlp.forward_fast(&inp).copy_vals().iter().zip(expected) {
+                assert_approx_eq!(fast, slow);
+            }
+        }
+    }
+
+    #[test]
+    fn recorded_forward_is_dropped_when_network_changes() {
+        let mut mlp = two_layer_network();
+        mlp.compile_forward((2, 1)).unwrap();
+        assert_eq!(mlp.compiled_input_shape(), Some((2, 1)));
+        mlp.replace_layer(1, Box::new(FunctionLayer::new(&FunctionLayer::tanh, "tanh", "tanh")));
+        assert_eq!(mlp.compiled_input_shape(), None);
+        mlp.compile_forward((2, 1)).unwrap();
+        mlp.set_training(false);
+        assert_eq!(mlp.compiled_input_shape(), None);
+    }
+
+    fn recurrent_network() -> MultiLayer {
+        let mut mlp = MultiLayer::new(vec![
+            Box::new(RecurrentLayer::new_rand(3, 2, "Recurrent")),
+            Box::new(LinearLayer::new_rand(1, 3, true, "Output")),
+        ]);
+        mlp.set_loss_function(&least_squares);
+        mlp
+    }
+
+    #[test]
+    fn stateful_layers_cannot_be_recorded() {
+        let mut mlp = recurrent_network();
+        let err = mlp.compile_forward((2, 1)).unwrap_err();
+        assert!(err.contains("Recurrent"), "{err}");
+        assert_eq!(mlp.compiled_input_shape(), None);
+        // The state was not advanced by the failed attempt
+        let inp = CalcNode::new_col_vector(vec![1., -1.]);
+        let first = mlp.forward(&inp).value_indexed(0);
+        mlp.reset_state();
+        assert_eq!(mlp.forward(&inp).value_indexed(0), first);
+    }
+
+    #[test]
+    fn state_is_reset_before_every_training_example() {
+        let mlp = recurrent_network();
+        let example = (CalcNode::new_col_vector(vec![1., -1.]), CalcNode::new_scalar(0.5));
+        let single = mlp.loss_sum(std::slice::from_ref(&example)).value_indexed(0);
+        let repeated = mlp.loss_sum(&[example.clone(), example.clone()]).value_indexed(0);
+        assert_approx_eq!(repeated, 2. * single);
+        // A state left from earlier calls does not leak into the loss either
+        mlp.forward(&example.0);
+        assert_eq!(mlp.loss_sum(&[example]).value_indexed(0), single);
+    }
+
+    #[test]
+    fn seeded_networks_are_identical() {
+        let build = || {
+            let mut mlp = MultiLayer::new_seeded(
+                vec![
+                    Box::new(LinearLayer::new_rand(3, 2, true, "Layer 1")),
+                    Box::new(FunctionLayer::new(
+                        &FunctionLayer::sigmoid,
+                        "Sigmoid",
+                        "Non-linearity",
+                    )),
+                    Box::new(LinearLayer::new_rand(1, 3, true, "Layer 2")),
+                ],
+                42,
+            );
+            mlp.set_loss_function(&least_squares);
+            mlp
+        };
+        let mut mlp1 = build();
+        let mut mlp2 = build();
+        let data = vec![
+            (CalcNode::new_col_vector(vec![0.1, 0.2]), CalcNode::new_scalar(1.)),
+            (CalcNode::new_col_vector(vec![-0.3, 0.4]), CalcNode::new_scalar(0.)),
+        ];
+        for _ in 0..3 {
+            mlp1.train(&data, 0.1);
+            mlp2.train(&data, 0.1);
+        }
+        let params1: Vec<_> = mlp1.param_iter().flat_map(|p| p.copy_vals()).collect();
+        let params2: Vec<_> = mlp2.param_iter().flat_map(|p| p.copy_vals()).collect();
+        assert_eq!(params1, params2);
+    }
+
+    #[test]
+    fn gradient_clipping_bounds_update() {
+        const LEARNING_RATE: FloatType = 0.1;
+        const CLIP: FloatType = 1.;
+        let mut mlp = two_layer_network();
+        mlp.set_grad_clip(Some(CLIP));
+        let data = vec![(
+            CalcNode::new_col_vector(vec![1e3, -1e3]),
+            CalcNode::new_scalar(1e4),
+        )];
+        let before: Vec<_> = mlp.param_iter().flat_map(|p| p.copy_vals()).collect();
+        mlp.train(&data, LEARNING_RATE);
+        let after: Vec<_> = mlp.param_iter().flat_map(|p| p.copy_vals()).collect();
+        let update_norm = before
+            .iter()
+            .zip(after.iter())
+            .map(|(b, a)| (b - a).powi(2))
+            .sum::<FloatType>()
+            .sqrt();
+        assert!(update_norm > 0.);
+        assert!(update_norm <= LEARNING_RATE * CLIP + 1e-12);
+    }
+
+    #[test]
+    fn gradient_clipping_ignores_frozen_layers() {
+        const LEARNING_RATE: FloatType = 0.1;
+        let data = vec![(CalcNode::new_col_vector(vec![1., -1.]), CalcNode::new_scalar(3.))];
+        let last_layer_update = |clip: Option<FloatType>| {
+            let mut mlp = two_layer_network();
+            mlp.set_layer_trainable(0, false);
+            mlp.set_grad_clip(clip);
+            let before = mlp.get_parameter_bundle();
+            mlp.train(&data, LEARNING_RATE);
+            let after = mlp.get_parameter_bundle();
+            // The bundle skips the parameterless tanh layer
+            assert_eq!(before.layer_parameters(0), after.layer_parameters(0));
+            after.layer_parameters(1).to_vec()
+        };
+        // The gradient of the last layer alone is below the threshold, the joint one is not
+        let mlp = two_layer_network();
+        mlp.loss(&data).back_propagation();
+        let norm_of = |layer: &dyn Layer| {
+            layer
+                .param_iter()
+                .flat_map(|p| p.copy_grad())
+                .map(|g| g * g)
+                .sum::<FloatType>()
+                .sqrt()
+        };
+        let norm_first = norm_of(mlp._layers[0].as_ref());
+        let norm_last = norm_of(mlp._layers[2].as_ref());
+        let clip = 1.1 * norm_last;
+        assert!(norm_first.hypot(norm_last) > clip);
+        assert_eq!(last_layer_update(Some(clip)), last_layer_update(None));
+    }
+
+    #[test]
+    fn l1_shrinks_small_parameters_faster_than_l2() {
+        // The data gives no gradient, so only the regularization moves the parameters
+        let final_weights = |reg: Regularization| {
+            let mut mlp = MultiLayer::new(vec![Box::new(LinearLayer::from_nodes(
+                CalcNode::new_from_shape((1, 2), vec![0.05, 1.]),
+                None,
+                "Layer",
+            ))]);
+            mlp.set_loss_function(&least_squares);
+            mlp.set_regularization(reg);
+            let data = vec![(CalcNode::new_col_vector(vec![0., 0.]), CalcNode::new_scalar(0.))];
+            for _ in 0..4 {
+                mlp.train(&data, 0.1);
+            }
+            let weights = mlp.param_iter().next().unwrap().copy_vals();
+            weights
+        };
+        let l1 = final_weights(Regularization::L1(0.1));
+        let l2 = final_weights(Regularization::L2(0.1));
+        assert!(l1[0].abs() < l2[0].abs());
+        assert_approx_eq!(l1[0], 0.01);
+        assert!(l2[0] > 0.04);
+        // Passing an Option still selects L2
+        assert_eq!(Regularization::from(Some(0.1)), Regularization::L2(0.1));
+    }
+
+    #[test]
+    fn seeded_layers_keep_their_initialisation() {
+        let mlp = MultiLayer::new_seeded(
+            vec![
+                Box::new(LinearLayer::new_rand_with_init(3, 2, true, "Layer", InitScheme::Xavier)),
+                Box::new(LayerNormLayer::new(3, "Normalisation")),
+            ],
+            42,
+        );
+        let params: Vec<_> = mlp.param_iter().map(|p| p.copy_vals()).collect();
+        assert!(params[0].iter().all(|&w| w != 0.));
+        assert_eq!(params[1], vec![0.; 3]);
+        assert_eq!(params[2], vec![1.; 3]);
+        assert_eq!(params[3], vec![0.; 3]);
+    }
+
+    #[test]
+    fn replaced_layer_starts_without_optimizer_state() {
+        let mut mlp = two_layer_network();
+        mlp.set_optimizer(Box::new(Adam::default()));
+        let data = vec![(CalcNode::new_col_vector(vec![0.5, -1.]), CalcNode::new_scalar(2.))];
+        mlp.train(&data, 0.1);
+        let fresh_state = |mlp: &MultiLayer| {
+            let params: Vec<&CalcNode> = mlp.param_iter().collect();
+            Adam::default().save_state(&params)
+        };
+        assert_ne!(mlp.save_optimizer_state(), fresh_state(&mlp));
+
+        let resized = LinearLayer::from_nodes(
+            CalcNode::new_from_shape((1, 2), vec![1., 1.]),
+            None,
+            "Layer 2",
+        );
+        mlp.replace_layer(2, Box::new(resized));
+        assert_eq!(mlp.save_optimizer_state(), fresh_state(&mlp));
+        mlp.train(&data, 0.1);
+    }
+
+    #[test]
+    fn overwriting_export_replaces_file() {
+        let dir = std::env::temp_dir().join(format!("overwrite_export_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.param").to_str().unwrap().to_string();
+        let first = two_layer_network();
+        let second = MultiLayer::new_seeded(
+            vec![
+                Box::new(LinearLayer::new_rand(2, 2, true, "Layer 1")),
+                Box::new(FunctionLayer::new(&FunctionLayer::tanh, "tanh", "Non-linearity")),
+                Box::new(LinearLayer::new_rand(1, 2, true, "Layer 2")),
+            ],
+            7,
+        );
+        assert_eq!(first.export_parameters_overwrite(&path).unwrap(), path);
+        assert_eq!(second.export_parameters_overwrite(&path).unwrap(), path);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        let mut imported = two_layer_network();
+        imported.import_parameters(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(imported.get_parameter_bundle(), second.get_parameter_bundle());
+    }
+
+    #[test]
+    fn corrupt_parameter_file_leaves_network_untouched() {
+        let dir = std::env::temp_dir().join(format!("corrupt_import_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.param").to_str().unwrap().to_string();
+        let source = MultiLayer::new_seeded(
+            vec![
+                Box::new(LinearLayer::new_rand(2, 2, true, "Layer 1")),
+                Box::new(FunctionLayer::new(&FunctionLayer::tanh, "tanh", "Non-linearity")),
+                Box::new(LinearLayer::new_rand(1, 2, true, "Layer 2")),
+            ],
+            7,
+        );
+        source.export_parameters_overwrite(&path).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        let mut lines: Vec<_> = content.lines().collect();
+        let mut imported = two_layer_network();
+        let original = imported.get_parameter_bundle();
+        // The last parameter misses a value
+        let last_end = lines.iter().rposition(|l| l.starts_with("Parameter END")).unwrap();
+        lines.remove(last_end - 1);
+        std::fs::write(&path, lines.join("\n")).unwrap();
+        let err = imported.import_parameters(&path).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(imported.get_parameter_bundle(), original);
+        // A value that is not a number
+        let mut lines: Vec<_> = content.lines().collect();
+        let first_shape = lines.iter().position(|l| l.starts_with("Shape:")).unwrap();
+        lines[first_shape + 1] = "not a number";
+        std::fs::write(&path, lines.join("\n")).unwrap();
+        let err = imported.import_parameters(&path).unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(imported.get_parameter_bundle(), original);
+    }
+
+    #[test]
+    fn learning_rate_multiplier_scales_layer_update() {
+        // Both layers see the gradient 1 for their single parameter
+        let new_layer = |label| LinearLayer::from_nodes(CalcNode::new_scalar(1.), None, label);
+        let mut mlp = MultiLayer::new(vec![
+            Box::new(new_layer("Layer 1")),
+            Box::new(new_layer("Layer 2")),
+        ]);
+        mlp.set_loss_function(&|inp, _truth| inp.clone());
+        mlp.set_param_group_lr(0, 0.5);
+        let data = vec![(CalcNode::new_scalar(1.), CalcNode::new_scalar(0.))];
+        mlp.train(&data, 0.1);
+        let updates: Vec<_> = mlp.param_iter().map(|p| 1. - p.value_indexed(0)).collect();
+        assert_approx_eq!(updates[1], 0.1);
+        assert_approx_eq!(updates[0], 0.5 * updates[1]);
+    }
+
+    #[test]
+    fn summary_lists_layers_and_parameter_count() {
+        let summary = two_layer_network().summary();
+        for name in ["Layer 1", "Non-linearity", "Layer 2", "LinearLayer", "FunctionLayer"] {
+            assert!(summary.contains(name), "Missing {name} in:\n{summary}");
+        }
+        // 2x2 weights and 2 biases plus 1x2 weights and 1 bias
+        assert!(summary.ends_with("Total parameters: 9\n"));
+        // No parameter values are shown
+        assert!(!summary.contains("0.5"));
+    }
+
+    #[test]
+    fn frozen_layer_keeps_parameters() {
+        let mut mlp = two_layer_network();
+        mlp.set_layer_trainable(0, false);
+        let before = mlp.get_parameter_bundle();
+        let data = vec![(CalcNode::new_col_vector(vec![0.5, -1.]), CalcNode::new_scalar(2.))];
+        mlp.train(&data, 0.1);
+        let after = mlp.get_parameter_bundle();
+        // The bundle skips the parameterless tanh layer
+        assert_eq!(before.layer_parameters(0), after.layer_parameters(0));
+        assert_ne!(before.layer_parameters(1), after.layer_parameters(1));
+    }
+
+    #[test]
+    fn weight_decay_shrinks_parameters_geometrically() {
+        const LEARNING_RATE: FloatType = 0.1;
+        const WEIGHT_DECAY: FloatType = 0.5;
+        let new_network = || {
+            let mut mlp = MultiLayer::new(vec![Box::new(LinearLayer::from_nodes(
+                CalcNode::new_scalar(2.),
+                None,
+                "Layer",
+            ))]);
+            mlp.set_loss_function(&|inp, _truth| inp.clone());
+            mlp.set_weight_decay(WEIGHT_DECAY);
+            mlp
+        };
+        let weight = |mlp: &MultiLayer| mlp.param_iter().next().unwrap().value_indexed(0);
+        // A zero input gives a zero gradient
+        let mut mlp = new_network();
+        let data = vec![(CalcNode::new_scalar(0.), CalcNode::new_scalar(0.))];
+        for _ in 0..3 {
+            mlp.train(&data, LEARNING_RATE);
+        }
+        assert_approx_eq!(weight(&mlp), 2. * (1. - LEARNING_RATE * WEIGHT_DECAY).powi(3));
+        // With the gradient 1 the step is taken after the decay
+        let mut mlp = new_network();
+        let data = vec![(CalcNode::new_scalar(1.), CalcNode::new_scalar(0.))];
+        mlp.train(&data, LEARNING_RATE);
+        assert_approx_eq!(weight(&mlp), 2. * (1. - LEARNING_RATE * WEIGHT_DECAY) - LEARNING_RATE);
+    }
+
+    #[test]
+    fn separate_steps_equal_train() {
+        let data = vec![(CalcNode::new_col_vector(vec![0.5, -1.]), CalcNode::new_scalar(2.))];
+        let mut trained = two_layer_network();
+        let loss = trained.train(&data, 0.1);
+
+        let mut manual = two_layer_network();
+        let mut manual_loss = manual.compute_loss(&data);
+        manual.backward(&mut manual_loss);
+        assert!(manual.param_iter().any(|p| p.copy_grad().iter().any(|&g| g != 0.)));
+        manual.step(0.1);
+
+        assert_eq!(manual_loss.value_indexed(0), loss);
+        assert_eq!(manual.get_parameter_bundle(), trained.get_parameter_bundle());
+    }
+
+    #[test]
+    fn activations_of_every_layer() {
+        let mlp = two_layer_network();
+        let inp = CalcNode::new_col_vector(vec![0.5, -1.]);
+        let (out, activations) = mlp.forward_with_activations(&inp);
+        assert_eq!(activations.len(), mlp.len());
+        assert_eq!(activations.last().unwrap().copy_vals(), out.copy_vals());
+        assert_eq!(out.copy_vals(), mlp.forward(&inp).copy_vals());
+        // The tanh layer squashes the output of the first layer
+        assert!(activations[1].copy_vals().iter().all(|v| v.abs() < 1.));
+    }
+
+    #[test]
+    fn inference_gives_the_same_values() {
+        type Activation = fn(&CalcNode) -> CalcNode;
+        let activations: [(Activation, &str); 5] = [
+            (FunctionLayer::sigmoid, "sigmoid"),
+            (FunctionLayer::tanh, "tanh"),
+            (FunctionLayer::relu, "relu"),
+            (FunctionLayer::gelu, "gelu"),
+            (FunctionLayer::softmax, "softmax"),
+        ];
+        for (func, name) in activations {
+            let mlp = MultiLayer::new(vec![
+                Box::new(LinearLayer::new_rand(3, 2, true, "Layer 1")),
+                Box::new(FunctionLayer::from_fn(func, name, "Activation")),
+                Box::new(LinearLayer::new_rand(2, 3, true, "Layer 2")),
+            ]);
+            for inp in [vec![0.5, -1.], vec![0., 0.], vec![2., 3.]] {
+                let inp = CalcNode::new_col_vector(inp);
+                let mut out = mlp.forward_inference(&inp);
+                assert_eq!(out.copy_vals(), mlp.forward(&inp).copy_vals(), "{name}");
+                // Nothing is kept for the back propagation
+                assert!(out.borrow().parents().is_empty());
+                out.back_propagation();
+            }
+        }
+        // Gradients are stored again afterwards
+        let out = two_layer_network().forward(&CalcNode::new_col_vector(vec![1., 1.]));
+        assert!(!out.borrow().parents().is_empty());
+    }
+
+    #[test]
+    fn pruning_zeroes_the_smallest_weights() {
+        let network = || {
+            MultiLayer::new(vec![
+                Box::new(LinearLayer::from_nodes(
+                    CalcNode::new_from_shape((2, 2), vec![1., -4., 0.5, 3.]),
+                    Some(CalcNode::new_col_vector(vec![0.1, -0.2])),
+                    "Layer 1",
+                )),
+                Box::new(FunctionLayer::new(&FunctionLayer::tanh, "tanh", "Non-linearity")),
+                Box::new(LinearLayer::from_nodes(
+                    CalcNode::new_from_shape((1, 2), vec![-2., 0.25]),
+                    Some(CalcNode::new_scalar(5.)),
+                    "Layer 2",
+                )),
+            ])
+        };
+        let mut mlp = network();
+        assert_eq!(mlp.prune(0.5), 3);
+        let vals: Vec<_> = mlp.param_iter().map(|p| p.copy_vals()).collect();
+        assert_eq!(vals[0], &[0., -4., 0., 3.]);
+        assert_eq!(vals[2], &[-2., 0.]);
+        // The biases are exempt
+        assert_eq!(vals[1], &[0.1, -0.2]);
+        assert_eq!(vals[3], &[5.]);
+
+        let mut mlp = network();
+        assert_eq!(mlp.prune_including_biases(0.5), 5);
+        let vals: Vec<_> = mlp.param_iter().map(|p| p.copy_vals()).collect();
+        assert_eq!(vals[0], &[0., -4., 0., 3.]);
+        assert_eq!(vals[1], &[0., 0.]);
+        assert_eq!(vals[2], &[-2., 0.]);
+        assert_eq!(vals[3], &[5.]);
+    }
+
+    #[test]
+    fn single_example_training_equals_batch_of_one() {
+        let inp = CalcNode::new_col_vector(vec![0.5, -1.]);
+        let truth = CalcNode::new_scalar(0.3);
+        let mut batch = two_layer_network();
+        let mut online = two_layer_network();
+        for _ in 0..3 {
+            let loss = batch.train(&[(inp.clone(), truth.clone())], 0.1);
+            assert_eq!(online.train_one(&inp, &truth, 0.1), loss);
+        }
+        assert_eq!(online.get_parameter_bundle(), batch.get_parameter_bundle());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "LinearLayer 'Layer 1' outputs 3 rows but LinearLayer 'Layer 2' expects 4."
+    )]
+    fn mismatched_layers_are_rejected() {
+        // The function layer passes the rows through
+        MultiLayer::new(vec![
+            Box::new(LinearLayer::new_rand(3, 2, true, "Layer 1")),
+            Box::new(FunctionLayer::new(&FunctionLayer::tanh, "tanh", "Non-linearity")),
+            Box::new(LinearLayer::new_rand(1, 4, true, "Layer 2")),
+        ]);
+    }
+
+    #[test]
+    fn flattened_context_must_fit_the_next_layer() {
+        // Two embedded characters of dimension 3 give 6 values, not 5
+        let result = MultiLayer::try_new(vec![
+            Box::new(EmbeddingLayer::new_rand(3, 4, "Embedding")),
+            Box::new(FlattenLayer::new((3, 2), "Flatten")),
+            Box::new(FunctionLayer::new(&FunctionLayer::tanh, "tanh", "Non-linearity")),
+            Box::new(LinearLayer::new_rand(2, 5, true, "Resize")),
+        ]);
+        assert_eq!(
+            result.err(),
+            Some("FlattenLayer 'Flatten' outputs 6 rows but LinearLayer 'Resize' expects 5.".into())
+        );
+    }
+
+    #[test]
+    fn tracked_norms_of_a_step() {
+        let mut mlp = MultiLayer::new(vec![Box::new(LinearLayer::from_nodes(
+            CalcNode::new_scalar(2.),
+            Some(CalcNode::new_scalar(1.)),
+            "Layer",
+        ))]);
+        mlp.set_loss_function(&least_squares);
+        let data = vec![(CalcNode::new_scalar(3.), CalcNode::new_scalar(4.))];
+        mlp.train(&data, 0.01);
+        assert_eq!(mlp.last_norms(), None);
+
+        mlp.set_track_norms(true);
+        mlp.param_iter_mut()
+            .zip([2., 1.])
+            .for_each(|(p, v)| p.set_vals(&[v]));
+        mlp.train(&data, 0.01);
+        // The error 2*3+1-4 = 3 gives the gradients 2*3*3 = 18 and 2*3 = 6
+        let (grad_norm, param_norm) = mlp.last_norms().unwrap();
+        assert_approx_eq!(grad_norm, (18. * 18. + 6. * 6. as FloatType).sqrt());
+        assert_approx_eq!(param_norm, (1.82 * 1.82 + 0.94 * 0.94 as FloatType).sqrt());
+    }
 }
@@ -0,0 +1,377 @@
+use std::collections::HashMap;
+
+use crate::nnetwork::{CalcNode, FloatType};
+
+use super::parameter_bundle::{encode_floats, read_floats, read_len, write_len};
+
+/// Decides how the parameters are updated once their gradients have been calculated.
+pub trait Optimizer {
+    /// Updates every parameter supplied by the iterator using its current gradient.
+    fn step(&mut self, params: &mut dyn Iterator<Item = &mut CalcNode>, learning_rate: FloatType);
+
+    /// Forgets any state accumulated during previous steps. Call this when a new training run begins.
+    fn reset(&mut self);
+
+    /// Returns the state accumulated during previous steps, e.g., to store it in a checkpoint. The state of every parameter is stored in the order of `params`, so that [Optimizer::load_state] can restore it for the corresponding parameters of another network. Optimizers without state return nothing.
+    fn save_state(&self, _params: &[&CalcNode]) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Replaces the current state with one returned by [Optimizer::save_state]. Fails if the state belongs to another type of optimizer or does not fit the parameters.
+    fn load_state(&mut self, _params: &[&CalcNode], state: &[u8]) -> Result<(), String> {
+        if state.is_empty() {
+            Ok(())
+        } else {
+            Err("The optimizer has no state to load.".to_string())
+        }
+    }
+}
+
+// Parameters are identified by the address of their underlying node. The state of a parameter is kept together with a clone of its node, so the address cannot be reused by another node as long as the state exists.
+fn param_key(param: &CalcNode) -> usize {
+    param.as_ptr() as usize
+}
+
+// A saved state starts with the name of the optimizer and the number of parameters. Writing to memory cannot fail.
+fn write_header(name: &str, n_params: usize) -> Vec<u8> {
+    let mut state = Vec::new();
+    write_len(&mut state, name.len()).unwrap();
+    state.extend(name.as_bytes());
+    write_len(&mut state, n_params).unwrap();
+    state
+}
+
+// Checks the header written by [write_header] and returns the rest of the state
+fn read_header<'a>(mut state: &'a [u8], name: &str, n_params: usize) -> Result<&'a [u8], String> {
+    let saved_name = read_len(&mut state)
+        .ok()
+        .and_then(|len| state.get(..len))
+        .map(|bytes| String::from_utf8_lossy(bytes).to_string())
+        .ok_or("The optimizer state is truncated.")?;
+    if saved_name != name {
+        return Err(format!(
+            "The state belongs to the optimizer {saved_name}, not {name}."
+        ));
+    }
+    state = &state[saved_name.len()..];
+    let saved_params = read_len(&mut state).map_err(truncated)?;
+    if saved_params != n_params {
+        return Err(format!(
+            "The state holds {saved_params} parameters but the network has {n_params}."
+        ));
+    }
+    Ok(state)
+}
+
+// Reads the values saved for one parameter, nothing if the parameter had not been stepped
+fn read_param_state(
+    state: &mut &[u8],
+    param: &CalcNode,
+    n_buffers: usize,
+) -> Result<Option<Vec<Vec<FloatType>>>, String> {
+    let len = read_len(state).map_err(truncated)?;
+    if len == 0 {
+        return Ok(None);
+    }
+    if len != param.len() {
+        return Err(format!(
+            "The state holds {len} values for a parameter of length {}.",
+            param.len()
+        ));
+    }
+    (0..n_buffers)
+        .map(|_| read_floats(state, len).map_err(truncated))
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+fn truncated(_: std::io::Error) -> String {
+    "The optimizer state is truncated.".to_string()
+}
+
+/// Plain gradient descent, i.e., every value is moved a fraction of its gradient. With momentum, the values are instead moved along a velocity that accumulates the gradients of previous steps.
+#[derive(Default)]
+pub struct Sgd {
+    _momentum: FloatType,
+    _velocities: HashMap<usize, (CalcNode, Vec<FloatType>)>,
+}
+
+impl Sgd {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps a velocity `v = momentum * v + grad` for every parameter and descends along it. The momentum must be in the range [0,1).
+    pub fn with_momentum(momentum: FloatType) -> Self {
+        assert!(
+            (0. ..1.).contains(&momentum),
+            "Momentum must be in the range [0,1)."
+        );
+        Sgd {
+            _momentum: momentum,
+            _velocities: HashMap::new(),
+        }
+    }
+}
+
+impl Optimizer for Sgd {
+    fn step(&mut self, params: &mut dyn Iterator<Item = &mut CalcNode>, learning_rate: FloatType) {
+        if self._momentum == 0. {
+            params.for_each(|p| p.decend_grad(learning_rate));
+            return;
+        }
+        for p in params {
+            // The buffer is sized the first time the parameter is seen
+            let (_, velocity) = self
+                ._velocities
+                .entry(param_key(p))
+                .or_insert_with(|| (p.clone(), vec![0.; p.len()]));
+            assert_eq!(
+                velocity.len(),
+                p.len(),
+                "Parameter changed size between optimizer steps."
+            );
+            let vals: Vec<FloatType> = p
+                .borrow()
+                .vals()
+                .iter()
+                .zip(p.borrow().grad())
+                .zip(velocity.iter_mut())
+                .map(|((&val, &g), v)| {
+                    *v = self._momentum * *v + g;
+                    val - learning_rate * *v
+                })
+                .collect();
+            p.set_vals(&vals);
+        }
+    }
+
+    fn reset(&mut self) {
+        self._velocities.clear();
+    }
+
+    fn save_state(&self, params: &[&CalcNode]) -> Vec<u8> {
+        let mut state = write_header("Sgd", params.len());
+        for p in params {
+            let velocity = self
+                ._velocities
+                .get(&param_key(p))
+                .map_or(&[][..], |(_, v)| v.as_slice());
+            write_len(&mut state, velocity.len()).unwrap();
+            state.extend(encode_floats(velocity));
+        }
+        state
+    }
+
+    fn load_state(&mut self, params: &[&CalcNode], state: &[u8]) -> Result<(), String> {
+        let mut state = read_header(state, "Sgd", params.len())?;
+        let mut velocities = HashMap::new();
+        for p in params {
+            if let Some(mut buffers) = read_param_state(&mut state, p, 1)? {
+                velocities.insert(param_key(p), ((*p).clone(), buffers.remove(0)));
+            }
+        }
+        self._velocities = velocities;
+        Ok(())
+    }
+}
+
+// First and second moment estimates together with the number of steps taken
+struct AdamMoments {
+    // Keeps the node alive, see [param_key]
+    _param: CalcNode,
+    _m: Vec<FloatType>,
+    _v: Vec<FloatType>,
+    _t: i32,
+}
+
+/// The Adam optimizer keeps running averages of the gradients and their squares for every parameter and uses them to adapt the step size per value.
+pub struct Adam {
+    _beta1: FloatType,
+    _beta2: FloatType,
+    _epsilon: FloatType,
+    _moments: HashMap<usize, AdamMoments>,
+}
+
+impl Adam {
+    pub fn new(beta1: FloatType, beta2: FloatType, epsilon: FloatType) -> Self {
+        assert!(
+            (0. ..1.).contains(&beta1) && (0. ..1.).contains(&beta2),
+            "Decay rates must be in the range [0,1)."
+        );
+        Adam {
+            _beta1: beta1,
+            _beta2: beta2,
+            _epsilon: epsilon,
+            _moments: HashMap::new(),
+        }
+    }
+}
+
+impl Default for Adam {
+    fn default() -> Self {
+        Self::new(0.9, 0.999, 1e-8)
+    }
+}
+
+impl Optimizer for Adam {
+    fn step(&mut self, params: &mut dyn Iterator<Item = &mut CalcNode>, learning_rate: FloatType) {
+        for p in params {
+            // The buffers are sized the first time the parameter is seen
+            let moments = self
+                ._moments
+                .entry(param_key(p))
+                .or_insert_with(|| AdamMoments {
+                    _param: p.clone(),
+                    _m: vec![0.; p.len()],
+                    _v: vec![0.; p.len()],
+                    _t: 0,
+                });
+            assert_eq!(
+                moments._m.len(),
+                p.len(),
+                "Parameter changed size between optimizer steps."
+            );
+            moments._t += 1;
+            let m_correction = 1. - self._beta1.powi(moments._t);
+            let v_correction = 1. - self._beta2.powi(moments._t);
+            let vals: Vec<FloatType> = p
+                .borrow()
+                .vals()
+                .iter()
+                .zip(p.borrow().grad())
+                .zip(moments._m.iter_mut().zip(moments._v.iter_mut()))
+                .map(|((&val, &g), (m, v))| {
+                    *m = self._beta1 * *m + (1. - self._beta1) * g;
+                    *v = self._beta2 * *v + (1. - self._beta2) * g * g;
+                    let m_hat = *m / m_correction;
+                    let v_hat = *v / v_correction;
+                    val - learning_rate * m_hat / (v_hat.sqrt() + self._epsilon)
+                })
+                .collect();
+            p.set_vals(&vals);
+        }
+    }
+
+    fn reset(&mut self) {
+        self._moments.clear();
+    }
+
+    fn save_state(&self, params: &[&CalcNode]) -> Vec<u8> {
+        let mut state = write_header("Adam", params.len());
+        for p in params {
+            match self._moments.get(&param_key(p)) {
+                Some(moments) => {
+                    write_len(&mut state, moments._m.len()).unwrap();
+                    state.extend(encode_floats(&moments._m));
+                    state.extend(encode_floats(&moments._v));
+                    write_len(&mut state, moments._t as usize).unwrap();
+                }
+                None => write_len(&mut state, 0).unwrap(),
+            }
+        }
+        state
+    }
+
+    fn load_state(&mut self, params: &[&CalcNode], state: &[u8]) -> Result<(), String> {
+        let mut state = read_header(state, "Adam", params.len())?;
+        let mut moments = HashMap::new();
+        for p in params {
+            if let Some(mut buffers) = read_param_state(&mut state, p, 2)? {
+                let t = read_len(&mut state).map_err(truncated)?;
+                let v = buffers.remove(1);
+                let m = buffers.remove(0);
+                moments.insert(
+                    param_key(p),
+                    AdamMoments {
+                        _param: (*p).clone(),
+                        _m: m,
+                        _v: v,
+                        _t: t as i32,
+                    },
+                );
+            }
+        }
+        self._moments = moments;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn adam_first_step_has_size_of_learning_rate() {
+        let mut param = CalcNode::new_col_vector(vec![1., 2., 3.]);
+        param.set_grad(&[0.5, -20., 0.001]);
+        let mut adam = Adam::default();
+        adam.step(&mut std::iter::once(&mut param), 0.1);
+        let expected = [0.9, 2.1, 2.9];
+        for (value, expected) in param.copy_vals().iter().zip(expected) {
+            assert_approx_eq!(value, expected, 1e-4);
+        }
+    }
+
+    #[test]
+    fn adam_reset_forgets_moments() {
+        let mut param = CalcNode::new_scalar(0.);
+        let mut adam = Adam::default();
+        param.set_grad(&[1.]);
+        adam.step(&mut std::iter::once(&mut param), 1.);
+        adam.reset();
+        assert!(adam._moments.is_empty());
+        param.set_grad(&[1.]);
+        adam.step(&mut std::iter::once(&mut param), 1.);
+        assert_approx_eq!(param.value_indexed(0), -2., 1e-6);
+    }
+
+    #[test]
+    fn saved_state_continues_on_other_parameters() {
+        let mut param = CalcNode::new_col_vector(vec![1., -1.]);
+        let mut adam = Adam::default();
+        for grad in [[0.5, 2.], [-1., 0.25]] {
+            param.set_grad(&grad);
+            adam.step(&mut std::iter::once(&mut param), 0.1);
+        }
+        let state = adam.save_state(&[&param]);
+
+        let mut copy = CalcNode::new_col_vector(param.copy_vals());
+        let mut restored = Adam::default();
+        restored.load_state(&[&copy], &state).unwrap();
+        param.set_grad(&[2., -3.]);
+        copy.set_grad(&[2., -3.]);
+        adam.step(&mut std::iter::once(&mut param), 0.1);
+        restored.step(&mut std::iter::once(&mut copy), 0.1);
+        assert_eq!(copy.copy_vals(), param.copy_vals());
+
+        let err = Sgd::new().load_state(&[&copy], &state).unwrap_err();
+        assert!(err.contains("Adam"), "{err}");
+        let err = restored.load_state(&[&copy, &param], &state).unwrap_err();
+        assert!(err.contains("network has 2"), "{err}");
+    }
+
+    #[test]
+    fn momentum_accelerates_constant_gradient() {
+        // Returns the distance moved by each of the steps
+        let step_sizes = |mut optimizer: Sgd| {
+            let mut param = CalcNode::new_scalar(0.);
+            (0..5)
+                .map(|_| {
+                    let before = param.value_indexed(0);
+                    param.set_grad(&[1.]);
+                    optimizer.step(&mut std::iter::once(&mut param), 0.1);
+                    before - param.value_indexed(0)
+                })
+                .collect::<Vec<_>>()
+        };
+        let plain = step_sizes(Sgd::new());
+        let momentum = step_sizes(Sgd::with_momentum(0.9));
+        assert!(plain.iter().all(|&s| (s - 0.1).abs() < 1e-12));
+        assert_approx_eq!(momentum[0], 0.1);
+        assert_approx_eq!(momentum[1], 0.19);
+        assert!(momentum.windows(2).all(|w| w[1] > w[0]));
+        assert!(momentum.iter().sum::<FloatType>() > plain.iter().sum::<FloatType>());
+    }
+}
@@ -0,0 +1,156 @@
+//! [CalcNode]s are reference counted and cannot be shared between threads. Instead every worker thread builds its own replica of the network, loads the current parameter values into it and evaluates its share of the examples. Only plain values are sent between the threads.
+
+use rayon::prelude::*;
+
+use crate::nnetwork::{CalcNode, FloatType, NodeShape, Parameters};
+
+use super::MultiLayer;
+
+/// Builds a network with the same layers, loss function and regularization as the one being trained. The parameter values do not matter since they are overwritten.
+pub type ReplicaFactory = dyn Fn() -> MultiLayer + Sync;
+
+// A node stripped down to what can be sent between threads
+type RawNode = (NodeShape, Vec<FloatType>);
+
+fn to_raw(node: &CalcNode) -> RawNode {
+    (node.shape(), node.copy_vals())
+}
+
+fn from_raw((shape, vals): &RawNode) -> CalcNode {
+    CalcNode::new_from_shape(*shape, vals.clone())
+}
+
+impl MultiLayer {
+    // Returns the summed loss of all examples and, if requested, the summed gradients of all parameters
+    fn par_loss_sum(
+        &self,
+        inp: &[(CalcNode, CalcNode)],
+        factory: &ReplicaFactory,
+        with_grad: bool,
+    ) -> (FloatType, Option<Vec<Vec<FloatType>>>) {
+        let bundle = self.get_parameter_bundle();
+        let raw: Vec<(RawNode, RawNode)> = inp.iter().map(|(x, y)| (to_raw(x), to_raw(y))).collect();
+        let chunk_size = raw.len().div_ceil(rayon::current_num_threads()).max(1);
+        raw.par_chunks(chunk_size)
+            .map(|chunk| {
+                let mut replica = factory();
+                replica
+                    .load_parameter_bundle(&bundle)
+                    .expect("Replica must have the same parameters as the network.");
+                let examples: Vec<_> = chunk.iter().map(|(x, y)| (from_raw(x), from_raw(y))).collect();
+                let mut loss = replica.loss_sum(&examples);
+                let grad = with_grad.then(|| {
+                    loss.back_propagation();
+                    replica.param_iter().map(|p| p.copy_grad()).collect::<Vec<_>>()
+                });
+                (loss.value_indexed(0), grad)
+            })
+            .reduce(
+                || (0., None),
+                |(loss_a, grad_a), (loss_b, grad_b)| {
+                    let grad = match (grad_a, grad_b) {
+                        (Some(mut a), Some(b)) => {
+                            a.iter_mut()
+                                .flatten()
+                                .zip(b.iter().flatten())
+                                .for_each(|(a, b)| *a += b);
+                            Some(a)
+                        }
+                        (a, b) => a.or(b),
+                    };
+                    (loss_a + loss_b, grad)
+                },
+            )
+    }
+
+    /// Same as [MultiLayer::loss] but evaluates the examples in parallel on replicas built by the factory. Returns only the value since the graph cannot be shared between threads.
+    pub fn loss_parallel(&self, inp: &[(CalcNode, CalcNode)], factory: &ReplicaFactory) -> FloatType {
+        let (loss, _) = self.par_loss_sum(inp, factory, false);
+        loss / inp.len() as FloatType + self.calc_regularization().value_indexed(0)
+    }
+
+    /// Same as [MultiLayer::train] but calculates the loss and gradients in parallel on replicas built by the factory. Without any examples nothing is changed and NaN is returned.
+    pub fn train_parallel(
+        &mut self,
+        inp: &[(CalcNode, CalcNode)],
+        learning_rate: FloatType,
+        factory: &ReplicaFactory,
+    ) -> FloatType {
+        if inp.is_empty() {
+            return FloatType::NAN;
+        }
+        let (loss, grad) = self.par_loss_sum(inp, factory, true);
+        let scale = 1. / inp.len() as FloatType;
+        // The regularization only depends on the parameters and is calculated here
+        self.param_iter_mut().for_each(|p| p.reset_grad());
+        let mut reg = self.calc_regularization();
+        reg.back_propagation();
+        let grad = grad.expect("Every replica returns the gradients.");
+        for (p, g) in self.param_iter_mut().zip(grad) {
+            p.add_grad(&g.iter().map(|g| g * scale).collect::<Vec<_>>());
+        }
+        self.decend_grad(learning_rate);
+        loss * scale + reg.value_indexed(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nnetwork::{loss_functions::least_squares, FunctionLayer, LinearLayer};
+    use assert_approx_eq::assert_approx_eq;
+
+    fn network() -> MultiLayer {
+        let mut mlp = MultiLayer::new_seeded(
+            vec![
+                Box::new(LinearLayer::new_rand(8, 4, true, "Layer 1")),
+                Box::new(FunctionLayer::new(&FunctionLayer::tanh, "Tanh", "Non-linearity")),
+                Box::new(LinearLayer::new_rand(1, 8, true, "Layer 2")),
+            ],
+            7,
+        );
+        mlp.set_loss_function(&least_squares);
+        mlp.set_regularization(Some(0.01));
+        mlp
+    }
+
+    fn examples() -> Vec<(CalcNode, CalcNode)> {
+        (0..200)
+            .map(|i| {
+                let x = i as FloatType / 100.;
+                (
+                    CalcNode::new_col_vector(vec![x, -x, x * x, 1. - x]),
+                    CalcNode::new_scalar(x.sin()),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parallel_and_sequential_training_agree() {
+        let data = examples();
+        let mut sequential = network();
+        let mut parallel = network();
+
+        let sequential_loss = sequential.loss(&data).value_indexed(0);
+        let parallel_loss = parallel.loss_parallel(&data, &network);
+        assert_approx_eq!(sequential_loss, parallel_loss);
+
+        let sequential_loss = sequential.train(&data, 0.1);
+        let parallel_loss = parallel.train_parallel(&data, 0.1, &network);
+        assert_approx_eq!(sequential_loss, parallel_loss);
+        for (a, b) in sequential.param_iter().zip(parallel.param_iter()) {
+            for (x, y) in a.copy_vals().iter().zip(b.copy_vals()) {
+                assert_approx_eq!(x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_training_without_examples_changes_nothing() {
+        let mut mlp = network();
+        let before = mlp.get_parameter_bundle();
+        assert!(mlp.train_parallel(&[], 0.1, &network).is_nan());
+        assert_eq!(mlp.get_parameter_bundle(), before);
+    }
+}
@@ -0,0 +1,688 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Error, ErrorKind, Read, Write},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::nnetwork::{FloatType, Layer, NodeShape};
+
+/// Identifies files written by [ParameterBundle::export_binary]
+const MAGIC: &[u8; 4] = b"NNPB";
+/// The current version of the binary format. Version 1 did not store the shapes of the parameters and version 2 not the optimizer state.
+const FORMAT_VERSION: u32 = 3;
+/// The current version of the format written by [ParameterBundle::export_json]
+const JSON_VERSION: u32 = 1;
+/// The number of bytes of every value in the binary format
+const FLOAT_SIZE: usize = std::mem::size_of::<FloatType>();
+
+// The parameters of a single layer. The shapes are missing if read from a file that did not record them.
+#[derive(Clone, Debug, PartialEq)]
+struct BundledLayer {
+    _name: String,
+    _shapes: Vec<Option<NodeShape>>,
+    _params: Vec<Vec<FloatType>>,
+}
+
+/// A snapshot of the values of all parameters in a network, grouped by the [Layer]s holding them. Only [Layer]s that have parameters are included.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParameterBundle {
+    _layers: Vec<BundledLayer>,
+    _optimizer_state: Option<Vec<u8>>,
+}
+
+impl ParameterBundle {
+    /// Copies the current parameter values of the [Layer]s.
+    pub(super) fn from_layers<'a>(layers: impl Iterator<Item = &'a dyn Layer>) -> Self {
+        ParameterBundle {
+            _layers: layers
+                .map(|layer| BundledLayer {
+                    _name: layer.layer_name().to_string(),
+                    _shapes: layer.param_iter().map(|p| Some(p.shape())).collect(),
+                    _params: layer.param_iter().map(|p| p.copy_vals()).collect(),
+                })
+                .filter(|layer| !layer._params.is_empty())
+                .collect(),
+            _optimizer_state: None,
+        }
+    }
+
+    /// Overwrites the parameter values of the [Layer]s. Nothing is changed if the parameters do not match in number and shape. Parameters without a recorded shape are only checked for their length.
+    pub(super) fn load_into<'a>(
+        &self,
+        layers: impl Iterator<Item = &'a mut Box<dyn Layer>>,
+    ) -> Result<(), String> {
+        let mut targets: Vec<_> = layers
+            .filter(|layer| layer.param_iter().next().is_some())
+            .collect();
+        if targets.len() != self._layers.len() {
+            return Err(format!(
+                "Bundle contains parameters for {} layers but the network has {}.",
+                self._layers.len(),
+                targets.len()
+            ));
+        }
+        for (layer, target) in self._layers.iter().zip(&targets) {
+            let name = &layer._name;
+            let n_target_params = target.param_iter().count();
+            if n_target_params != layer._params.len() {
+                return Err(format!(
+                    "Layer {name} has {} parameters but the bundle contains {}.",
+                    n_target_params,
+                    layer._params.len()
+                ));
+            }
+            for (n, ((vals, shape), param)) in layer
+                ._params
+                .iter()
+                .zip(&layer._shapes)
+                .zip(target.param_iter())
+                .enumerate()
+            {
+                match shape {
+                    Some(shape) if *shape != param.shape() => {
+                        return Err(format!(
+                            "Parameter {n} of layer {name} has shape {:?} but the bundle contains shape {shape:?}.",
+                            param.shape()
+                        ))
+                    }
+                    _ if vals.len() != param.len() => {
+                        return Err(format!(
+                            "Parameter {n} of layer {name} has length {} but the bundle contains length {}.",
+                            param.len(),
+                            vals.len()
+                        ))
+                    }
+                    _ => (),
+                }
+            }
+        }
+        for (layer, target) in self._layers.iter().zip(targets.iter_mut()) {
+            for (vals, param) in layer._params.iter().zip(target.param_iter_mut()) {
+                param.set_vals(vals);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the number of [Layer]s in the bundle.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self._layers.len()
+    }
+
+    /// Returns the names of the [Layer]s in the bundle.
+    pub fn layer_names(&self) -> impl Iterator<Item = &str> {
+        self._layers.iter().map(|layer| layer._name.as_str())
+    }
+
+    /// Returns the parameter values of the indexed [Layer]. Panics if the index is invalid.
+    pub fn layer_parameters(&self, i: usize) -> &[Vec<FloatType>] {
+        &self._layers[i]._params
+    }
+
+    /// Returns the shapes of the parameters of the indexed [Layer], if known. Panics if the index is invalid.
+    pub fn layer_shapes(&self, i: usize) -> &[Option<NodeShape>] {
+        &self._layers[i]._shapes
+    }
+
+    /// Returns the state of the optimizer stored together with the parameters, if any, see [MultiLayer::save_optimizer_state](super::MultiLayer::save_optimizer_state).
+    pub fn optimizer_state(&self) -> Option<&[u8]> {
+        self._optimizer_state.as_deref()
+    }
+
+    /// Stores the state of the optimizer together with the parameters, e.g., in a checkpoint from which training is continued.
+    pub fn set_optimizer_state(&mut self, state: Option<Vec<u8>>) {
+        self._optimizer_state = state;
+    }
+
+    /// Creates a bundle where every value is the weighted mean of the corresponding values of the bundles, e.g., to average networks trained with different seeds. All bundles must have the same layer names and parameter shapes, and the weights must have a positive sum. No optimizer state is kept.
+    pub fn weighted_average(
+        bundles: &[(ParameterBundle, FloatType)],
+    ) -> Result<ParameterBundle, String> {
+        let Some((first, _)) = bundles.first() else {
+            return Err("Cannot average zero bundles.".to_string());
+        };
+        let weight_sum: FloatType = bundles.iter().map(|(_, weight)| weight).sum();
+        if weight_sum <= 0. || !weight_sum.is_finite() {
+            return Err(format!(
+                "The weights must have a positive sum, not {weight_sum}."
+            ));
+        }
+        for (n, (bundle, _)) in bundles.iter().enumerate().skip(1) {
+            if bundle.len() != first.len() {
+                return Err(format!(
+                    "Bundle {n} has {} layers but bundle 0 has {}.",
+                    bundle.len(),
+                    first.len()
+                ));
+            }
+            for (layer, reference) in bundle._layers.iter().zip(&first._layers) {
+                if layer._name != reference._name {
+                    return Err(format!(
+                        "Bundle {n} has layer {} where bundle 0 has layer {}.",
+                        layer._name, reference._name
+                    ));
+                }
+                let lengths =
+                    |l: &BundledLayer| l._params.iter().map(|p| p.len()).collect::<Vec<_>>();
+                if layer._shapes != reference._shapes || lengths(layer) != lengths(reference) {
+                    return Err(format!(
+                        "Layer {} of bundle {n} has parameters of shapes {:?} and lengths {:?} but bundle 0 has shapes {:?} and lengths {:?}.",
+                        layer._name,
+                        layer._shapes,
+                        lengths(layer),
+                        reference._shapes,
+                        lengths(reference)
+                    ));
+                }
+            }
+        }
+
+        let mut average = first.clone();
+        average._optimizer_state = None;
+        for (i, layer) in average._layers.iter_mut().enumerate() {
+            for (j, vals) in layer._params.iter_mut().enumerate() {
+                for (k, val) in vals.iter_mut().enumerate() {
+                    *val = bundles
+                        .iter()
+                        .map(|(bundle, weight)| weight * bundle._layers[i]._params[j][k])
+                        .sum::<FloatType>()
+                        / weight_sum;
+                }
+            }
+        }
+        Ok(average)
+    }
+
+    /// Writes the bundle to a compact binary file. After a header with magic bytes, format version and layer count follows, for each layer, the length of the name, the name, the number of parameters, and for each parameter its shape, length and values. Last comes a flag telling whether an optimizer state follows, in which case its length and bytes are written. All numbers are little-endian. A missing shape is written as (0,0).
+    pub fn export_binary(&self, path: &str) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_binary(&mut writer)?;
+        writer.flush()
+    }
+
+    // Writes the binary format of [ParameterBundle::export_binary], e.g., after other data in the same file
+    pub(super) fn write_binary(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        write_len(writer, self._layers.len())?;
+        for layer in &self._layers {
+            write_len(writer, layer._name.len())?;
+            writer.write_all(layer._name.as_bytes())?;
+            write_len(writer, layer._params.len())?;
+            for (vals, shape) in layer._params.iter().zip(&layer._shapes) {
+                let (rows, cols) = shape.unwrap_or((0, 0));
+                write_len(writer, rows)?;
+                write_len(writer, cols)?;
+                write_len(writer, vals.len())?;
+                writer.write_all(&encode_floats(vals))?;
+            }
+        }
+        match &self._optimizer_state {
+            Some(state) => {
+                write_len(writer, 1)?;
+                write_len(writer, state.len())?;
+                writer.write_all(state)?;
+            }
+            None => write_len(writer, 0)?,
+        }
+        Ok(())
+    }
+
+    /// Reads a bundle written by [ParameterBundle::export_binary]. Fails if the file is not a parameter bundle or uses an unsupported version of the format.
+    ///
+    /// The whole file is read at once. With the `rayon` feature the values are decoded in parallel.
+    pub fn import_binary(path: &str) -> std::io::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        Self::read_binary(&mut bytes.as_slice(), path)
+    }
+
+    // Reads the binary format of [ParameterBundle::export_binary] from the start of the bytes. The source is only used in error messages.
+    pub(super) fn read_binary(reader: &mut &[u8], source: &str) -> std::io::Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{source} is not a parameter bundle, wrong magic bytes: {magic:?}"),
+            ));
+        }
+        let mut version = [0u8; 4];
+        reader.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+        if !(1..=FORMAT_VERSION).contains(&version) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Unsupported parameter bundle version {version}, expected at most {FORMAT_VERSION}."),
+            ));
+        }
+        // Every layer holds at least the lengths of its name and of its parameters
+        let n_layers = read_bounded_len(reader, 16, source)?;
+        let mut layers = Vec::with_capacity(n_layers);
+        for _ in 0..n_layers {
+            let mut name = vec![0u8; read_bounded_len(reader, 1, source)?];
+            reader.read_exact(&mut name)?;
+            let name = String::from_utf8(name).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+            let n_params = read_bounded_len(reader, 8, source)?;
+            let mut params = Vec::with_capacity(n_params);
+            let mut shapes = Vec::with_capacity(n_params);
+            for _ in 0..n_params {
+                if version >= 2 {
+                    let shape = (read_len(reader)?, read_len(reader)?);
+                    shapes.push(if shape == (0, 0) { None } else { Some(shape) });
+                } else {
+                    shapes.push(None);
+                }
+                let n_vals = read_len(reader)?;
+                params.push(read_floats(reader, n_vals)?);
+            }
+            layers.push(BundledLayer {
+                _name: name,
+                _shapes: shapes,
+                _params: params,
+            });
+        }
+        let optimizer_state = match version {
+            1..=2 => None,
+            _ => match read_len(reader)? {
+                0 => None,
+                1 => {
+                    let mut state = vec![0u8; read_bounded_len(reader, 1, source)?];
+                    reader.read_exact(&mut state)?;
+                    Some(state)
+                }
+                flag => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("Invalid optimizer state flag {flag} in {source}."),
+                    ))
+                }
+            },
+        };
+        Ok(ParameterBundle {
+            _layers: layers,
+            _optimizer_state: optimizer_state,
+        })
+    }
+}
+
+// The layout of files written by [ParameterBundle::export_json]
+#[derive(Serialize, Deserialize)]
+struct JsonBundle {
+    version: u32,
+    layers: Vec<JsonLayer>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonLayer {
+    name: String,
+    // Optional so that files written by other tools need not record the shapes
+    #[serde(default)]
+    shapes: Option<Vec<Option<NodeShape>>>,
+    params: Vec<Vec<FloatType>>,
+}
+
+impl ParameterBundle {
+    /// Writes the bundle to a JSON file for use with other tools. The optimizer state is left out. The file holds an object with the `version` of the format and a list of `layers`, each with its `name`, the `params` as lists of values and the `shapes` of the parameters as `[rows, cols]` or `null` if unknown.
+    pub fn export_json(&self, path: &str) -> std::io::Result<()> {
+        let json = JsonBundle {
+            version: JSON_VERSION,
+            layers: self
+                ._layers
+                .iter()
+                .map(|layer| JsonLayer {
+                    name: layer._name.clone(),
+                    shapes: Some(layer._shapes.clone()),
+                    params: layer._params.clone(),
+                })
+                .collect(),
+        };
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, &json)?;
+        writer.flush()
+    }
+
+    /// Reads a bundle written by [ParameterBundle::export_json]. The `shapes` may be left out. Fails with a description of the problem if the file is not valid JSON of the expected layout.
+    pub fn import_json(path: &str) -> std::io::Result<Self> {
+        let invalid = |msg: String| Error::new(ErrorKind::InvalidData, msg);
+        let json: JsonBundle = serde_json::from_reader(BufReader::new(File::open(path)?))
+            .map_err(|e| invalid(format!("{path} is not a JSON parameter bundle: {e}")))?;
+        if json.version != JSON_VERSION {
+            return Err(invalid(format!(
+                "Unsupported JSON parameter bundle version {}, expected {JSON_VERSION}.",
+                json.version
+            )));
+        }
+        let layers = json
+            .layers
+            .into_iter()
+            .map(|layer| {
+                let shapes = layer
+                    .shapes
+                    .unwrap_or_else(|| vec![None; layer.params.len()]);
+                if shapes.len() != layer.params.len() {
+                    return Err(invalid(format!(
+                        "Layer '{}' has {} parameters but {} shapes.",
+                        layer.name,
+                        layer.params.len(),
+                        shapes.len()
+                    )));
+                }
+                Ok(BundledLayer {
+                    _name: layer.name,
+                    _shapes: shapes,
+                    _params: layer.params,
+                })
+            })
+            .collect::<std::io::Result<_>>()?;
+        Ok(ParameterBundle {
+            _layers: layers,
+            _optimizer_state: None,
+        })
+    }
+}
+
+pub(super) fn write_len(writer: &mut impl Write, len: usize) -> std::io::Result<()> {
+    writer.write_all(&(len as u64).to_le_bytes())
+}
+
+pub(super) fn read_len(reader: &mut impl Read) -> std::io::Result<usize> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes) as usize)
+}
+
+// Reads a length of items taking at least `item_size` bytes each, and fails if they cannot fit in the remaining bytes. Keeps a corrupt length from causing a huge allocation.
+fn read_bounded_len(reader: &mut &[u8], item_size: usize, source: &str) -> std::io::Result<usize> {
+    let len = read_len(reader)?;
+    check_fits(reader, len, item_size, source)?;
+    Ok(len)
+}
+
+fn check_fits(reader: &[u8], len: usize, item_size: usize, source: &str) -> std::io::Result<()> {
+    match len.checked_mul(item_size) {
+        Some(size) if size <= reader.len() => Ok(()),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "{source} is corrupt, {len} items do not fit in the remaining {} bytes.",
+                reader.len()
+            ),
+        )),
+    }
+}
+
+// Encodes all values at once so that every parameter is a single write
+pub(super) fn encode_floats(vals: &[FloatType]) -> Vec<u8> {
+    vals.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+pub(super) fn read_floats(reader: &mut &[u8], n: usize) -> std::io::Result<Vec<FloatType>> {
+    check_fits(reader, n, FLOAT_SIZE, "The data")?;
+    let mut bytes = vec![0u8; n * FLOAT_SIZE];
+    reader.read_exact(&mut bytes)?;
+    Ok(decode_floats(&bytes))
+}
+
+fn decode_float(bytes: &[u8]) -> FloatType {
+    FloatType::from_le_bytes(bytes.try_into().unwrap())
+}
+
+// Every value takes the same number of bytes, so the chunks can be decoded independently
+#[cfg(feature = "rayon")]
+fn decode_floats(bytes: &[u8]) -> Vec<FloatType> {
+    use rayon::prelude::*;
+    bytes.par_chunks_exact(FLOAT_SIZE).map(decode_float).collect()
+}
+
+#[cfg(not(feature = "rayon"))]
+fn decode_floats(bytes: &[u8]) -> Vec<FloatType> {
+    bytes.chunks_exact(FLOAT_SIZE).map(decode_float).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nnetwork::{CalcNode, LinearLayer};
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("{name}_{}.bin", std::process::id()))
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let bundle = ParameterBundle {
+            _layers: vec![
+                BundledLayer {
+                    _name: "Layer 1".to_string(),
+                    _shapes: vec![Some((1, 3)), Some((2, 1))],
+                    _params: vec![vec![1., -2.5, 3e-9], vec![0.5, -0.5]],
+                },
+                BundledLayer {
+                    _name: "Layer 2".to_string(),
+                    _shapes: vec![None],
+                    _params: vec![vec![FloatType::MAX]],
+                },
+            ],
+            _optimizer_state: Some(vec![1, 2, 3]),
+        };
+        let path = temp_path("binary_round_trip");
+        bundle.export_binary(&path).unwrap();
+        let imported = ParameterBundle::import_binary(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(imported.unwrap(), bundle);
+    }
+
+    // Counts the calls to write to tell whether the values are written one by one
+    struct CountingWriter {
+        _writes: usize,
+        _bytes: Vec<u8>,
+    }
+
+    impl Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self._writes += 1;
+            self._bytes.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn large_binary_round_trip() {
+        let layer = |i: usize| BundledLayer {
+            _name: format!("Layer {i}"),
+            _shapes: vec![Some((300, 500)), Some((300, 1))],
+            _params: vec![
+                (0..150_000).map(|v| (v * i) as FloatType * 1e-3).collect(),
+                (0..300).map(|v| -(v as FloatType)).collect(),
+            ],
+        };
+        let bundle = ParameterBundle {
+            _layers: (0..4).map(layer).collect(),
+            _optimizer_state: None,
+        };
+        let mut writer = CountingWriter {
+            _writes: 0,
+            _bytes: Vec::new(),
+        };
+        bundle.write_binary(&mut writer).unwrap();
+        // A handful of writes per parameter rather than one per value
+        assert!(writer._writes < 100, "{} writes", writer._writes);
+
+        let path = temp_path("large_binary_round_trip");
+        bundle.export_binary(&path).unwrap();
+        let written = std::fs::read(&path).unwrap();
+        let imported = ParameterBundle::import_binary(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written, writer._bytes);
+        assert_eq!(imported.unwrap(), bundle);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let bundle = ParameterBundle {
+            _layers: vec![
+                BundledLayer {
+                    _name: "Layer 1".to_string(),
+                    _shapes: vec![Some((1, 3)), Some((2, 1))],
+                    _params: vec![vec![1., -2.5, 3e-9], vec![0.1, -0.5]],
+                },
+                BundledLayer {
+                    _name: "Layer 2".to_string(),
+                    _shapes: vec![None],
+                    _params: vec![vec![FloatType::MAX]],
+                },
+            ],
+            _optimizer_state: None,
+        };
+        let path = temp_path("json_round_trip");
+        bundle.export_json(&path).unwrap();
+        let imported = ParameterBundle::import_json(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(imported.unwrap(), bundle);
+    }
+
+    #[test]
+    fn malformed_json_is_reported() {
+        let path = temp_path("malformed_json");
+        std::fs::write(
+            &path,
+            r#"{"version": 1, "layers": [{"name": "Layer", "params": [[1.0, "#,
+        )
+        .unwrap();
+        let truncated = ParameterBundle::import_json(&path);
+        std::fs::write(
+            &path,
+            r#"{"version": 1, "layers": [{"name": "Layer", "params": [1.0]}]}"#,
+        )
+        .unwrap();
+        let flat = ParameterBundle::import_json(&path);
+        std::fs::write(
+            &path,
+            r#"{"version": 1, "layers": [{"name": "Layer", "params": [[1.0]]}]}"#,
+        )
+        .unwrap();
+        let without_shapes = ParameterBundle::import_json(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let err = truncated.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(
+            err.to_string().contains("not a JSON parameter bundle"),
+            "{err}"
+        );
+        let err = flat.unwrap_err();
+        assert!(err.to_string().contains("line 1"), "{err}");
+        assert_eq!(without_shapes.unwrap().layer_shapes(0), &[None]);
+    }
+
+    #[test]
+    fn binary_import_rejects_wrong_magic() {
+        let path = temp_path("binary_wrong_magic");
+        std::fs::write(&path, b"ABCD\x01\0\0\0").unwrap();
+        let imported = ParameterBundle::import_binary(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(imported.unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn binary_import_rejects_lengths_beyond_the_data() {
+        let header = |n_layers: u64| {
+            let mut bytes = MAGIC.to_vec();
+            bytes.extend(FORMAT_VERSION.to_le_bytes());
+            bytes.extend(n_layers.to_le_bytes());
+            bytes
+        };
+        let read = |bytes: Vec<u8>| ParameterBundle::read_binary(&mut bytes.as_slice(), "test");
+        assert_eq!(read(header(1 << 40)).unwrap_err().kind(), ErrorKind::InvalidData);
+        // A single layer without a name holding one parameter with far too many values
+        let mut bytes = header(1);
+        for len in [0u64, 1, 1, 1, 1 << 61] {
+            bytes.extend(len.to_le_bytes());
+        }
+        bytes.extend((1. as FloatType).to_le_bytes());
+        assert_eq!(read(bytes).unwrap_err().kind(), ErrorKind::InvalidData);
+
+        let path = temp_path("binary_truncated");
+        let layers: Vec<Box<dyn Layer>> =
+            vec![Box::new(LinearLayer::from_nodes(CalcNode::new_scalar(1.), None, "Layer"))];
+        ParameterBundle::from_layers(layers.iter().map(|l| l.as_ref()))
+            .export_binary(&path)
+            .unwrap();
+        let mut bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        bytes.truncate(bytes.len() - 12);
+        assert!(read(bytes).is_err());
+    }
+
+    #[test]
+    fn binary_import_rejects_optimizer_state_beyond_the_data() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(FORMAT_VERSION.to_le_bytes());
+        // No layers, then an optimizer state claiming far more bytes than there are
+        for len in [0u64, 1, 1 << 50] {
+            bytes.extend(len.to_le_bytes());
+        }
+        let err = ParameterBundle::read_binary(&mut bytes.as_slice(), "test").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn shape_mismatch_is_reported() {
+        let mut layers: Vec<Box<dyn Layer>> = vec![Box::new(LinearLayer::from_nodes(
+            CalcNode::new_from_shape((3, 2), vec![1.; 6]),
+            None,
+            "Layer",
+        ))];
+        let bundle = ParameterBundle::from_layers(layers.iter().map(|l| l.as_ref()));
+        let mut transposed: Vec<Box<dyn Layer>> = vec![Box::new(LinearLayer::from_nodes(
+            CalcNode::new_from_shape((2, 3), vec![2.; 6]),
+            None,
+            "Layer",
+        ))];
+        let err = bundle.load_into(transposed.iter_mut()).unwrap_err();
+        assert!(err.contains("shape"), "{err}");
+        assert!(bundle.load_into(layers.iter_mut()).is_ok());
+        // Without a recorded shape only the length is checked
+        let mut length_only = bundle.clone();
+        length_only._layers[0]._shapes = vec![None];
+        assert!(length_only.load_into(transposed.iter_mut()).is_ok());
+    }
+
+    #[test]
+    fn weighted_average_of_bundles() {
+        let bundle = |vals: Vec<FloatType>| ParameterBundle {
+            _layers: vec![BundledLayer {
+                _name: "Layer".to_string(),
+                _shapes: vec![Some((1, 3))],
+                _params: vec![vals],
+            }],
+            _optimizer_state: None,
+        };
+        let average = ParameterBundle::weighted_average(&[
+            (bundle(vec![1., 2., -3.]), 0.5),
+            (bundle(vec![3., 0., -1.]), 0.5),
+        ])
+        .unwrap();
+        assert_eq!(average, bundle(vec![2., 1., -2.]));
+
+        let mut renamed = bundle(vec![1., 2., 3.]);
+        renamed._layers[0]._name = "Other".to_string();
+        let err = ParameterBundle::weighted_average(&[(bundle(vec![0.; 3]), 1.), (renamed, 1.)])
+            .unwrap_err();
+        assert!(err.contains("Other"), "{err}");
+        let mut reshaped = bundle(vec![1., 2., 3.]);
+        reshaped._layers[0]._shapes = vec![Some((3, 1))];
+        let err = ParameterBundle::weighted_average(&[(bundle(vec![0.; 3]), 1.), (reshaped, 1.)])
+            .unwrap_err();
+        assert!(err.contains("shapes"), "{err}");
+    }
+}
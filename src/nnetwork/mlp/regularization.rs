@@ -0,0 +1,52 @@
+use crate::nnetwork::{CalcNode, FloatType};
+
+/// Adds a penalty for non-zero parameters to the loss. The coefficients must be positive.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Regularization {
+    #[default]
+    None,
+    /// Punishes the sum of the absolute values of the parameters, driving small parameters to zero.
+    L1(FloatType),
+    /// Punishes the sum of the squares of the parameters.
+    L2(FloatType),
+    /// Both of the above with separate coefficients.
+    ElasticNet { l1: FloatType, l2: FloatType },
+}
+
+/// `Some(c)` is interpreted as [Regularization::L2] with coefficient `c`.
+impl From<Option<FloatType>> for Regularization {
+    fn from(coefficient: Option<FloatType>) -> Self {
+        match coefficient {
+            Some(c) => Regularization::L2(c),
+            None => Regularization::None,
+        }
+    }
+}
+
+impl Regularization {
+    // The penalty averaged over the parameter nodes, i.e., the weight matrices and bias vectors
+    pub(super) fn penalty<'a>(&self, params: impl Iterator<Item = &'a CalcNode>) -> CalcNode {
+        let (l1, l2) = match *self {
+            Regularization::None => return CalcNode::new_scalar(0.),
+            Regularization::L1(l1) => (Some(l1), None),
+            Regularization::L2(l2) => (None, Some(l2)),
+            Regularization::ElasticNet { l1, l2 } => (Some(l1), Some(l2)),
+        };
+        if l1.is_some_and(|c| c <= 0.) || l2.is_some_and(|c| c <= 0.) {
+            panic!("Regularization coefficient must be positive.");
+        }
+        let params: Vec<&CalcNode> = params.collect();
+        let n_param = CalcNode::new_scalar(params.len() as FloatType);
+        let mut penalty = CalcNode::new_scalar(0.);
+        if let Some(l1) = l1 {
+            penalty = penalty
+                + params.iter().map(|p| p.abs().sum()).sum::<CalcNode>() * CalcNode::new_scalar(l1);
+        }
+        if let Some(l2) = l2 {
+            penalty = penalty
+                + params.iter().map(|p| p.square().sum()).sum::<CalcNode>()
+                    * CalcNode::new_scalar(l2);
+        }
+        penalty / n_param
+    }
+}
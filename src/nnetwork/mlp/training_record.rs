@@ -0,0 +1,14 @@
+use crate::nnetwork::FloatType;
+
+/// The outcome of one training cycle, e.g., as returned by [crate::retext::ReText::train].
+#[derive(Clone, Debug)]
+pub struct TrainingRecord {
+    pub cycle: usize,
+    pub train_loss: FloatType,
+    /// Only set for the cycles where the validation loss was evaluated, e.g., when early stopping checks it.
+    pub validation_loss: Option<FloatType>,
+    pub duration_micros: u128,
+    /// The norm of the gradient and of the parameters after the step, if tracked, see [MultiLayer::set_track_norms](super::MultiLayer::set_track_norms).
+    pub grad_norm: Option<FloatType>,
+    pub param_norm: Option<FloatType>,
+}
@@ -1,5 +1,7 @@
 use std::fmt::Display;
 
+use rand::RngCore;
+
 use crate::nnetwork::{CalcNode, NodeShape};
 
 pub trait Layer: Parameters + Display {
@@ -8,15 +10,50 @@ pub trait Layer: Parameters + Display {
         None
     }
 
+    /// The number of rows the input must have, if the [Layer] requires a specific number. Used to check that consecutive [Layer]s fit together when creating a [MultiLayer](super::MultiLayer).
+    fn input_dim(&self) -> Option<usize> {
+        None
+    }
+
+    /// The number of rows of the output, if decided by the [Layer]. [Layer]s that keep the rows of the input, e.g., a [FunctionLayer](super::FunctionLayer), return None.
+    fn output_dim(&self) -> Option<usize> {
+        None
+    }
+
     /// Calculates the output given an input.
     fn forward(&self, inp: &CalcNode) -> CalcNode;
 
     /// All [Layer]s must have a name
     fn layer_name(&self) -> &str;
+
+    /// The name of the type of the [Layer], e.g., `LinearLayer`.
+    fn layer_type(&self) -> &'static str {
+        let path = std::any::type_name::<Self>();
+        path.rsplit("::").next().unwrap_or(path)
+    }
+
+    /// Switches between training and evaluation mode. Only [Layer]s that behave differently during training need to override this.
+    fn set_training(&mut self, _training: bool) {}
+
+    /// Draws new values for all parameters from the random number generator, the same way as when the [Layer] was created. Used by [MultiLayer::new_seeded](super::MultiLayer::new_seeded). Only [Layer]s with parameters need to override this.
+    fn reinit(&mut self, _rng: &mut dyn RngCore) {}
+
+    /// True if the output depends on earlier calls to [Layer::forward], e.g., the hidden state of a [RecurrentLayer](super::RecurrentLayer). Such [Layer]s cannot be recorded by [MultiLayer::compile_forward](super::MultiLayer::compile_forward).
+    fn is_stateful(&self) -> bool {
+        false
+    }
+
+    /// Forgets the state kept between calls to [Layer::forward]. Only stateful [Layer]s need to override this.
+    fn reset_state(&self) {}
 }
 
 /// Object implementing this trait must supply iterators to all its parameters, in arbitrary, but fixed, order.
 pub trait Parameters {
     fn param_iter(&self) -> Box<dyn Iterator<Item = &CalcNode> + '_>;
     fn param_iter_mut(&mut self) -> Box<dyn Iterator<Item = &mut CalcNode> + '_>;
+
+    /// Same as [Parameters::param_iter_mut] but without the biases, e.g., for pruning. Must be overridden by objects that have biases.
+    fn weight_iter_mut(&mut self) -> Box<dyn Iterator<Item = &mut CalcNode> + '_> {
+        self.param_iter_mut()
+    }
 }
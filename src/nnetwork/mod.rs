@@ -1,7 +1,15 @@
 mod calc_node;
 mod mlp;
 
-pub use calc_node::{CalcNode, CalcNodeCore, FloatType, NodeShape, NodeType, VecOrientation};
+pub use calc_node::{
+    CalcNode, CalcNode32, CalcNode64, CalcNodeCore, Float, FloatType, ForwardFunc, NodeShape,
+    NodeType, ReshapeError, VecOrientation,
+};
+#[cfg(feature = "rayon")]
+pub use mlp::ReplicaFactory;
 pub use mlp::{
-    FunctionLayer, Layer, LinearLayer, MultiLayer, Parameters, ReshapeLayer, loss_functions
+    attention, loss_functions, Adam, Constant, CosineAnnealing, DropoutLayer, EmbeddingLayer, FlattenLayer,
+    FunctionLayer, InitScheme, Layer, LayerDescription, LayerNormLayer, LinearLayer, LogSpaced,
+    LrSchedule, ModelArchive, MultiLayer, Optimizer, ParameterBundle, Parameters, RecurrentLayer,
+    Regularization, ReshapeLayer, ResidualLayer, Sgd, StepDecay, TrainingRecord,
 };
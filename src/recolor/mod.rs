@@ -1,3 +1,3 @@
 mod rgb_predictor;
 
-pub use rgb_predictor::ReColor;
+pub use rgb_predictor::{ClassificationStats, ReColor};
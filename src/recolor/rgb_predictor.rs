@@ -7,10 +7,21 @@ use rand_distr::{Distribution, Uniform};
 use std::{error::Error, fmt::Display, ops::Range, time::Instant};
 
 use crate::nnetwork::{
-    loss_functions::least_squares, CalcNode, FloatType, FunctionLayer, Layer, LinearLayer,
-    MultiLayer, Parameters,
+    loss_functions::least_squares, CalcNode, FloatType, FunctionLayer, InitScheme, Layer,
+    LinearLayer, LrSchedule, MultiLayer, Parameters, TrainingRecord,
 };
 
+/// Per colour channel (red, green, blue) statistics of how well the thresholded predictions match the colour key, see [ReColor::evaluate].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClassificationStats {
+    /// Fraction of the samples where the channel was predicted correctly.
+    pub accuracy: [FloatType; 3],
+    /// Fraction of the samples predicted to have the channel on that actually have it. Zero if no sample was predicted to have it.
+    pub precision: [FloatType; 3],
+    /// Fraction of the samples that have the channel on that were predicted to have it. Zero if no sample has it.
+    pub recall: [FloatType; 3],
+}
+
 /// Manages the construction and training of a network that decides what color a pixel should have.
 pub struct ReColor<T>
 where
@@ -27,7 +38,11 @@ where
     T: Fn((FloatType, FloatType)) -> [bool; 3],
 {
     // Helps ctor
-    fn create_layers(n_hidden_layers: usize, layer_size: usize) -> Vec<Box<dyn Layer>> {
+    fn create_layers(
+        n_hidden_layers: usize,
+        layer_size: usize,
+        init: InitScheme,
+    ) -> Vec<Box<dyn Layer>> {
         const BIASED_LAYERS: bool = true;
         const INPUT_DIM: usize = 2;
         const OUTPUT_DIM: usize = 3;
@@ -38,45 +53,65 @@ where
         let mut layers: Vec<Box<dyn Layer>> = Vec::new();
 
         // Input layer
-        layers.push(Box::new(LinearLayer::new_rand(
+        layers.push(Box::new(LinearLayer::new_rand_with_init(
             layer_size,
             INPUT_DIM,
             BIASED_LAYERS,
             "Resizing layer (in)",
+            init,
         )));
         layers.push(Box::new(non_linearity.clone()));
 
         // Hidden layers
         for n in 0..n_hidden_layers {
-            layers.push(Box::new(LinearLayer::new_rand(
+            layers.push(Box::new(LinearLayer::new_rand_with_init(
                 layer_size,
                 layer_size,
                 BIASED_LAYERS,
                 &format!("Hidden layer {n}"),
+                init,
             )));
             layers.push(Box::new(non_linearity.clone()));
         }
 
         // Output layer
-        layers.push(Box::new(LinearLayer::new_rand(
+        layers.push(Box::new(LinearLayer::new_rand_with_init(
             OUTPUT_DIM,
             layer_size,
             BIASED_LAYERS,
             "Resizing layer (out)",
+            init,
         )));
         layers.push(Box::new(non_linearity.clone()));
 
         layers
     }
 
-    /// After each linear layer a non-linear [FunctionLayer] is inserted.
+    /// After each linear layer a non-linear [FunctionLayer] is inserted. The weights are initialised with [InitScheme::Xavier], which keeps the sigmoid layers from saturating.
     pub fn new(
         color_key: T,
         n_hidden_layers: usize,
         layer_size: usize,
         regularization: Option<FloatType>,
     ) -> ReColor<T> {
-        let mut mlp = MultiLayer::new(Self::create_layers(n_hidden_layers, layer_size));
+        Self::new_with_init(
+            color_key,
+            n_hidden_layers,
+            layer_size,
+            regularization,
+            InitScheme::Xavier,
+        )
+    }
+
+    /// Same as [ReColor::new] but the weights of the linear layers are initialised according to the supplied [InitScheme].
+    pub fn new_with_init(
+        color_key: T,
+        n_hidden_layers: usize,
+        layer_size: usize,
+        regularization: Option<FloatType>,
+        init: InitScheme,
+    ) -> ReColor<T> {
+        let mut mlp = MultiLayer::new(Self::create_layers(n_hidden_layers, layer_size, init));
         mlp.set_regularization(regularization);
         mlp.set_loss_function(&least_squares);
         ReColor {
@@ -108,6 +143,43 @@ where
             })
     }
 
+    /// Samples `batch_size` coordinates and compares the predictions, counting a channel as on if its value exceeds 0.5, to the colour key.
+    pub fn evaluate(
+        &self,
+        batch_size: usize,
+        x_range: &Range<FloatType>,
+        y_range: &Range<FloatType>,
+    ) -> ClassificationStats {
+        // Counts of true positives, false positives, false negatives and true negatives per channel
+        let mut counts = [[0usize; 4]; 3];
+        for (coords, truth) in self.calc_correlations(batch_size, x_range, y_range) {
+            let prediction = self._mlp.forward(&coords).copy_vals();
+            for (channel, count) in counts.iter_mut().enumerate() {
+                let predicted = prediction[channel] > 0.5;
+                let actual = truth.value_indexed(channel) > 0.5;
+                let index = match (predicted, actual) {
+                    (true, true) => 0,
+                    (true, false) => 1,
+                    (false, true) => 2,
+                    (false, false) => 3,
+                };
+                count[index] += 1;
+            }
+        }
+        let ratio = |num: usize, den: usize| {
+            if den == 0 {
+                0.
+            } else {
+                num as FloatType / den as FloatType
+            }
+        };
+        ClassificationStats {
+            accuracy: counts.map(|[tp, fp, fn_, tn]| ratio(tp + tn, tp + fp + fn_ + tn)),
+            precision: counts.map(|[tp, fp, _, _]| ratio(tp, tp + fp)),
+            recall: counts.map(|[tp, _, fn_, _]| ratio(tp, tp + fn_)),
+        }
+    }
+
     // Creates a list of tuples containing input coords and the correct color
     fn calc_correlations(
         &self,
@@ -129,50 +201,66 @@ where
     }
 
     /// Trains the network for the specified number of cycles. Each cycles uses ´batch_size´ data points.
-    /// The learning rate is a range from highest to lowest which will be logspaced so that the learning rate get lower for each cycle.
+    /// The learning rate of every cycle is decided by the [LrSchedule], e.g., [LogSpaced](crate::nnetwork::LogSpaced) to lower it for each cycle.
+    ///
+    /// If a `progress` callback is given it receives a [TrainingRecord] for every cycle, otherwise the cycles and a summary are printed if `verbose` is set.
     ///
     /// Returns a vector of learning rates and loss values
+    #[allow(clippy::too_many_arguments)]
     pub fn train(
         &mut self,
         cycles: usize,
         batch_size: usize,
-        learning_rate: Range<FloatType>,
+        learning_rate: &dyn LrSchedule,
         x_range: &Range<FloatType>,
         y_range: &Range<FloatType>,
         verbose: bool,
+        mut progress: Option<&mut dyn FnMut(&TrainingRecord)>,
     ) -> &[(FloatType, FloatType)] {
         let timer = Instant::now();
         self._training_results.clear();
         let mut loss = 0.;
-        let learning_rate_log_step = if cycles < 2 {
-            learning_rate.start
-        } else {
-            (learning_rate.end.ln() - learning_rate.start.ln()) / (cycles - 1) as FloatType
-        };
         for n in 0..cycles {
             let correlations = self.calc_correlations(batch_size, x_range, y_range);
             let timer = Instant::now();
-            let learning_rate =
-                (learning_rate.start.ln() + learning_rate_log_step * n as FloatType).exp();
+            let learning_rate = learning_rate.lr(n, cycles);
             loss = self._mlp.train(&correlations, learning_rate);
 
             self._training_results.push((learning_rate, loss));
+            let record = TrainingRecord {
+                cycle: n,
+                train_loss: loss,
+                validation_loss: None,
+                duration_micros: timer.elapsed().as_micros(),
+                grad_norm: self._mlp.last_norms().map(|(grad_norm, _)| grad_norm),
+                param_norm: self._mlp.last_norms().map(|(_, param_norm)| param_norm),
+            };
 
             // Provide some per cycle stats
-            if verbose {
-                let width = (cycles as f64).log10() as usize + 1;
-                println!(
-                    "Cycle #{n: >width$}, learning_rate: {learning_rate:.2e} [ loss: {:.3e}, duration: {} µs ]",
-                    loss,
-                    timer.elapsed().as_micros()
-                );
+            match progress.as_mut() {
+                Some(progress) => progress(&record),
+                None if verbose => {
+                    let width = (cycles as f64).log10() as usize + 1;
+                    println!(
+                        "Cycle #{n: >width$}, learning_rate: {learning_rate:.2e} [ loss: {:.3e}, duration: {} µs ]",
+                        loss, record.duration_micros
+                    );
+                }
+                None => (),
             }
         }
-        println!(
-            "Trained network with {} parameters for {cycles} cycles in {} ms achieving a loss of: {:.3e}",
-            self._mlp.param_iter().map(|p| p.len()).sum::<usize>(),
-            timer.elapsed().as_millis(), loss
-        );
+        if verbose && progress.is_none() {
+            println!(
+                "Trained network with {} parameters for {cycles} cycles in {} ms achieving a loss of: {:.3e}",
+                self._mlp.param_iter().map(|p| p.len()).sum::<usize>(),
+                timer.elapsed().as_millis(), loss
+            );
+        }
+        &self._training_results
+    }
+
+    /// Returns the learning rate and loss of every cycle of the last call to [ReColor::train], see also [ReColor::plot_training_progress].
+    pub fn training_history(&self) -> &[(FloatType, FloatType)] {
         &self._training_results
     }
 
@@ -301,3 +389,45 @@ impl Display for TrainingError{
     }
 }
 impl Error for TrainingError{}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nnetwork::Constant;
+
+    #[test]
+    fn evaluation_of_untrained_network() {
+        let recolor = ReColor::new(|_| [true; 3], 1, 5, None);
+        let stats = recolor.evaluate(50, &(-1. ..1.), &(-1. ..1.));
+        for channel in 0..3 {
+            for value in [
+                stats.accuracy[channel],
+                stats.precision[channel],
+                stats.recall[channel],
+            ] {
+                assert!((0. ..=1.).contains(&value));
+            }
+            // Every sample has the channel on, so accuracy and recall coincide
+            assert_eq!(stats.accuracy[channel], stats.recall[channel]);
+        }
+    }
+
+    #[test]
+    fn default_initialisation_does_not_saturate() {
+        let recolor = ReColor::new(|_| [true; 3], 3, 20, None);
+        for value in recolor.predict((0.5, -0.5)) {
+            assert!(value > 0.05 && value < 0.95, "Saturated output: {value}");
+        }
+    }
+
+    #[test]
+    fn training_history_has_every_cycle() {
+        let mut recolor = ReColor::new(|(x, _)| [x > 0., true, false], 1, 4, None);
+        assert!(recolor.training_history().is_empty());
+        let range = -1. ..1.;
+        recolor.train(3, 8, &Constant(0.1), &range, &range, false, None);
+        let history = recolor.training_history();
+        assert_eq!(history.len(), 3);
+        assert!(history.iter().all(|&(lr, loss)| lr == 0.1 && loss.is_finite()));
+    }
+}
@@ -1,7 +1,14 @@
-use std::fs;
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader, Seek, SeekFrom},
+};
+
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
 
 use crate::nnetwork::{CalcNode, FloatType, NodeType, VecOrientation};
 
+use super::{text_predictor::windows, TextSource};
+
 #[derive(Debug, PartialEq)]
 pub enum DataSetError {
     Encoding(char),
@@ -16,6 +23,16 @@ pub struct CharSet {
     _chars: Vec<char>,
     _training_data: Vec<String>,
     _validation_data: Vec<String>,
+    // Only set when the lines are read from the file on demand
+    _line_index: Option<LineIndex>,
+}
+
+// Where the lines of a streamed data set start in the file
+struct LineIndex {
+    _path: String,
+    _lowercase: bool,
+    _offsets: Vec<u64>,
+    _n_training: usize,
 }
 
 impl CharSet {
@@ -32,6 +49,42 @@ impl CharSet {
     /// ```
     pub fn new(path: &str, training_ratio: f32, lowercase: bool) -> Self {
         let data = Self::get_string_from_file(path, lowercase);
+        Self::from_data(data, training_ratio)
+    }
+
+    /// Like [CharSet::new] but the lines are taken from one column of a CSV file. Fields may be quoted to contain commas, newlines or (doubled) quotes. Newlines within a field are replaced by spaces.
+    ///
+    /// # Example
+    /// ```
+    /// use net_neurons::retext::CharSet;
+    ///
+    /// let ds = CharSet::from_csv("./datasets/test.csv", 1, true, 1., true);
+    /// assert_eq!(ds.training_data(), &["hello", "bye, now", "say \"yo\""]);
+    /// ```
+    pub fn from_csv(
+        path: &str,
+        column: usize,
+        has_header: bool,
+        training_ratio: f32,
+        lowercase: bool,
+    ) -> Self {
+        let content = Self::get_string_from_file(path, lowercase);
+        let data = parse_csv(&content)
+            .into_iter()
+            .skip(if has_header { 1 } else { 0 })
+            .enumerate()
+            .map(|(n, mut record)| {
+                if column >= record.len() {
+                    panic!("Record {n} in {path} has no column {column}.");
+                }
+                record.swap_remove(column).replace('\n', " ")
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        Self::from_data(data, training_ratio)
+    }
+
+    fn from_data(data: String, training_ratio: f32) -> Self {
         let mut training_data = Vec::new();
         let mut validation_data = Vec::new();
         let n_training = (data.lines().count() as f32 * training_ratio) as usize - 1;
@@ -56,6 +109,163 @@ impl CharSet {
             _chars: chars,
             _training_data: training_data,
             _validation_data: validation_data,
+            _line_index: None,
+        }
+    }
+
+    /// Like [CharSet::new] but without keeping the data in memory. The file is read once to find the characters and where every line starts. Afterwards the lines are read from the file on demand using [CharSet::training_lines] and [CharSet::validation_lines], while [CharSet::training_data] and [CharSet::validation_data] are empty. A [ReText](super::ReText) reads the lines the same way through [TextSource].
+    pub fn from_file_streaming(path: &str, training_ratio: f32, lowercase: bool) -> Self {
+        let mut reader = BufReader::new(Self::open(path));
+        let mut offsets = Vec::new();
+        let mut chars = Vec::new();
+        let mut offset = 0;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let n_bytes = reader
+                .read_line(&mut line)
+                .unwrap_or_else(|err| panic!("Received {err:?} while reading dataset {path}."));
+            if n_bytes == 0 {
+                break;
+            }
+            offsets.push(offset);
+            offset += n_bytes as u64;
+            let line = if lowercase {
+                line.to_lowercase()
+            } else {
+                line.clone()
+            };
+            line.chars().for_each(|c| {
+                if c.is_ascii_alphabetic() && !chars.contains(&c) {
+                    chars.push(c);
+                }
+            });
+        }
+        chars.sort();
+        let n_training = (offsets.len() as f32 * training_ratio) as usize - 1;
+
+        CharSet {
+            _data: String::new(),
+            _chars: chars,
+            _training_data: Vec::new(),
+            _validation_data: Vec::new(),
+            _line_index: Some(LineIndex {
+                _path: path.to_string(),
+                _lowercase: lowercase,
+                _offsets: offsets,
+                _n_training: n_training,
+            }),
+        }
+    }
+
+    fn open(path: &str) -> File {
+        File::open(path)
+            .unwrap_or_else(|err| panic!("Received {err:?} while importing dataset from {path}."))
+    }
+
+    // Reads `count` lines from the file of a streamed data set, starting with the indexed line
+    fn stream_lines(
+        index: &LineIndex,
+        first: usize,
+        count: usize,
+    ) -> Box<dyn Iterator<Item = String>> {
+        if first >= index._offsets.len() || count == 0 {
+            return Box::new(std::iter::empty());
+        }
+        let mut file = Self::open(&index._path);
+        file.seek(SeekFrom::Start(index._offsets[first]))
+            .unwrap_or_else(|err| {
+                panic!("Received {err:?} while reading dataset {}.", index._path)
+            });
+        let lowercase = index._lowercase;
+        Box::new(BufReader::new(file).lines().take(count).map(move |line| {
+            let line = line.unwrap_or_else(|err| panic!("Received {err:?} while reading dataset."));
+            if lowercase {
+                line.to_lowercase()
+            } else {
+                line
+            }
+        }))
+    }
+
+    /// Iterates over the training data, reading it from the file if the [CharSet] was created by [CharSet::from_file_streaming].
+    pub fn training_lines(&self) -> Box<dyn Iterator<Item = String> + '_> {
+        match &self._line_index {
+            Some(index) => Self::stream_lines(index, 0, index._n_training),
+            None => Box::new(self._training_data.iter().cloned()),
+        }
+    }
+
+    /// Iterates over the validation data, reading it from the file if the [CharSet] was created by [CharSet::from_file_streaming].
+    pub fn validation_lines(&self) -> Box<dyn Iterator<Item = String> + '_> {
+        match &self._line_index {
+            Some(index) => {
+                let count = index._offsets.len() - index._n_training;
+                Self::stream_lines(index, index._n_training, count)
+            }
+            None => Box::new(self._validation_data.iter().cloned()),
+        }
+    }
+
+    /// Walks through all of the training data in order and yields batches of `batch_size` correlations, the last batch holding any remainder. Every correlation is a context of `block_size` characters and the character following it, encoded and padded exactly as when training a [ReText](super::ReText). Every correlation thus appears exactly once per pass, e.g., for epoch based training.
+    ///
+    /// The lines are padded with the `sentinel` token, see [ReText::sentinel](super::ReText::sentinel), which is added to the set when a [ReText](super::ReText) is created for it. Panics when reaching a character outside the set.
+    pub fn batches(
+        &self,
+        block_size: usize,
+        batch_size: usize,
+        sentinel: char,
+    ) -> impl Iterator<Item = Vec<(CalcNode, CalcNode)>> + '_ {
+        assert!(batch_size > 0, "Batches must not be empty.");
+        let mut correlations = self
+            .training_lines()
+            .flat_map(move |line| windows(&line, block_size, sentinel))
+            .map(|(prev, next)| {
+                let encode = |s: &str| {
+                    self.encode(s)
+                        .unwrap_or_else(|err| panic!("Cannot encode '{s}': {err:?}"))
+                };
+                (encode(&prev), encode(&next.to_string()))
+            });
+        std::iter::from_fn(move || {
+            let batch: Vec<_> = correlations.by_ref().take(batch_size).collect();
+            if batch.is_empty() {
+                None
+            } else {
+                Some(batch)
+            }
+        })
+    }
+
+    /// Splits all lines anew into training and validation data by a random partition, instead of reserving the last lines of the file for validation. The same seed always gives the same partition of the same data. The training ratio decides the fraction of the lines used for training, rounded to the nearest line.
+    ///
+    /// Panics if the ratio is not within [0,1] or if the [CharSet] was created by [CharSet::from_file_streaming].
+    pub fn reshuffle_split(&mut self, training_ratio: f32, seed: u64) {
+        assert!(
+            (0. ..=1.).contains(&training_ratio),
+            "The training ratio must be within [0,1], not {training_ratio}."
+        );
+        assert!(self._line_index.is_none(), "The split of a streamed data set cannot be reshuffled.");
+        let mut lines: Vec<String> = self._data.lines().map(str::to_string).collect();
+        lines.shuffle(&mut StdRng::seed_from_u64(seed));
+        let n_training = (lines.len() as f32 * training_ratio).round() as usize;
+        self._validation_data = lines.split_off(n_training);
+        self._training_data = lines;
+    }
+
+    /// The number of lines available for training, whether they are kept in memory or not.
+    pub fn training_len(&self) -> usize {
+        match &self._line_index {
+            Some(index) => index._n_training,
+            None => self._training_data.len(),
+        }
+    }
+
+    /// The number of lines reserved for validation, whether they are kept in memory or not.
+    pub fn validation_len(&self) -> usize {
+        match &self._line_index {
+            Some(index) => index._offsets.len() - index._n_training,
+            None => self._validation_data.len(),
         }
     }
 
@@ -91,12 +301,12 @@ impl CharSet {
         data.unwrap()
     }
 
-    /// Returns a slice of all available training data.
+    /// Returns a slice of all available training data. Empty if the [CharSet] was created by [CharSet::from_file_streaming], use [CharSet::training_lines] instead.
     pub fn training_data(&self) -> &[String] {
         &self._training_data
     }
 
-    /// Returns a slice of all available validation data.
+    /// Returns a slice of all available validation data. Empty if the [CharSet] was created by [CharSet::from_file_streaming], use [CharSet::validation_lines] instead.
     pub fn validation_data(&self) -> &[String] {
         &self._validation_data
     }
@@ -157,7 +367,7 @@ impl CharSet {
     /// ```
     pub fn encode(&self, s: &str) -> Result<CalcNode, DataSetError> {
         let n_rows = self._chars.len();
-        let n_cols = s.len();
+        let n_cols = s.chars().count();
         let mut out_vec = vec![0.; n_rows * n_cols];
         for (col, ch) in s.chars().enumerate() {
             if let Some(row) = self._chars.iter().position(|&k| ch == k) {
@@ -170,6 +380,105 @@ impl CharSet {
     }
 }
 
+// Splits CSV content into records of fields. Quoted fields may contain commas, newlines and doubled quotes.
+pub(super) fn parse_csv(content: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, in_quotes) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', true) => in_quotes = false,
+            ('"', false) if field.is_empty() => in_quotes = true,
+            (',', false) => record.push(std::mem::take(&mut field)),
+            ('\r', false) => (),
+            ('\n', false) => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            _ => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+}
+
+// Quotes the field if needed so that it is read back unchanged by [parse_csv], or any other CSV reader
+pub(super) fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+impl TextSource for CharSet {
+    fn training_data(&self) -> &[String] {
+        CharSet::training_data(self)
+    }
+
+    fn validation_data(&self) -> &[String] {
+        CharSet::validation_data(self)
+    }
+
+    fn training_len(&self) -> usize {
+        CharSet::training_len(self)
+    }
+
+    fn validation_len(&self) -> usize {
+        CharSet::validation_len(self)
+    }
+
+    fn training_range(
+        &self,
+        first: usize,
+        count: usize,
+    ) -> Box<dyn Iterator<Item = String> + '_> {
+        match &self._line_index {
+            Some(index) => {
+                let count = count.min(index._n_training.saturating_sub(first));
+                Self::stream_lines(index, first, count)
+            }
+            None => Box::new(self._training_data.iter().skip(first).take(count).cloned()),
+        }
+    }
+
+    fn validation_range(
+        &self,
+        first: usize,
+        count: usize,
+    ) -> Box<dyn Iterator<Item = String> + '_> {
+        match &self._line_index {
+            Some(index) => Self::stream_lines(index, index._n_training + first, count),
+            None => Box::new(self._validation_data.iter().skip(first).take(count).cloned()),
+        }
+    }
+
+    fn encode(&self, s: &str) -> Result<CalcNode, DataSetError> {
+        CharSet::encode(self, s)
+    }
+
+    fn decode_char(&self, vector: &CalcNode) -> Result<char, DataSetError> {
+        CharSet::decode_char(self, vector)
+    }
+
+    fn number_of_chars(&self) -> usize {
+        CharSet::number_of_chars(self)
+    }
+
+    fn add_character(&mut self, c: char) {
+        CharSet::add_character(self, c)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -180,6 +489,27 @@ mod tests {
         CharSet::new("no dataset here", 0.9, false);
     }
 
+    #[test]
+    fn reshuffled_split_is_reproducible() {
+        let mut ds = CharSet::new("./datasets/names.txt", 1., true);
+        let n_lines = ds.training_len() + ds.validation_len();
+        ds.reshuffle_split(0.8, 5);
+        assert_eq!(ds.training_len(), (n_lines as f32 * 0.8).round() as usize);
+        assert_eq!(ds.validation_len(), n_lines - ds.training_len());
+        let training = ds.training_data().to_vec();
+        let validation = ds.validation_data().to_vec();
+        // Not simply the tail of the file
+        let content = fs::read_to_string("./datasets/names.txt").unwrap();
+        let tail: Vec<_> = content.lines().skip(training.len()).collect();
+        assert_ne!(validation, tail);
+
+        ds.reshuffle_split(0.5, 6);
+        assert_ne!(ds.training_data(), training);
+        ds.reshuffle_split(0.8, 5);
+        assert_eq!(ds.training_data(), training);
+        assert_eq!(ds.validation_data(), validation);
+    }
+
     #[test]
     fn reading_all_shakespeare() {
         let ds = CharSet::new("./datasets/tiny_shakespeare.txt", 1., false);
@@ -192,4 +522,49 @@ mod tests {
         let ds = CharSet::new("./datasets/tiny_shakespeare.txt", 1., true);
         assert_eq!(ds.number_of_chars(), 26);
     }
+
+    #[test]
+    fn batches_cover_every_correlation_once() {
+        // "anna" and "bob" are used for training
+        let mut ds = CharSet::new("./datasets/short_names.txt", 0.5, true);
+        ds.add_character('^');
+        let batches: Vec<_> = ds.batches(2, 4, '^').collect();
+        // One correlation per character plus the terminating sentinel, 5 + 4 in total
+        assert_eq!(
+            batches.iter().map(|b| b.len()).collect::<Vec<_>>(),
+            [4, 4, 1]
+        );
+        let expected = ["^^a", "^an", "ann", "nna", "na^", "^^b", "^bo", "bob", "ob^"];
+        for ((context, next), window) in batches.iter().flatten().zip(expected) {
+            assert_eq!(context.copy_vals(), ds.encode(&window[..2]).unwrap().copy_vals());
+            assert_eq!(next.copy_vals(), ds.encode(&window[2..]).unwrap().copy_vals());
+        }
+    }
+
+    #[test]
+    fn reading_column_from_csv() {
+        // The letters of the label column must not end up in the set
+        let ds = CharSet::from_csv("./datasets/test.csv", 1, true, 1., true);
+        assert_eq!(ds.number_of_chars(), 11);
+        assert_eq!(ds.training_data().len(), 3);
+        assert_eq!(ds.validation_data(), &["ok"]);
+    }
+
+    #[test]
+    fn streaming_gives_same_data_as_eager_loading() {
+        let eager = CharSet::new("./datasets/short_names.txt", 0.5, true);
+        let streamed = CharSet::from_file_streaming("./datasets/short_names.txt", 0.5, true);
+        assert_eq!(streamed.characters(), eager.characters());
+        assert_eq!(streamed.training_len(), eager.training_data().len());
+        assert_eq!(streamed.validation_len(), eager.validation_data().len());
+        assert!(streamed.training_data().is_empty());
+        assert_eq!(
+            streamed.training_lines().collect::<Vec<_>>(),
+            eager.training_data()
+        );
+        assert_eq!(
+            streamed.validation_lines().collect::<Vec<_>>(),
+            eager.validation_data()
+        );
+    }
 }
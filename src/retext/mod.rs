@@ -1,5 +1,13 @@
 mod char_set;
 mod text_predictor;
+mod text_source;
+mod word_set;
 
+pub use crate::nnetwork::TrainingRecord;
 pub use char_set::{CharSet, DataSetError};
-pub use text_predictor::ReText;
+pub use text_predictor::{
+    Checkpoint, DecodeStrategy, EarlyStopping, ReText, ReTextBuilder, DEFAULT_SENTINEL,
+    NON_LINEARITIES,
+};
+pub use text_source::TextSource;
+pub use word_set::{WordSet, UNKNOWN_WORD};
@@ -1,40 +1,292 @@
-use rand::Rng;
-use std::io::Error;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Write};
+use std::iter;
 use std::time::Instant;
 
 use crate::{
-    nnetwork::{FunctionLayer, LinearLayer, Parameters, ReshapeLayer},
-    retext::char_set::{CharSet, DataSetError},
+    nnetwork::{EmbeddingLayer, FlattenLayer, FunctionLayer, InitScheme, LinearLayer, Parameters},
+    retext::{char_set::csv_field, CharSet, DataSetError, TextSource},
 };
 
-use crate::nnetwork::{loss_functions::neg_log_likelihood, CalcNode, FloatType, Layer, MultiLayer};
+use crate::nnetwork::{
+    loss_functions::{cross_entropy_from_logits, LossFuncType},
+    CalcNode, FloatType, Layer, LrSchedule, ModelArchive, MultiLayer, Optimizer, ParameterBundle,
+    Regularization, TrainingRecord,
+};
+
+/// Marks the beginning and end of a string, unless the data set contains it, see [ReTextBuilder::sentinel].
+pub const DEFAULT_SENTINEL: char = '^';
+
+type NonLinearity = fn(&CalcNode) -> CalcNode;
+
+/// The non-linearities that [ReText::save] can record, each with the formula that identifies it when given to [ReTextBuilder::non_linearity].
+pub const NON_LINEARITIES: [(&str, NonLinearity); 5] = [
+    ("Sigmoid", FunctionLayer::sigmoid),
+    ("Tanh", FunctionLayer::tanh),
+    ("ReLU", FunctionLayer::relu),
+    ("Leaky ReLU", FunctionLayer::leaky_relu),
+    ("GELU", FunctionLayer::gelu),
+];
+
+// The name of the embedding layer, used to find it in the network
+const EMBEDDING_LAYER: &str = "Embedding layer";
+
+/// How [ReText::predict] chooses the next character from the predicted probabilities.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DecodeStrategy {
+    /// Draws the character at random according to the probabilities.
+    #[default]
+    Sample,
+    /// Always picks the most probable character, making the prediction deterministic.
+    Greedy,
+}
+
+/// Stops the training when the validation loss, evaluated every `every` cycles, has not improved by at least `min_delta` for `patience` consecutive evaluations.
+pub struct EarlyStopping {
+    pub patience: usize,
+    pub min_delta: FloatType,
+    pub every: usize,
+}
+
+/// Exports the parameters together with the state of the [Optimizer] to the binary file at `path` every `every` cycles, replacing the previous checkpoint. Use [ReText::resume_from] to continue from it.
+pub struct Checkpoint {
+    pub every: usize,
+    pub path: String,
+}
+
+/// Sets up a [ReText] step by step. Settings that are not made keep their default values: a block size of 3, an embedding dimension of 2, two hidden layers of width 30, sigmoid non-linearities, no regularization, [cross_entropy_from_logits] as loss function and an automatically chosen sentinel token.
+///
+/// # Example
+/// ```
+/// use net_neurons::retext::{CharSet, ReTextBuilder};
+///
+/// let data = CharSet::new("./datasets/short_names.txt", 1., true);
+/// let retext = ReTextBuilder::new().block_size(2).hidden_layers(1).layer_width(10).build(data);
+/// ```
+pub struct ReTextBuilder {
+    _block_size: usize,
+    _embed_dim: Option<usize>,
+    _n_hidden_layers: usize,
+    _layer_width: usize,
+    _non_linearity: FunctionLayer,
+    _regularization: Regularization,
+    _loss_func: &'static LossFuncType,
+    _sentinel: Option<char>,
+}
+
+impl Default for ReTextBuilder {
+    fn default() -> Self {
+        ReTextBuilder {
+            _block_size: 3,
+            _embed_dim: Some(2),
+            _n_hidden_layers: 2,
+            _layer_width: 30,
+            _non_linearity: FunctionLayer::new(
+                &FunctionLayer::sigmoid,
+                "Sigmoid",
+                "Non-linearity layer",
+            ),
+            _regularization: Regularization::None,
+            _loss_func: &cross_entropy_from_logits,
+            _sentinel: None,
+        }
+    }
+}
+
+impl ReTextBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How many characters are used to predict the next one.
+    pub fn block_size(mut self, block_size: usize) -> Self {
+        self._block_size = block_size;
+        self
+    }
+
+    /// Every character is embedded into a vector of this dimension before entering the rest of the network.
+    pub fn embedding(mut self, dim: usize) -> Self {
+        self._embed_dim = Some(dim);
+        self
+    }
+
+    /// The one-hot encoded characters are fed directly into the first linear layer.
+    pub fn no_embedding(mut self) -> Self {
+        self._embed_dim = None;
+        self
+    }
+
+    /// The number of square layers between the input and output layers.
+    pub fn hidden_layers(mut self, n: usize) -> Self {
+        self._n_hidden_layers = n;
+        self
+    }
+
+    /// The number of neurons in every layer except the output layer.
+    pub fn layer_width(mut self, width: usize) -> Self {
+        self._layer_width = width;
+        self
+    }
+
+    /// The function applied after every linear layer except the output layer, e.g., [FunctionLayer::relu]. The `formula` describes the function when the network is printed. [ReText::save] can only record the functions in [NON_LINEARITIES], given together with their formula from there.
+    pub fn non_linearity(
+        mut self,
+        f: impl Fn(&CalcNode) -> CalcNode + 'static,
+        formula: &str,
+    ) -> Self {
+        self._non_linearity = FunctionLayer::from_fn(f, formula, "Non-linearity layer");
+        self
+    }
+
+    /// Punishes non-zero parameters, see [Regularization]. `Some(float)` is accepted as [Regularization::L2].
+    pub fn regularization(mut self, regularization: impl Into<Regularization>) -> Self {
+        self._regularization = regularization.into();
+        self
+    }
 
-/// Used to mark the beginning and end of a string
-const SENTINEL_TOKEN: &str = "^";
+    /// The loss function receives the logits, i.e., the output before softmax.
+    pub fn loss_function(mut self, f: &'static LossFuncType) -> Self {
+        self._loss_func = f;
+        self
+    }
+
+    /// The character that marks the beginning and end of every line. It must not be part of the data set. If not set, [DEFAULT_SENTINEL] is used, or the first control character missing from the data set if it contains [DEFAULT_SENTINEL].
+    pub fn sentinel(mut self, sentinel: char) -> Self {
+        self._sentinel = Some(sentinel);
+        self
+    }
+
+    /// Creates the network for the characters of the data set. Panics if the settings cannot give a working network, see [ReTextBuilder::try_build].
+    pub fn build<T: TextSource>(self, data: T) -> ReText<T> {
+        self.try_build(data).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Same as [ReTextBuilder::build] but fails with a description of the problem if any size is zero, the layers do not fit together or the sentinel token is part of the data set.
+    pub fn try_build<T: TextSource>(self, mut data: T) -> Result<ReText<T>, String> {
+        let is_known = |c: char| data.encode(&c.to_string()).is_ok();
+        let sentinel = match self._sentinel {
+            Some(c) if is_known(c) => {
+                return Err(format!("The sentinel token {c:?} is part of the data set."))
+            }
+            Some(c) => c,
+            None if !is_known(DEFAULT_SENTINEL) => DEFAULT_SENTINEL,
+            None => ('\u{1}'..='\u{1f}')
+                .find(|&c| !is_known(c))
+                .ok_or("The data set contains every control character, set a sentinel token.")?,
+        };
+        data.add_character(sentinel);
+        let n_chars = data.number_of_chars();
+        let non_linearity = self._non_linearity.formula().to_string();
+        let layers = ReText::<T>::create_layers(
+            n_chars,
+            self._block_size,
+            self._embed_dim,
+            self._n_hidden_layers,
+            self._layer_width,
+            self._non_linearity,
+        )?;
+        let mut mlp = MultiLayer::try_new(layers)?;
+        mlp.set_regularization(self._regularization);
+        mlp.set_loss_function(self._loss_func);
+        Ok(ReText {
+            _dataset: data,
+            _block_size: self._block_size,
+            _sentinel: sentinel,
+            _embed_dim: self._embed_dim,
+            _n_hidden_layers: self._n_hidden_layers,
+            _layer_width: self._layer_width,
+            _non_linearity: non_linearity,
+            _mlp: mlp,
+            _shuffle: true,
+            _rng: RefCell::new(StdRng::from_entropy()),
+            _early_stopping: None,
+            _checkpoint: None,
+            _top_p: None,
+            _decode_strategy: DecodeStrategy::Sample,
+            _training_history: Vec::new(),
+        })
+    }
+}
 
 /// Manages a network that predicts the next character in a name based on the x previous characters.
-pub struct ReText {
-    _dataset: CharSet,
+pub struct ReText<T: TextSource = CharSet> {
+    _dataset: T,
     _mlp: MultiLayer,
     _block_size: usize,
+    _sentinel: char,
+    _embed_dim: Option<usize>,
+    _n_hidden_layers: usize,
+    _layer_width: usize,
+    // The formula of the non-linearity layers
+    _non_linearity: String,
+    _shuffle: bool,
+    _rng: RefCell<StdRng>,
+    _early_stopping: Option<EarlyStopping>,
+    _checkpoint: Option<Checkpoint>,
+    _top_p: Option<FloatType>,
+    _decode_strategy: DecodeStrategy,
+    _training_history: Vec<TrainingRecord>,
+}
+
+// Splits the line into all windows of `block_size` characters together with the character following each window
+pub(super) fn windows(line: &str, block_size: usize, sentinel: char) -> Vec<(String, char)> {
+    // Pad the string with the sentinel token
+    let pad = sentinel.to_string().repeat(block_size);
+    let s = pad + line + &sentinel.to_string();
+    s.char_indices()
+        .zip(s.char_indices().skip(block_size))
+        .map(|((i, _prev), (j, next))| (s[i..j].to_string(), next))
+        .collect()
+}
+
+// Keeps the smallest set of the most probable entries whose summed probability reaches `p`, at least one entry, and renormalizes them
+fn nucleus(probabilities: &[FloatType], p: FloatType) -> Vec<FloatType> {
+    if p >= 1. {
+        return probabilities.to_vec();
+    }
+    let mut order: Vec<usize> = (0..probabilities.len()).collect();
+    order.sort_by(|&a, &b| probabilities[b].total_cmp(&probabilities[a]));
+    let mut kept = vec![0.; probabilities.len()];
+    let mut cumulative = 0.;
+    for i in order {
+        kept[i] = probabilities[i];
+        cumulative += probabilities[i];
+        if cumulative >= p {
+            break;
+        }
+    }
+    kept.iter().map(|v| v / cumulative).collect()
 }
 
-impl ReText {
+impl<T: TextSource> ReText<T> {
+    // Fails with a description of the problem if the dimensions cannot give a working network
     fn create_layers(
         n_chars: usize,
         block_size: usize,
         embed_dim: Option<usize>,
         n_hidden_layers: usize,
         layer_dim: usize,
-    ) -> Vec<Box<dyn Layer>> {
+        non_linearity: FunctionLayer,
+    ) -> Result<Vec<Box<dyn Layer>>, String> {
+        if block_size == 0 {
+            return Err("The block size must be positive.".to_string());
+        }
+        if embed_dim == Some(0) {
+            return Err("The embedding dimension must be positive.".to_string());
+        }
+        if layer_dim == 0 {
+            return Err("The layer width must be positive.".to_string());
+        }
         let mut layers: Vec<Box<dyn Layer>> = Vec::new();
-        let non_linearity = FunctionLayer::new(&FunctionLayer::sigmoid, "Sigmoid", "Non-linearity layer");
         const BIASED_LAYERS: bool = true;
 
         //Embed
         if let Some(embed_dim) = embed_dim {
-            let embed_layer = LinearLayer::new_rand(embed_dim, n_chars, false, "Embedding layer");
-            let reshape_layer = ReshapeLayer::new((block_size * embed_dim, 1), "Reshaping layer");
+            let embed_layer = EmbeddingLayer::new_rand(embed_dim, n_chars, EMBEDDING_LAYER);
+            // The embedded context has one column per character
+            let flatten_layer = FlattenLayer::new((embed_dim, block_size), "Flattening layer");
             let resize_layer = LinearLayer::new_rand(
                 layer_dim,
                 block_size * embed_dim,
@@ -42,7 +294,7 @@ impl ReText {
                 "Resizing layer (in)",
             );
             layers.push(Box::new(embed_layer));
-            layers.push(Box::new(reshape_layer));
+            layers.push(Box::new(flatten_layer));
             layers.push(Box::new(non_linearity.clone()));
             layers.push(Box::new(resize_layer));
         } else {
@@ -71,126 +323,346 @@ impl ReText {
             "Resizing layer (out)",
         )));
 
-        layers.push(Box::new(FunctionLayer::new(
-            &FunctionLayer::softmax,
-            "SoftMax",
-            "Probability producing layer",
-        )));
-
-        layers
+        Ok(layers)
     }
 
     /// The `block_size` determines how many characters are used to predict the next one.
     /// 
-    ///  After each linear layer a non-linear [FunctionLayer] is inserted. The last layer is a softmax calculation.
+    ///  After each linear layer a non-linear [FunctionLayer] is inserted, sigmoid unless another is chosen with [ReTextBuilder::non_linearity]. The network outputs logits which are trained using [cross_entropy_from_logits]. Softmax is only applied when predicting.
     pub fn new(
-        mut data: CharSet,
+        data: T,
         block_size: usize,
         embed_dim: Option<usize>,
         n_hidden_layers: usize,
         layer_dim: usize,
         regularization: Option<FloatType>,
-    ) -> ReText {
-        data.add_character(SENTINEL_TOKEN.chars().nth(0).unwrap());
-        let n_chars = data.number_of_chars();
-        let layers =
-            Self::create_layers(n_chars, block_size, embed_dim, n_hidden_layers, layer_dim);
-        let mut mlp = MultiLayer::new(layers);
-        mlp.set_regularization(regularization);
-        mlp.set_loss_function(&neg_log_likelihood);
-        ReText {
-            _dataset: data,
-            _block_size: block_size,
-            _mlp: mlp,
+    ) -> ReText<T> {
+        let builder = ReTextBuilder::new()
+            .block_size(block_size)
+            .hidden_layers(n_hidden_layers)
+            .layer_width(layer_dim)
+            .regularization(regularization);
+        match embed_dim {
+            Some(dim) => builder.embedding(dim),
+            None => builder.no_embedding(),
         }
+        .build(data)
+    }
+
+    /// The character marking the beginning and end of every line, see [ReTextBuilder::sentinel].
+    pub fn sentinel(&self) -> char {
+        self._sentinel
     }
 
+    /// If true (default) the training data is drawn from random lines and shuffled. Otherwise a contiguous block of lines is used, starting from a random line.
+    pub fn set_shuffle(&mut self, shuffle: bool) {
+        self._shuffle = shuffle;
+    }
+
+    /// Enables nucleus sampling when predicting: only the most probable characters whose probabilities together reach `p` can be chosen. A `p` close to zero always picks the most probable character. Disabled (the default) or `p >= 1` samples from all characters.
+    pub fn set_top_p(&mut self, top_p: Option<FloatType>) {
+        if let Some(p) = top_p {
+            assert!(p > 0., "Top-p must be positive.");
+        }
+        self._top_p = top_p;
+    }
+
+    /// Decides how characters are chosen when predicting. Defaults to [DecodeStrategy::Sample]. Nucleus sampling, see [ReText::set_top_p], has no effect on [DecodeStrategy::Greedy].
+    pub fn set_decode_strategy(&mut self, strategy: DecodeStrategy) {
+        self._decode_strategy = strategy;
+    }
+
+    /// Seeds the random number generator used when drawing data and sampling predictions, making both reproducible.
+    pub fn set_seed(&mut self, seed: u64) {
+        self._rng = RefCell::new(StdRng::seed_from_u64(seed));
+    }
+
+    /// If set, the training is stopped early when the validation loss stops improving and the best parameters seen are restored.
+    pub fn set_early_stopping(&mut self, early_stopping: Option<EarlyStopping>) {
+        if let Some(es) = &early_stopping {
+            assert!(es.every > 0, "Early stopping interval must be positive.");
+        }
+        self._early_stopping = early_stopping;
+    }
+
+    /// If set, the parameters are exported regularly during training so that an interrupted run can be resumed.
+    pub fn set_checkpoint(&mut self, checkpoint: Option<Checkpoint>) {
+        if let Some(cp) = &checkpoint {
+            assert!(cp.every > 0, "Checkpoint interval must be positive.");
+        }
+        self._checkpoint = checkpoint;
+    }
+
+    /// Replaces the [Optimizer] used when training, see [MultiLayer::set_optimizer].
+    pub fn set_optimizer(&mut self, optimizer: Box<dyn Optimizer>) {
+        self._mlp.set_optimizer(optimizer);
+    }
+
+    /// If true, the norms of the gradient and of the parameters are included in the [TrainingRecord]s and the verbose output, see [MultiLayer::set_track_norms].
+    pub fn set_track_norms(&mut self, track: bool) {
+        self._mlp.set_track_norms(track);
+    }
+
+    /// Loads the parameters from a checkpoint written during training, see [ReText::set_checkpoint]. The state of the [Optimizer] is restored too, if the checkpoint holds one, so the [Optimizer] must be of the same type as when the checkpoint was written.
+    pub fn resume_from(&mut self, path: &str) -> Result<(), Error> {
+        let bundle = ParameterBundle::import_binary(path)?;
+        let invalid = |e| Error::new(ErrorKind::InvalidData, e);
+        self._mlp.load_parameter_bundle(&bundle).map_err(invalid)?;
+        match bundle.optimizer_state() {
+            Some(state) => self._mlp.load_optimizer_state(state).map_err(invalid),
+            None => Ok(()),
+        }
+    }
+
+    // Draws the correlations from the held-out lines, never from the training data
+    fn validation_correlations(&self, data_size: usize) -> Vec<(CalcNode, CalcNode)> {
+        let lines = |first, count| self._dataset.validation_range(first, count);
+        self.extract_correlations(self._dataset.validation_len(), &lines, data_size)
+    }
+
+    // Returns NaN if there is no validation data
     fn validate(&self, data_size: usize) -> FloatType {
-        let data = self._dataset.validation_data();
-        let correlations = self.extract_correlations(data, data_size);
+        let correlations = self.validation_correlations(data_size);
+        if correlations.is_empty() {
+            eprintln!("Warning: No validation data available, cannot calculate the validation loss.");
+            return FloatType::NAN;
+        }
         self._mlp.loss(&correlations).value_indexed(0)
     }
 
+    /// Calculates the loss, see [MultiLayer::loss], of up to `sample_size` correlations from the validation data without changing the network. The correlations are taken in order from the first held-out line, so repeated calls give the same value. Returns NaN if there is no validation data.
+    pub fn validation_loss(&self, sample_size: usize) -> FloatType {
+        let correlations: Vec<_> = self
+            ._dataset
+            .validation_range(0, self._dataset.validation_len())
+            .flat_map(|line| self.get_all_correlations_from_str(&line))
+            .take(sample_size)
+            .collect();
+        if correlations.is_empty() {
+            return FloatType::NAN;
+        }
+        CalcNode::without_gradients(|| self._mlp.loss(&correlations)).value_indexed(0)
+    }
+
+    /// Calculates the perplexity of the lines, i.e., the exponential of the average negative log-likelihood per predicted character. The lines are split into windows just like when training. Assumes the loss function is [cross_entropy_from_logits] (the default). Returns NaN if there is nothing to predict, and an error if the lines contain a character that the text source cannot encode.
+    pub fn perplexity(&self, data: &[String]) -> Result<FloatType, DataSetError> {
+        let mut correlations = Vec::new();
+        for line in data {
+            correlations.append(&mut self.try_get_all_correlations_from_str(line)?);
+        }
+        if correlations.is_empty() {
+            return Ok(FloatType::NAN);
+        }
+        let nll_sum = self._mlp.loss_sum(&correlations).value_indexed(0);
+        Ok((nll_sum / correlations.len() as FloatType).exp())
+    }
+
     /// Trains the network for the specified number of cycles. Each cycles uses ´batch_size´ data points.
-    /// The learning rate is a constant for all cycles.
+    /// The learning rate of every cycle is decided by the [LrSchedule]. See [ReText::set_early_stopping] for how to end the training earlier and [ReText::set_checkpoint] for how to save the progress. Returns one [TrainingRecord] per cycle run.
+    ///
+    /// If a `progress` callback is given it receives the [TrainingRecord] of every cycle, otherwise the records and a summary including the validation loss are printed if `verbose` is set.
     pub fn train(
         &mut self,
         cycles: usize,
-        learning_rate: FloatType,
+        learning_rate: &dyn LrSchedule,
         batch_size: usize,
         verbose: bool,
-    ) {
+        mut progress: Option<&mut dyn FnMut(&TrainingRecord)>,
+    ) -> Vec<TrainingRecord> {
         let timer = Instant::now();
         let mut loss = 0.;
+        let mut history = Vec::with_capacity(cycles);
+        let mut best: Option<(FloatType, ParameterBundle)> = None;
+        let mut stale_checks = 0;
+        let mut cycles_run = 0;
         for n in 0..cycles {
-            let data = self._dataset.training_data();
-            let correlations = self.extract_correlations(data, batch_size);
+            let lines = |first, count| self._dataset.training_range(first, count);
+            let n_lines = self._dataset.training_len();
+            let correlations = self.extract_correlations(n_lines, &lines, batch_size);
             let timer = Instant::now();
-            loss = self._mlp.train(&correlations, learning_rate);
+            loss = self._mlp.train(&correlations, learning_rate.lr(n, cycles));
+            cycles_run = n + 1;
+            let mut record = TrainingRecord {
+                cycle: n,
+                train_loss: loss,
+                validation_loss: None,
+                duration_micros: timer.elapsed().as_micros(),
+                grad_norm: self._mlp.last_norms().map(|(grad_norm, _)| grad_norm),
+                param_norm: self._mlp.last_norms().map(|(_, param_norm)| param_norm),
+            };
+
+            if let Some(cp) = &self._checkpoint {
+                if cycles_run % cp.every == 0 {
+                    let mut bundle = self._mlp.get_parameter_bundle();
+                    bundle.set_optimizer_state(Some(self._mlp.save_optimizer_state()));
+                    if let Err(err) = bundle.export_binary(&cp.path) {
+                        eprintln!("Writing checkpoint failed: {err}");
+                    }
+                }
+            }
+
+            let mut stop = false;
+            if let Some(es) = &self._early_stopping {
+                if cycles_run % es.every == 0 {
+                    let validation = self.validate(batch_size);
+                    record.validation_loss = Some(validation);
+                    let improved = match &best {
+                        Some((best_loss, _)) => best_loss - validation > es.min_delta,
+                        None => !validation.is_nan(),
+                    };
+                    if improved {
+                        best = Some((validation, self._mlp.get_parameter_bundle()));
+                        stale_checks = 0;
+                    } else {
+                        stale_checks += 1;
+                        stop = stale_checks >= es.patience;
+                    }
+                }
+            }
 
             // Provide some per cycle stats
-            if verbose {
-                let width = (cycles as f64).log10() as usize + 1;
-                println!(
-                    "Cycle #{n: >width$}: [ loss: {:.3e}, duration: {} µs ]",
-                    loss,
-                    timer.elapsed().as_micros()
-                );
+            match progress.as_mut() {
+                Some(progress) => progress(&record),
+                None if verbose => {
+                    let width = (cycles as f64).log10() as usize + 1;
+                    let norms = match (record.grad_norm, record.param_norm) {
+                        (Some(grad), Some(param)) => {
+                            format!(", grad norm: {grad:.3e}, param norm: {param:.3e}")
+                        }
+                        _ => String::new(),
+                    };
+                    println!(
+                        "Cycle #{n: >width$}: [ loss: {:.3e}{norms}, duration: {} µs ]",
+                        loss, record.duration_micros
+                    );
+                }
+                None => (),
+            }
+            history.push(record);
+
+            if stop {
+                if verbose && progress.is_none() {
+                    println!("Validation loss stopped improving, stopping after {cycles_run} cycles.");
+                }
+                break;
             }
         }
-        println!(
-            "Trained network with {} parameters for {cycles} cycles in {} ms achieving a loss of: {:.3e}",
-            self._mlp.param_iter().map(|p| p.len()).sum::<usize>(),
-            timer.elapsed().as_millis(), loss
-        );
+        if let Some((_, bundle)) = &best {
+            self._mlp
+                .load_parameter_bundle(bundle)
+                .expect("Parameters were taken from the same network.");
+        }
+        if verbose && progress.is_none() {
+            println!(
+                "Trained network with {} parameters for {cycles_run} cycles in {} ms achieving a loss of: {:.3e}",
+                self._mlp.param_iter().map(|p| p.len()).sum::<usize>(),
+                timer.elapsed().as_millis(), loss
+            );
+            println!("Validation loss: {}", self.validate(batch_size));
+        }
+        self._training_history = history.clone();
+        history
+    }
 
-        let validation = self.validate(batch_size);
-        println!("Validation loss: {}", validation);
+    /// Returns the [TrainingRecord]s of the last call to [ReText::train].
+    pub fn training_history(&self) -> &[TrainingRecord] {
+        &self._training_history
     }
 
+    /// Writes the [ReText::training_history] to a CSV file with the header `cycle,train_loss,validation_loss,duration_micros,grad_norm,param_norm` and one row per cycle. Values that were not recorded are left empty. Will overwrite any existing file.
+    pub fn export_training_history_csv(&self, path: &str) -> std::io::Result<()> {
+        let optional = |v: Option<FloatType>| v.map_or(String::new(), |v| v.to_string());
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(
+            writer,
+            "cycle,train_loss,validation_loss,duration_micros,grad_norm,param_norm"
+        )?;
+        for record in &self._training_history {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{}",
+                record.cycle,
+                record.train_loss,
+                optional(record.validation_loss),
+                record.duration_micros,
+                optional(record.grad_norm),
+                optional(record.param_norm)
+            )?;
+        }
+        writer.flush()
+    }
+
+    fn get_all_windows_from_str(&self, line: &str) -> Vec<(String, char)> {
+        windows(line, self._block_size, self._sentinel)
+    }
+
+    // Only for lines of the text source, which can always be encoded
     fn get_all_correlations_from_str(&self, line: &str) -> Vec<(CalcNode, CalcNode)> {
-        // Pad the string with the sentinel token
-        let pad = SENTINEL_TOKEN.to_string().repeat(self._block_size);
-        let s = pad + line + SENTINEL_TOKEN;
-        s.char_indices()
-            .zip(s.char_indices().skip(self._block_size))
-            .map(|((i, _prev), (j, next))| {
-                let prev = &s[i..j];
+        self.try_get_all_correlations_from_str(line)
+            .unwrap_or_else(|err| panic!("Cannot encode the line '{line}': {err:?}"))
+    }
+
+    fn try_get_all_correlations_from_str(
+        &self,
+        line: &str,
+    ) -> Result<Vec<(CalcNode, CalcNode)>, DataSetError> {
+        self.get_all_windows_from_str(line)
+            .into_iter()
+            .map(|(prev, next)| {
                 let next = next.to_string();
-                (
-                    self._dataset
-                        .encode(prev)
-                        .expect("Cannot encode character: {prev}"),
-                    self._dataset
-                        .encode(&next)
-                        .expect("Cannot encode character: {next}"),
-                )
+                Ok((self._dataset.encode(&prev)?, self._dataset.encode(&next)?))
             })
             .collect()
     }
 
+    /// Returns the first `n` context windows and the character that follows them, taken from the training data in order. The windows are created exactly as when training, which makes this useful for inspecting the data.
+    pub fn correlations_preview(&self, n: usize) -> Vec<(String, char)> {
+        self._dataset
+            .training_range(0, self._dataset.training_len())
+            .flat_map(|line| self.get_all_windows_from_str(&line))
+            .take(n)
+            .collect()
+    }
+
     // Returns a list of all correlations in the data encoded as a tuple of Matrix(m*n) and ColumnVector(n).
-    fn extract_correlations(&self, data: &[String], n: usize) -> Vec<(CalcNode, CalcNode)> {
-        let n_lines = data.len();
+    // The `n_lines` lines are read through `lines`, which yields up to `count` lines from index
+    // `first`, so that streamed data need not be kept in memory
+    fn extract_correlations<'a>(
+        &self,
+        n_lines: usize,
+        lines: &dyn Fn(usize, usize) -> Box<dyn Iterator<Item = String> + 'a>,
+        n: usize,
+    ) -> Vec<(CalcNode, CalcNode)> {
         let mut correlations = Vec::new();
-        let start_idx = rand::thread_rng().gen_range(0..n_lines);
-        let mut line_idx = start_idx;
-        while correlations.len() < n {
-            let line = &data[line_idx];
-            correlations.append(&mut self.get_all_correlations_from_str(line));
-            line_idx += 1;
-            if line_idx >= data.len() {
-                line_idx = 0;
+        if n_lines == 0 {
+            return correlations;
+        }
+        let mut rng = self._rng.borrow_mut();
+        if self._shuffle {
+            // Lines are drawn with replacement. Every line gives at least one correlation so this always terminates.
+            while correlations.len() < n {
+                let line = lines(rng.gen_range(0..n_lines), 1)
+                    .next()
+                    .expect("The text source holds fewer lines than it reports.");
+                correlations.append(&mut self.get_all_correlations_from_str(&line));
             }
-            if line_idx == start_idx {
+            correlations.shuffle(&mut *rng);
+            correlations.truncate(n);
+            return correlations;
+        }
+        // Take a contiguous block of lines, wrapping around at most once
+        let start_idx = rng.gen_range(0..n_lines);
+        for line in lines(start_idx, n_lines - start_idx).chain(lines(0, start_idx)) {
+            if correlations.len() >= n {
                 break;
             }
+            correlations.append(&mut self.get_all_correlations_from_str(&line));
         }
         correlations.truncate(n);
         correlations
     }
 
-    /// Predicts the next `n_char` characters given the starting `seed_string´. If a sentinel token is predicted the string is terminated regardless of size.
+    /// Predicts the next `n_char` characters given the starting `seed_string´. If a sentinel token is predicted the string is terminated regardless of size. Seeds shorter than the block size are padded with sentinel tokens, which are not part of the returned string.
     pub fn predict(
         &mut self,
         seed_string: &str,
@@ -200,23 +672,175 @@ impl ReText {
             !seed_string.is_empty(),
             "Cannot extrapolate from empty string."
         );
-        // Pad the string with the sentinel token
-        let mut str = SENTINEL_TOKEN.to_string().repeat(self._block_size - 1) + seed_string;
+        // Left-pad the string with the sentinel token up to the block size, so that any seed gives a full context. Work with chars since byte offsets may not be char boundaries.
+        let padding = self._block_size.saturating_sub(seed_string.chars().count());
+        let mut chars: Vec<char> = iter::repeat_n(self._sentinel, padding)
+            .chain(seed_string.chars())
+            .collect();
+        // Replaying a recorded calculation is faster than building a new graph for every character.
+        // The recording is kept by the network until it changes.
+        let input_shape = (self._dataset.number_of_chars(), self._block_size);
+        let compiled = self._mlp.compiled_input_shape() == Some(input_shape)
+            || self._mlp.compile_forward(input_shape).is_ok();
         for _ in 0..n_char {
-            // The following line break upon non ascii input
-            let mut last = self._dataset.encode(&str[str.len() - self._block_size..])?;
-            last = self._mlp.forward(&last).collapse();
+            let context: String = chars[chars.len() - self._block_size..].iter().collect();
+            let mut last = self._dataset.encode(&context)?;
+            last = if compiled {
+                self._mlp.forward_fast(&last)
+            } else {
+                self._mlp.forward(&last)
+            };
+            last = FunctionLayer::softmax(&last);
+            last = match self._decode_strategy {
+                DecodeStrategy::Sample => {
+                    if let Some(p) = self._top_p {
+                        let kept = nucleus(&last.copy_vals(), p);
+                        last = CalcNode::new_from_shape(last.shape(), kept);
+                    }
+                    last.collapse_with(&mut *self._rng.borrow_mut())
+                }
+                DecodeStrategy::Greedy => {
+                    let mut one_hot = vec![0.; last.len()];
+                    one_hot[last.argmax()] = 1.;
+                    CalcNode::new_from_shape(last.shape(), one_hot)
+                }
+            };
             let c = self._dataset.decode_char(&last)?;
-            if c == SENTINEL_TOKEN.chars().nth(0).unwrap() {
+            if c == self._sentinel {
                 break;
             }
-            str.push(c);
+            chars.push(c);
         }
-        Ok(str[self._block_size - 1..].to_string())
+        Ok(chars[padding..].iter().collect())
     }
 
-    pub fn characters(&self) -> &[char] {
-        self._dataset.characters()
+    /// Adds the characters that are not yet known to the [TextSource] and grows the first and last layers of the network to fit them. The trained parameters of the known characters are kept and the new ones are random. The known characters keep their encoding.
+    pub fn extend_vocabulary(&mut self, new_chars: &[char]) {
+        let mut added = 0;
+        for &c in new_chars {
+            if self._dataset.encode(&c.to_string()).is_err() {
+                self._dataset.add_character(c);
+                added += 1;
+            }
+        }
+        if added == 0 {
+            return;
+        }
+
+        // Copies of the parameters of a layer, to build a new layer from
+        let copy_params = |layer: &dyn Layer| -> Vec<CalcNode> {
+            layer
+                .param_iter()
+                .map(|p| CalcNode::new_from_shape(p.shape(), p.copy_vals()))
+                .collect()
+        };
+        let first = self._mlp.get_layer(0);
+        let mut params = copy_params(first);
+        let grown_first: Box<dyn Layer> = if self._embed_dim.is_some() {
+            let mut layer = EmbeddingLayer::from_nodes(params.remove(0), first.layer_name());
+            layer.grow_vocabulary(added);
+            Box::new(layer)
+        } else {
+            let w = params.remove(0);
+            let mut layer = LinearLayer::from_nodes(w, params.pop(), first.layer_name());
+            layer.grow_cols(added, InitScheme::StandardNormal);
+            Box::new(layer)
+        };
+        self._mlp.replace_layer(0, grown_first);
+
+        let last_index = self._mlp.len() - 1;
+        let last = self._mlp.get_layer(last_index);
+        let mut params = copy_params(last);
+        let w = params.remove(0);
+        let mut grown_last = LinearLayer::from_nodes(w, params.pop(), last.layer_name());
+        grown_last.grow_rows(added, InitScheme::StandardNormal);
+        self._mlp.replace_layer(last_index, Box::new(grown_last));
+    }
+
+    /// Returns every known character together with its embedding vector, in the order of the encoding, e.g., to export the embedding to other tools. The vectors have the length of the embedding dimension.
+    ///
+    /// Panics if the network has no embedding layer.
+    pub fn embedding_table(&self) -> Vec<(char, Vec<FloatType>)> {
+        let embedding = self
+            ._mlp
+            .get_layer_by_name(EMBEDDING_LAYER)
+            .expect("The network has no embedding layer.");
+        let n_chars = self._dataset.number_of_chars();
+        (0..n_chars)
+            .map(|i| {
+                let mut one_hot = vec![0.; n_chars];
+                one_hot[i] = 1.;
+                let one_hot = CalcNode::new_col_vector(one_hot);
+                let c = self._dataset.decode_char(&one_hot).unwrap();
+                (c, embedding.forward(&one_hot).copy_vals())
+            })
+            .collect()
+    }
+
+    /// Writes the [ReText::embedding_table] to a CSV file with the header `character,dim_0,dim_1,...` followed by one row per character. Characters that need it, e.g., a comma, are quoted. Will overwrite any existing file.
+    ///
+    /// Panics if the network has no embedding layer.
+    pub fn export_embeddings_csv(&self, path: &str) -> std::io::Result<()> {
+        let table = self.embedding_table();
+        let dim = table.first().map_or(0, |(_, v)| v.len());
+        let mut writer = BufWriter::new(File::create(path)?);
+        let header: Vec<String> = iter::once("character".to_string())
+            .chain((0..dim).map(|d| format!("dim_{d}")))
+            .collect();
+        writeln!(writer, "{}", header.join(","))?;
+        for (c, vector) in &table {
+            let row: Vec<String> = iter::once(csv_field(&c.to_string()))
+                .chain(vector.iter().map(|v| v.to_string()))
+                .collect();
+            writeln!(writer, "{}", row.join(","))?;
+        }
+        writer.flush()
+    }
+
+    /// Returns the `k` characters whose embedding vectors are most similar to the one of `ch`, measured by cosine similarity and sorted with the most similar first. The character itself is not included.
+    ///
+    /// Panics if the network has no embedding layer or if the character cannot be encoded.
+    pub fn embedding_neighbors(&self, ch: char, k: usize) -> Vec<(char, FloatType)> {
+        let embedded = self.embedding_table();
+        let encoded = match self._dataset.encode(&ch.to_string()) {
+            Ok(encoded) => encoded,
+            Err(err) => panic!("Received {err:?} while looking up the embedding of '{ch}'."),
+        };
+        let target = self
+            ._mlp
+            .get_layer_by_name(EMBEDDING_LAYER)
+            .unwrap()
+            .forward(&encoded)
+            .copy_vals();
+
+        let norm = |v: &[FloatType]| v.iter().map(|x| x * x).sum::<FloatType>().sqrt();
+        let mut neighbors: Vec<(char, FloatType)> = embedded
+            .into_iter()
+            .filter(|(c, _)| *c != ch)
+            .map(|(c, v)| {
+                let dot: FloatType = v.iter().zip(&target).map(|(a, b)| a * b).sum();
+                let norms = norm(&v) * norm(&target);
+                (c, if norms > 0. { dot / norms } else { 0. })
+            })
+            .collect();
+        neighbors.sort_by(|a, b| b.1.total_cmp(&a.1));
+        neighbors.truncate(k);
+        neighbors
+    }
+
+    /// Returns a copy of the current values of all parameters.
+    pub fn get_parameter_bundle(&self) -> ParameterBundle {
+        self._mlp.get_parameter_bundle()
+    }
+
+    /// Overwrites all parameters with the values from the bundle. Fails if the bundle does not fit the network.
+    pub fn load_parameter_bundle(&mut self, bundle: &ParameterBundle) -> Result<(), String> {
+        self._mlp.load_parameter_bundle(bundle)
+    }
+
+    /// The [TextSource] the network was created for, e.g., to decode the predictions.
+    pub fn text_source(&self) -> &T {
+        &self._dataset
     }
 
     pub fn export_parameters(&self, filename: &str) -> std::io::Result<String> {
@@ -226,4 +850,764 @@ impl ReText {
     pub fn import_parameters(&mut self, filename: &str) -> Result<(), Error> {
         self._mlp.import_parameters(filename)
     }
+
+    /// Saves the parameters together with the architecture of the network in a [ModelArchive], so that [ReText::load] can rebuild it. Will overwrite any existing file! Fails without writing anything if the non-linearity is not one of [NON_LINEARITIES].
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let non_linearity = NON_LINEARITIES
+            .iter()
+            .position(|(formula, _)| *formula == self._non_linearity)
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "The non-linearity '{}' is not one of NON_LINEARITIES and cannot be saved.",
+                        self._non_linearity
+                    ),
+                )
+            })?;
+        let mut settings = vec![
+            ("n_chars", self._dataset.number_of_chars()),
+            ("block_size", self._block_size),
+            ("sentinel", self._sentinel as usize),
+            ("hidden_layers", self._n_hidden_layers),
+            ("layer_width", self._layer_width),
+            ("non_linearity", non_linearity),
+        ];
+        if let Some(embed_dim) = self._embed_dim {
+            settings.push(("embed_dim", embed_dim));
+        }
+        ModelArchive::new(&settings, &self._mlp).save(path)
+    }
+
+    /// Rebuilds a network saved by [ReText::save] for the data set and loads its parameters. The data set must have the same characters as the one used when saving. Settings that are not part of the architecture, e.g., the regularization, get their default values.
+    pub fn load(path: &str, data: T) -> Result<ReText<T>, Error> {
+        let archive = ModelArchive::load(path)?;
+        let setting = |name: &str| {
+            archive.setting(name).ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("The archive {path} lacks the setting {name}."),
+                )
+            })
+        };
+        let builder = ReTextBuilder::new()
+            .block_size(setting("block_size")?)
+            .hidden_layers(setting("hidden_layers")?)
+            .layer_width(setting("layer_width")?)
+            .sentinel(char::from_u32(setting("sentinel")? as u32).ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    "The sentinel token is not a character.",
+                )
+            })?);
+        let (formula, non_linearity) =
+            *NON_LINEARITIES.get(setting("non_linearity")?).ok_or_else(|| {
+                Error::new(ErrorKind::InvalidData, "The non-linearity is not a known one.")
+            })?;
+        let builder = builder.non_linearity(non_linearity, formula);
+        let builder = match archive.setting("embed_dim") {
+            Some(dim) => builder.embedding(dim),
+            None => builder.no_embedding(),
+        };
+        let mut retext = builder
+            .try_build(data)
+            .map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+        let n_chars = setting("n_chars")?;
+        if retext._dataset.number_of_chars() != n_chars {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "The network was saved for {n_chars} characters but the data set has {}.",
+                    retext._dataset.number_of_chars()
+                ),
+            ));
+        }
+        archive
+            .load_into(&mut retext._mlp)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        Ok(retext)
+    }
+}
+
+impl ReText<CharSet> {
+    pub fn characters(&self) -> &[char] {
+        self._dataset.characters()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::nnetwork::{Adam, Constant};
+    use crate::retext::char_set::parse_csv;
+
+    #[test]
+    fn shuffled_extraction_exceeding_data_size() {
+        let data = CharSet::new("./datasets/short_names.txt", 1., true);
+        let mut retext = ReText::new(data, 2, None, 0, 5, None);
+        retext.set_seed(1);
+        // Only the last line, "cleo", is not used for training
+        let n_lines = retext._dataset.validation_len();
+        let lines = |first, count| retext._dataset.validation_range(first, count);
+        let correlations = retext.extract_correlations(n_lines, &lines, 10);
+        assert_eq!(correlations.len(), 10);
+        retext.set_shuffle(false);
+        let n_lines = retext._dataset.validation_len();
+        let lines = |first, count| retext._dataset.validation_range(first, count);
+        let correlations = retext.extract_correlations(n_lines, &lines, 10);
+        assert_eq!(correlations.len(), 5);
+    }
+
+    #[test]
+    fn early_stopping_restores_best_parameters() {
+        let new_retext = || {
+            let data = CharSet::new("./datasets/short_names.txt", 1., true);
+            let mut retext = ReText::new(data, 2, Some(2), 0, 5, None);
+            retext.set_seed(3);
+            retext
+        };
+        let mut reference = new_retext();
+        let mut retext = new_retext();
+        retext
+            .load_parameter_bundle(&reference.get_parameter_bundle())
+            .unwrap();
+        // Nothing counts as an improvement after the first check which is made after the first cycle
+        retext.set_early_stopping(Some(EarlyStopping {
+            patience: 2,
+            min_delta: FloatType::INFINITY,
+            every: 1,
+        }));
+        retext.train(10, &Constant(0.1), 4, false, None);
+        reference.train(1, &Constant(0.1), 4, false, None);
+        assert_eq!(retext.get_parameter_bundle(), reference.get_parameter_bundle());
+    }
+
+    #[test]
+    fn streamed_data_trains_like_data_in_memory() {
+        let path = "./datasets/short_names.txt";
+        let new_retext = |data| {
+            let mut retext = ReText::new(data, 2, Some(2), 0, 5, None);
+            retext.set_seed(3);
+            retext
+        };
+        let mut reference = new_retext(CharSet::new(path, 0.8, true));
+        let mut retext = new_retext(CharSet::from_file_streaming(path, 0.8, true));
+        retext
+            .load_parameter_bundle(&reference.get_parameter_bundle())
+            .unwrap();
+        let history = retext.train(3, &Constant(0.1), 4, false, None);
+        let reference_history = reference.train(3, &Constant(0.1), 4, false, None);
+        let train_losses =
+            |h: &[TrainingRecord]| h.iter().map(|r| r.train_loss).collect::<Vec<_>>();
+        assert_eq!(train_losses(&history), train_losses(&reference_history));
+        assert!(retext.validation_loss(10).is_finite());
+        assert_eq!(retext.validation_loss(10), reference.validation_loss(10));
+        assert_eq!(retext.get_parameter_bundle(), reference.get_parameter_bundle());
+    }
+
+    #[test]
+    fn training_returns_one_record_per_cycle() {
+        let data = CharSet::new("./datasets/short_names.txt", 1., true);
+        let mut retext = ReText::new(data, 2, Some(2), 0, 5, None);
+        retext.set_seed(3);
+        let history = retext.train(3, &Constant(0.1), 4, false, None);
+        assert_eq!(history.len(), 3);
+        assert!(history.windows(2).all(|w| w[0].cycle < w[1].cycle));
+        assert!(history.iter().all(|r| r.train_loss.is_finite()));
+        // Without early stopping the validation loss is never evaluated
+        assert!(history.iter().all(|r| r.validation_loss.is_none()));
+    }
+
+    #[test]
+    fn training_can_be_resumed_from_checkpoint() {
+        let path = std::env::temp_dir()
+            .join(format!("retext_checkpoint_{}.bin", std::process::id()))
+            .to_str()
+            .unwrap()
+            .to_string();
+        let new_retext = || {
+            let data = CharSet::new("./datasets/short_names.txt", 1., true);
+            ReText::new(data, 2, Some(2), 0, 5, None)
+        };
+        let mut retext = new_retext();
+        retext.set_seed(3);
+        retext.set_checkpoint(Some(Checkpoint {
+            every: 2,
+            path: path.clone(),
+        }));
+        retext.train(4, &Constant(0.1), 4, false, None);
+        let mut resumed = new_retext();
+        resumed.resume_from(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // The last cycle was checkpointed
+        assert_eq!(resumed.get_parameter_bundle(), retext.get_parameter_bundle());
+    }
+
+    #[test]
+    fn resumed_training_keeps_optimizer_state() {
+        let path = std::env::temp_dir()
+            .join(format!("retext_optimizer_checkpoint_{}.bin", std::process::id()))
+            .to_str()
+            .unwrap()
+            .to_string();
+        let new_retext = || {
+            let data = CharSet::new("./datasets/short_names.txt", 1., true);
+            let mut retext = ReText::new(data, 2, Some(2), 0, 5, None);
+            retext.set_optimizer(Box::new(Adam::default()));
+            retext
+        };
+        let mut retext = new_retext();
+        retext.set_seed(3);
+        retext.set_checkpoint(Some(Checkpoint {
+            every: 3,
+            path: path.clone(),
+        }));
+        retext.train(3, &Constant(0.01), 4, false, None);
+        let mut resumed = new_retext();
+        resumed.resume_from(&path).unwrap();
+        let mut restarted = new_retext();
+        restarted.resume_from(&path).unwrap();
+        restarted._mlp.reset_optimizer();
+        std::fs::remove_file(&path).unwrap();
+
+        // The next step is the same as if the training had never been interrupted
+        for r in [&mut retext, &mut resumed, &mut restarted] {
+            r.set_checkpoint(None);
+            r.set_seed(5);
+            r.train(1, &Constant(0.01), 4, false, None);
+        }
+        assert_eq!(resumed.get_parameter_bundle(), retext.get_parameter_bundle());
+        assert_ne!(restarted.get_parameter_bundle(), retext.get_parameter_bundle());
+    }
+
+    // A fixed alphabet and a few lines, no files involved
+    struct MockSource {
+        chars: Vec<char>,
+        lines: Vec<String>,
+    }
+
+    impl TextSource for MockSource {
+        fn training_data(&self) -> &[String] {
+            &self.lines
+        }
+
+        fn validation_data(&self) -> &[String] {
+            &self.lines
+        }
+
+        fn encode(&self, s: &str) -> Result<CalcNode, DataSetError> {
+            let n_cols = s.chars().count();
+            let mut vals = vec![0.; self.chars.len() * n_cols];
+            for (col, c) in s.chars().enumerate() {
+                let row = self
+                    .chars
+                    .iter()
+                    .position(|&k| k == c)
+                    .ok_or(DataSetError::Encoding(c))?;
+                vals[row * n_cols + col] = 1.;
+            }
+            Ok(CalcNode::new_from_shape((self.chars.len(), n_cols), vals))
+        }
+
+        fn decode_char(&self, vector: &CalcNode) -> Result<char, DataSetError> {
+            Ok(self.chars[vector.argmax()])
+        }
+
+        fn number_of_chars(&self) -> usize {
+            self.chars.len()
+        }
+
+        fn add_character(&mut self, c: char) {
+            if !self.chars.contains(&c) {
+                self.chars.push(c);
+            }
+        }
+    }
+
+    #[test]
+    fn training_on_custom_text_source() {
+        let source = MockSource {
+            chars: vec!['a', 'b'],
+            lines: vec!["abba".to_string(), "baab".to_string()],
+        };
+        let mut retext = ReText::new(source, 2, Some(2), 0, 5, None);
+        retext.set_seed(3);
+        // The sentinel token is added to the alphabet
+        assert_eq!(retext._dataset.number_of_chars(), 3);
+        let history = retext.train(1, &Constant(0.1), 4, false, None);
+        assert_eq!(history.len(), 1);
+        assert!(history[0].train_loss.is_finite());
+    }
+
+    // Zeroes the weights of the output layer so that the output only depends on the biases
+    fn rig_output_layer(retext: &mut ReText, biases: &[FloatType]) {
+        let mut params: Vec<&mut CalcNode> = retext._mlp.param_iter_mut().collect();
+        let n_params = params.len();
+        let zeros = vec![0.; params[n_params - 2].len()];
+        params[n_params - 2].set_vals(&zeros);
+        params[n_params - 1].set_vals(biases);
+    }
+
+    #[test]
+    fn greedy_decoding_picks_most_probable_character() {
+        let data = CharSet::new("./datasets/short_names.txt", 1., true);
+        let mut retext = ReText::new(data, 2, Some(2), 0, 5, None);
+        let favoured = retext.characters().iter().position(|&c| c == 'a').unwrap();
+        let mut biases = vec![0.; retext.characters().len()];
+        biases[favoured] = 1.;
+        rig_output_layer(&mut retext, &biases);
+
+        retext.set_decode_strategy(DecodeStrategy::Greedy);
+        for _ in 0..3 {
+            assert_eq!(retext.predict("an", 4).unwrap(), "anaaaa");
+        }
+    }
+
+    #[test]
+    fn perplexity_of_uniform_model_is_vocabulary_size() {
+        let data = CharSet::new("./datasets/short_names.txt", 1., true);
+        let mut retext = ReText::new(data, 2, Some(2), 0, 5, None);
+        let n_chars = retext.characters().len();
+        rig_output_layer(&mut retext, &vec![0.; n_chars]);
+        let lines = vec!["anna".to_string(), "bob".to_string()];
+        assert_approx_eq!(retext.perplexity(&lines).unwrap(), n_chars as FloatType);
+        assert!(retext.perplexity(&[]).unwrap().is_nan());
+    }
+
+    #[test]
+    fn perplexity_of_unknown_characters_is_an_error() {
+        let data = CharSet::new("./datasets/short_names.txt", 1., true);
+        let retext = ReText::new(data, 2, Some(2), 0, 5, None);
+        let lines = vec!["anna".to_string(), "b\u{1F600}b".to_string()];
+        assert_eq!(retext.perplexity(&lines), Err(DataSetError::Encoding('\u{1F600}')));
+    }
+
+    #[test]
+    fn progress_callback_receives_every_cycle() {
+        let data = CharSet::new("./datasets/short_names.txt", 1., true);
+        let mut retext = ReText::new(data, 2, Some(2), 0, 5, None);
+        let mut cycles = Vec::new();
+        let mut progress = |record: &TrainingRecord| cycles.push(record.cycle);
+        retext.train(3, &Constant(0.1), 4, true, Some(&mut progress));
+        assert_eq!(cycles, [0, 1, 2]);
+    }
+
+    #[test]
+    fn nearest_neighbors_in_embedding_space() {
+        let source = MockSource {
+            chars: vec!['a', 'b', 'c'],
+            lines: vec!["abc".to_string()],
+        };
+        let mut retext = ReText::new(source, 2, Some(2), 0, 5, None);
+        // The columns are the embeddings of 'a', 'b', 'c' and the sentinel token
+        let table = retext._mlp.param_iter_mut().next().unwrap();
+        table.set_vals(&[1., 0.9, 0., -1., 0., 0.1, 1., 0.]);
+
+        let neighbors = retext.embedding_neighbors('a', 2);
+        assert_eq!(neighbors.len(), 2);
+        assert_eq!(neighbors[0].0, 'b');
+        assert_approx_eq!(neighbors[0].1, 0.9 / (0.82 as FloatType).sqrt());
+        assert_eq!(neighbors[1], ('c', 0.));
+        // Asking for more than available gives every other character
+        let neighbors = retext.embedding_neighbors('^', 10);
+        assert_eq!(neighbors.len(), 3);
+        assert_eq!(neighbors.last().unwrap(), &('a', -1.));
+    }
+
+    #[test]
+    fn embedding_table_has_every_character() {
+        let data = CharSet::new("./datasets/short_names.txt", 1., true);
+        let retext = ReTextBuilder::new().block_size(2).embedding(3).build(data);
+        let table = retext.embedding_table();
+        assert_eq!(table.len(), retext.text_source().number_of_chars());
+        assert!(table.iter().all(|(_, v)| v.len() == 3));
+        let chars: Vec<char> = table.iter().map(|(c, _)| *c).collect();
+        assert_eq!(chars, retext.text_source().characters());
+    }
+
+    #[test]
+    fn csv_export_of_embeddings_and_history() {
+        let source = MockSource {
+            chars: vec!['a', ','],
+            lines: vec!["a,a".to_string()],
+        };
+        let mut retext = ReText::new(source, 2, Some(2), 0, 5, None);
+        retext.set_track_norms(true);
+        retext.train(2, &Constant(0.1), 4, false, None);
+        let path = std::env::temp_dir()
+            .join(format!("retext_csv_{}.csv", std::process::id()))
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        retext.export_embeddings_csv(&path).unwrap();
+        let embeddings = parse_csv(&std::fs::read_to_string(&path).unwrap());
+        assert_eq!(embeddings[0], ["character", "dim_0", "dim_1"]);
+        assert_eq!(embeddings.len(), 1 + 3);
+        // The comma is quoted and read back as one field
+        let (c, vector) = &retext.embedding_table()[1];
+        assert_eq!(embeddings[2][0], c.to_string());
+        let parsed: Vec<FloatType> = embeddings[2][1..]
+            .iter()
+            .map(|v| v.parse().unwrap())
+            .collect();
+        assert_eq!(&parsed, vector);
+
+        retext.export_training_history_csv(&path).unwrap();
+        let history = parse_csv(&std::fs::read_to_string(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            history[0],
+            ["cycle", "train_loss", "validation_loss", "duration_micros", "grad_norm", "param_norm"]
+        );
+        assert_eq!(history.len(), 1 + 2);
+        let record = &retext.training_history()[1];
+        assert_eq!(history[2][0], "1");
+        assert_eq!(history[2][1].parse::<FloatType>().unwrap(), record.train_loss);
+        assert_eq!(history[2][2], "");
+        assert_eq!(history[2][4].parse::<FloatType>().ok(), record.grad_norm);
+    }
+
+    #[test]
+    fn validation_loss_is_repeatable() {
+        let data = CharSet::new("./datasets/short_names.txt", 0.5, true);
+        let mut trained = ReText::new(data, 2, Some(2), 0, 5, None);
+        trained.train(2, &Constant(0.1), 4, false, None);
+        let data = CharSet::new("./datasets/short_names.txt", 0.5, true);
+        let mut retext = ReText::new(data, 2, Some(2), 0, 5, None);
+        retext
+            .load_parameter_bundle(&trained.get_parameter_bundle())
+            .unwrap();
+        let loss = retext.validation_loss(20);
+        assert!(loss.is_finite());
+        assert_eq!(retext.validation_loss(20), loss);
+        assert_eq!(retext.get_parameter_bundle(), trained.get_parameter_bundle());
+
+        let empty = MockSource {
+            chars: vec!['a', 'b', '^'],
+            lines: Vec::new(),
+        };
+        assert!(ReText::new(empty, 2, None, 0, 5, None).validation_loss(20).is_nan());
+    }
+
+    #[test]
+    fn saved_network_is_rebuilt_without_architecture() {
+        let data = CharSet::new("./datasets/short_names.txt", 1., true);
+        let mut retext = ReTextBuilder::new()
+            .block_size(2)
+            .embedding(3)
+            .hidden_layers(1)
+            .layer_width(6)
+            .build(data);
+        retext.set_seed(3);
+        retext.train(2, &Constant(0.1), 4, false, None);
+        let path = std::env::temp_dir()
+            .join(format!("retext_archive_{}.bin", std::process::id()))
+            .to_str()
+            .unwrap()
+            .to_string();
+        retext.save(&path).unwrap();
+
+        let data = CharSet::new("./datasets/short_names.txt", 1., true);
+        let loaded = ReText::load(&path, data);
+        std::fs::remove_file(&path).unwrap();
+        let mut loaded = loaded.unwrap();
+        assert_eq!(loaded.get_parameter_bundle(), retext.get_parameter_bundle());
+        retext.set_decode_strategy(DecodeStrategy::Greedy);
+        loaded.set_decode_strategy(DecodeStrategy::Greedy);
+        assert_eq!(
+            loaded.predict("an", 8).unwrap(),
+            retext.predict("an", 8).unwrap()
+        );
+    }
+
+    #[test]
+    fn archive_with_invalid_architecture_is_an_error() {
+        let data = CharSet::new("./datasets/short_names.txt", 1., true);
+        let n_chars = data.number_of_chars();
+        let retext = ReText::new(data, 2, None, 1, 5, None);
+        let path = std::env::temp_dir()
+            .join(format!("retext_corrupt_archive_{}.bin", std::process::id()))
+            .to_str()
+            .unwrap()
+            .to_string();
+        // A layer width of zero cannot be built
+        let settings = [
+            ("n_chars", n_chars),
+            ("block_size", 2),
+            ("sentinel", DEFAULT_SENTINEL as usize),
+            ("hidden_layers", 1),
+            ("layer_width", 0),
+            ("non_linearity", 0),
+        ];
+        ModelArchive::new(&settings, &retext._mlp).save(&path).unwrap();
+
+        let data = CharSet::new("./datasets/short_names.txt", 1., true);
+        let loaded = ReText::load(&path, data);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.err().map(|err| err.kind()), Some(ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn chosen_non_linearity_is_used_throughout() {
+        let data = CharSet::new("./datasets/short_names.txt", 1., true);
+        let retext = ReTextBuilder::new()
+            .hidden_layers(2)
+            .non_linearity(FunctionLayer::relu, "ReLU")
+            .build(data);
+        let functions: Vec<String> = (0..retext._mlp.len())
+            .map(|i| retext._mlp.get_layer(i))
+            .filter(|layer| layer.layer_type() == "FunctionLayer")
+            .map(|layer| layer.to_string())
+            .collect();
+        // After the embedding, the resizing layer and every hidden layer
+        assert_eq!(functions.len(), 4);
+        assert!(functions.iter().all(|f| f.contains("(ReLU)")), "{functions:?}");
+    }
+
+    #[test]
+    fn saved_non_linearity_is_restored() {
+        let data = CharSet::new("./datasets/short_names.txt", 1., true);
+        let mut retext = ReTextBuilder::new()
+            .hidden_layers(1)
+            .non_linearity(FunctionLayer::relu, "ReLU")
+            .build(data);
+        let path = std::env::temp_dir()
+            .join(format!("retext_relu_archive_{}.bin", std::process::id()))
+            .to_str()
+            .unwrap()
+            .to_string();
+        retext.save(&path).unwrap();
+
+        let data = CharSet::new("./datasets/short_names.txt", 1., true);
+        let loaded = ReText::load(&path, data);
+        std::fs::remove_file(&path).unwrap();
+        let mut loaded = loaded.unwrap();
+        let formulas = |retext: &ReText| -> Vec<String> {
+            (0..retext._mlp.len())
+                .map(|i| retext._mlp.get_layer(i))
+                .filter(|layer| layer.layer_type() == "FunctionLayer")
+                .map(|layer| layer.to_string())
+                .collect()
+        };
+        assert!(formulas(&loaded).iter().all(|f| f.contains("(ReLU)")));
+        assert_eq!(formulas(&loaded), formulas(&retext));
+        retext.set_decode_strategy(DecodeStrategy::Greedy);
+        loaded.set_decode_strategy(DecodeStrategy::Greedy);
+        assert_eq!(
+            loaded.predict("an", 8).unwrap(),
+            retext.predict("an", 8).unwrap()
+        );
+    }
+
+    #[test]
+    fn custom_non_linearity_cannot_be_saved() {
+        let data = CharSet::new("./datasets/short_names.txt", 1., true);
+        let retext = ReTextBuilder::new()
+            .non_linearity(|inp: &CalcNode| inp.tanh() * CalcNode::new_scalar(2.), "2*tanh")
+            .build(data);
+        let path = std::env::temp_dir()
+            .join(format!("retext_custom_archive_{}.bin", std::process::id()))
+            .to_str()
+            .unwrap()
+            .to_string();
+        let err = retext.save(&path).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert!(!std::path::Path::new(&path).exists());
+    }
+
+    #[test]
+    fn invalid_dimensions_fail_at_construction() {
+        let data = || CharSet::new("./datasets/short_names.txt", 1., true);
+        let err = ReTextBuilder::new().embedding(0).try_build(data()).err();
+        assert_eq!(
+            err.as_deref(),
+            Some("The embedding dimension must be positive.")
+        );
+        let err = ReTextBuilder::new().layer_width(0).try_build(data()).err();
+        assert_eq!(err.as_deref(), Some("The layer width must be positive."));
+        assert!(ReTextBuilder::new()
+            .block_size(4)
+            .embedding(3)
+            .try_build(data())
+            .is_ok());
+    }
+
+    #[test]
+    fn sentinel_avoids_characters_of_the_data_set() {
+        let source = || MockSource {
+            chars: vec!['^', 'a', 'b'],
+            lines: vec!["a^b".to_string(), "b^a".to_string()],
+        };
+        let mut retext = ReText::new(source(), 2, Some(2), 0, 5, None);
+        assert_eq!(retext.sentinel(), '\u{1}');
+        assert_eq!(retext._dataset.number_of_chars(), 4);
+        assert_eq!(
+            retext.get_all_windows_from_str("a^")[..2],
+            [("\u{1}\u{1}".to_string(), 'a'), ("\u{1}a".to_string(), '^')]
+        );
+        retext.set_seed(3);
+        retext.train(2, &Constant(0.1), 4, false, None);
+        let prediction = retext.predict("a^", 10).unwrap();
+        assert!(prediction.starts_with("a^"));
+        assert!(!prediction.contains('\u{1}'));
+
+        let err = ReTextBuilder::new().sentinel('a').try_build(source()).err();
+        assert_eq!(
+            err.as_deref(),
+            Some("The sentinel token 'a' is part of the data set.")
+        );
+    }
+
+    #[test]
+    fn extended_vocabulary_keeps_known_characters() {
+        let source = MockSource {
+            chars: vec!['a', 'b'],
+            lines: vec!["abba".to_string(), "baab".to_string()],
+        };
+        let mut retext = ReText::new(source, 2, Some(2), 0, 5, None);
+        retext.set_seed(3);
+        retext.train(1, &Constant(0.1), 4, false, None);
+        let before = retext.get_parameter_bundle();
+        let encoded_before = retext._dataset.encode("ab^").unwrap().copy_vals();
+
+        // The sentinel token makes the third character
+        retext.extend_vocabulary(&['a', 'c']);
+        assert_eq!(retext._dataset.number_of_chars(), 4);
+        let encoded = retext._dataset.encode("ab^").unwrap();
+        assert_eq!(encoded.shape(), (4, 3));
+        // The new row of the one-hot matrix is appended
+        assert_eq!(&encoded.copy_vals()[..9], &encoded_before[..]);
+        let c = CalcNode::new_col_vector(vec![0., 0., 0., 1.]);
+        assert_eq!(retext._dataset.decode_char(&c), Ok('c'));
+
+        // The embedding of the known characters and the output weights and biases are kept
+        let after = retext.get_parameter_bundle();
+        let (table_before, table_after) = (&before.layer_parameters(0)[0], &after.layer_parameters(0)[0]);
+        for row in 0..2 {
+            assert_eq!(table_after[row * 4..row * 4 + 3], table_before[row * 3..row * 3 + 3]);
+        }
+        let last = before.len() - 1;
+        let (w_before, w_after) = (&before.layer_parameters(last)[0], &after.layer_parameters(last)[0]);
+        assert_eq!(w_after[..w_before.len()], w_before[..]);
+        assert_eq!(after.layer_parameters(last)[1][..3], before.layer_parameters(last)[1][..]);
+
+        retext.train(1, &Constant(0.1), 4, false, None);
+        assert!(retext.predict("c", 3).is_ok());
+    }
+
+    #[test]
+    fn validation_uses_held_out_lines() {
+        // "anna" and "bob" are used for training, the other four names for validation
+        let data = CharSet::new("./datasets/short_names.txt", 0.5, true);
+        assert_eq!(data.training_data(), &["anna", "bob"]);
+        let mut retext = ReText::new(data, 2, Some(2), 0, 5, None);
+        retext.set_shuffle(false);
+        // Asking for more than available gives all correlations, one per character plus the terminating sentinel
+        assert_eq!(retext.validation_correlations(100).len(), 4 + 4 + 4 + 5);
+        assert!(retext.validate(100).is_finite());
+    }
+
+    #[test]
+    fn preview_of_padded_windows() {
+        let data = CharSet::new("./datasets/short_names.txt", 1., true);
+        let retext = ReText::new(data, 2, Some(2), 0, 5, None);
+        let expected = [("^^", 'a'), ("^a", 'n'), ("an", 'n'), ("nn", 'a'), ("na", '^'), ("^^", 'b')];
+        let preview = retext.correlations_preview(expected.len());
+        assert_eq!(
+            preview,
+            expected.map(|(prev, next)| (prev.to_string(), next))
+        );
+    }
+
+    #[test]
+    fn builder_and_new_give_same_network() {
+        let data = || CharSet::new("./datasets/short_names.txt", 1., true);
+        let retext = ReText::new(data(), 2, Some(3), 1, 8, Some(0.1));
+        let built = ReTextBuilder::new()
+            .layer_width(8)
+            .embedding(3)
+            .hidden_layers(1)
+            .block_size(2)
+            .regularization(Some(0.1))
+            .build(data());
+        assert_eq!(built._mlp.len(), retext._mlp.len());
+        let n_params = |r: &ReText| r._mlp.param_iter().map(|p| p.len()).sum::<usize>();
+        assert_eq!(n_params(&built), n_params(&retext));
+        assert_eq!(built.characters(), retext.characters());
+    }
+
+    #[test]
+    fn nucleus_keeps_most_probable() {
+        let probabilities = [0.02, 0.5, 0.12, 0.3, 0.06];
+        let kept = nucleus(&probabilities, 0.9);
+        let survivors: Vec<usize> = (0..kept.len()).filter(|&i| kept[i] > 0.).collect();
+        assert_eq!(survivors, &[1, 2, 3]);
+        assert_approx_eq!(kept.iter().sum::<FloatType>(), 1.);
+        assert_approx_eq!(kept[1], 0.5 / 0.92);
+        // At least the most probable one is always kept
+        assert_eq!(nucleus(&probabilities, 1e-9), &[0., 1., 0., 0., 0.]);
+        assert_eq!(nucleus(&probabilities, 1.), probabilities);
+    }
+
+    #[test]
+    fn seeded_predictions_are_reproducible() {
+        let data = CharSet::new("./datasets/short_names.txt", 1., true);
+        let mut retext = ReText::new(data, 2, Some(2), 1, 5, None);
+        retext.set_top_p(Some(0.9));
+        let mut predict = |seed| {
+            retext.set_seed(seed);
+            (0..5)
+                .map(|_| retext.predict("a", 12).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let first = predict(11);
+        predict(12);
+        assert_eq!(predict(11), first);
+    }
+
+    #[test]
+    fn recorded_prediction_follows_training() {
+        let new_retext = || {
+            let data = CharSet::new("./datasets/short_names.txt", 1., true);
+            let mut retext = ReText::new(data, 2, Some(2), 1, 5, None);
+            retext.set_decode_strategy(DecodeStrategy::Greedy);
+            retext.set_seed(3);
+            retext
+        };
+        let mut retext = new_retext();
+        retext.predict("an", 8).unwrap();
+        let input_shape = (retext._dataset.number_of_chars(), 2);
+        assert_eq!(retext._mlp.compiled_input_shape(), Some(input_shape));
+        // The recording is reused and sees the trained parameters
+        retext.train(3, &Constant(0.5), 4, false, None);
+        assert_eq!(retext._mlp.compiled_input_shape(), Some(input_shape));
+        let mut reference = new_retext();
+        reference
+            .load_parameter_bundle(&retext.get_parameter_bundle())
+            .unwrap();
+        assert_eq!(retext.predict("an", 8).unwrap(), reference.predict("an", 8).unwrap());
+    }
+
+    #[test]
+    fn predict_from_seed_shorter_than_block_size() {
+        let data = CharSet::new("./datasets/short_names.txt", 1., true);
+        let mut retext = ReText::new(data, 3, Some(2), 1, 5, None);
+        let prediction = retext.predict("b", 6).unwrap();
+        assert!(prediction.starts_with('b'), "{prediction}");
+        assert!(!prediction.contains(retext.sentinel()));
+        let prediction = retext.predict("bella", 6).unwrap();
+        assert!(prediction.starts_with("bella"), "{prediction}");
+    }
+
+    #[test]
+    fn predict_from_multibyte_seed() {
+        let mut data = CharSet::new("./datasets/test.txt", 1., true);
+        data.add_character('å');
+        data.add_character('l');
+        let mut retext = ReText::new(data, 2, Some(2), 1, 5, None);
+        let prediction = retext.predict("ål", 10).unwrap();
+        assert!(prediction.starts_with("ål"));
+    }
 }
@@ -0,0 +1,50 @@
+use crate::{nnetwork::CalcNode, retext::DataSetError};
+
+/// A source of lines of text for [crate::retext::ReText], which also decides how characters are encoded as one-hot [CalcNode]s. Implemented by [crate::retext::CharSet].
+pub trait TextSource {
+    /// The lines used for training.
+    fn training_data(&self) -> &[String];
+
+    /// The held-out lines used for validation.
+    fn validation_data(&self) -> &[String];
+
+    /// The number of lines used for training. Sources that do not keep their lines in memory must override this together with [TextSource::training_range].
+    fn training_len(&self) -> usize {
+        self.training_data().len()
+    }
+
+    /// The number of held-out lines. Sources that do not keep their lines in memory must override this together with [TextSource::validation_range].
+    fn validation_len(&self) -> usize {
+        self.validation_data().len()
+    }
+
+    /// Iterates over up to `count` lines used for training, starting with the line at index `first`.
+    fn training_range(
+        &self,
+        first: usize,
+        count: usize,
+    ) -> Box<dyn Iterator<Item = String> + '_> {
+        Box::new(self.training_data().iter().skip(first).take(count).cloned())
+    }
+
+    /// Iterates over up to `count` held-out lines, starting with the line at index `first`.
+    fn validation_range(
+        &self,
+        first: usize,
+        count: usize,
+    ) -> Box<dyn Iterator<Item = String> + '_> {
+        Box::new(self.validation_data().iter().skip(first).take(count).cloned())
+    }
+
+    /// Returns a matrix where each column is the one-hot vector of a character of the string.
+    fn encode(&self, s: &str) -> Result<CalcNode, DataSetError>;
+
+    /// Interprets a one-hot column vector as a character.
+    fn decode_char(&self, vector: &CalcNode) -> Result<char, DataSetError>;
+
+    /// The length of the one-hot vectors.
+    fn number_of_chars(&self) -> usize;
+
+    /// Makes the character known to the source if it is not already. Used to add the token marking the beginning and end of a line.
+    fn add_character(&mut self, c: char);
+}
@@ -0,0 +1,205 @@
+use std::{collections::HashMap, fs};
+
+use crate::nnetwork::{CalcNode, NodeType, VecOrientation};
+
+use super::{DataSetError, TextSource};
+
+/// Stands in for all words that are not part of the vocabulary.
+pub const UNKNOWN_WORD: &str = "<unk>";
+
+// Tokens are taken from a private use plane of Unicode, which holds no assigned characters
+const FIRST_TOKEN: u32 = 0xF0000;
+const MAX_TOKENS: u32 = 0xFFFE;
+
+/// Holds lines of text split into words together with a sorted vocabulary of all words, by which the [WordSet] encodes and decodes words to and from one-hot [CalcNode]s.
+///
+/// To fit the character based [TextSource] every word is represented by a single token character from the private use area of Unicode. The lines of the training and validation data are thus strings of tokens, and so are the predictions of a [crate::retext::ReText] using the [WordSet]. Use [WordSet::detokenize] to get the words back.
+pub struct WordSet {
+    _words: Vec<String>,
+    _tokens: Vec<char>,
+    // Lookups from word to token and from token to row of the one-hot vectors
+    _token_of_word: HashMap<String, char>,
+    _index_of_token: HashMap<char, usize>,
+    _training_data: Vec<String>,
+    _validation_data: Vec<String>,
+}
+
+impl WordSet {
+    /// Loads the data from the text file and splits every line on whitespace. Optionally all words are made lowercase. The training ratio decides how much of the data that is available for training and not reserved for validation.
+    pub fn new(path: &str, training_ratio: f32, lowercase: bool) -> Self {
+        let data = match fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(err) => panic!("Received {err:?} while importing dataset from {path}."),
+        };
+        let data = if lowercase { data.to_lowercase() } else { data };
+        Self::from_data(&data, training_ratio)
+    }
+
+    fn from_data(data: &str, training_ratio: f32) -> Self {
+        let mut words: Vec<String> = data.split_whitespace().map(|w| w.to_string()).collect();
+        words.push(UNKNOWN_WORD.to_string());
+        words.sort();
+        words.dedup();
+        assert!(
+            words.len() <= MAX_TOKENS as usize,
+            "The vocabulary is limited to {MAX_TOKENS} words."
+        );
+        let tokens: Vec<char> = (0..words.len() as u32)
+            .map(|i| char::from_u32(FIRST_TOKEN + i).unwrap())
+            .collect();
+        let token_of_word = words.iter().cloned().zip(tokens.iter().copied()).collect();
+        let index_of_token = tokens.iter().enumerate().map(|(i, &t)| (t, i)).collect();
+        let mut word_set = WordSet {
+            _words: words,
+            _tokens: tokens,
+            _token_of_word: token_of_word,
+            _index_of_token: index_of_token,
+            _training_data: Vec::new(),
+            _validation_data: Vec::new(),
+        };
+
+        // Split the same way as for a CharSet
+        let n_training = (data.lines().count() as f32 * training_ratio) as usize - 1;
+        let lines: Vec<String> = data.lines().map(|line| word_set.tokenize(line)).collect();
+        word_set._validation_data = lines[n_training..].to_vec();
+        word_set._training_data = lines[..n_training].to_vec();
+        word_set
+    }
+
+    /// Returns the vocabulary, i.e., all known words in alphabetical order including [UNKNOWN_WORD], followed by any characters added using [TextSource::add_character].
+    pub fn words(&self) -> &[String] {
+        &self._words
+    }
+
+    /// Converts the words of the line, separated by whitespace, into a string of tokens. Words outside the vocabulary become the token of [UNKNOWN_WORD].
+    pub fn tokenize(&self, line: &str) -> String {
+        let unknown = self.token_of(UNKNOWN_WORD).unwrap();
+        line.split_whitespace()
+            .map(|word| self.token_of(word).unwrap_or(unknown))
+            .collect()
+    }
+
+    /// Converts a string of tokens back into words separated by spaces.
+    pub fn detokenize(&self, tokens: &str) -> Result<String, DataSetError> {
+        let words = tokens
+            .chars()
+            .map(
+                |token| match self._index_of_token.get(&token) {
+                    Some(&index) => Ok(self._words[index].as_str()),
+                    None => Err(DataSetError::Encoding(token)),
+                },
+            )
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(words.join(" "))
+    }
+
+    fn token_of(&self, word: &str) -> Option<char> {
+        self._token_of_word.get(word).copied()
+    }
+}
+
+impl TextSource for WordSet {
+    fn training_data(&self) -> &[String] {
+        &self._training_data
+    }
+
+    fn validation_data(&self) -> &[String] {
+        &self._validation_data
+    }
+
+    /// Creates a matrix where each column is the one-hot vector of a token in the string.
+    fn encode(&self, s: &str) -> Result<CalcNode, DataSetError> {
+        let n_rows = self._tokens.len();
+        let n_cols = s.chars().count();
+        let mut out_vec = vec![0.; n_rows * n_cols];
+        for (col, token) in s.chars().enumerate() {
+            let row = *self
+                ._index_of_token
+                .get(&token)
+                .ok_or(DataSetError::Encoding(token))?;
+            out_vec[row * n_cols + col] = 1.;
+        }
+        Ok(CalcNode::new_from_shape((n_rows, n_cols), out_vec))
+    }
+
+    fn decode_char(&self, vector: &CalcNode) -> Result<char, DataSetError> {
+        if vector.node_type() != NodeType::Vector(VecOrientation::Column) {
+            panic!("Can only decode column vectors.");
+        }
+        let index: Vec<usize> = vector
+            .borrow()
+            .vals()
+            .iter()
+            .enumerate()
+            .filter_map(|(n, &elem)| if elem > 0. { Some(n) } else { None })
+            .collect();
+        if index.len() != 1 {
+            return Err(DataSetError::DecodingVector(vector.copy_vals()));
+        }
+        self._tokens
+            .get(index[0])
+            .copied()
+            .ok_or(DataSetError::DecodingIndex(index[0]))
+    }
+
+    fn number_of_chars(&self) -> usize {
+        self._tokens.len()
+    }
+
+    /// The character becomes its own token, standing for a word consisting of only that character.
+    fn add_character(&mut self, c: char) {
+        if !self._index_of_token.contains_key(&c) {
+            self._index_of_token.insert(c, self._tokens.len());
+            self._tokens.push(c);
+            // A word spelled the same keeps its own token
+            self._token_of_word.entry(c.to_string()).or_insert(c);
+            self._words.push(c.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vocabulary_and_round_trip() {
+        let words = WordSet::from_data("the cat sat\nthe dog sat down\nthe end", 1.);
+        // Six distinct words and the unknown word
+        assert_eq!(words.number_of_chars(), 7);
+        assert_eq!(
+            words.words(),
+            &[UNKNOWN_WORD, "cat", "dog", "down", "end", "sat", "the"]
+        );
+        assert_eq!(words.training_data().len(), 2);
+
+        let unseen = words.tokenize("bird");
+        assert_eq!(words.detokenize(&unseen), Ok(UNKNOWN_WORD.to_string()));
+
+        assert_eq!(
+            words.encode(&words.tokenize("the dog")).unwrap().shape(),
+            (7, 2)
+        );
+        let one_hot = words.encode(&words.tokenize("dog")).unwrap();
+        let token = words.decode_char(&one_hot).unwrap();
+        assert_eq!(words.detokenize(&token.to_string()), Ok("dog".to_string()));
+    }
+
+    #[test]
+    fn added_characters_are_encoded_after_the_words() {
+        let mut words = WordSet::from_data("a b
+b a
+c", 1.);
+        words.add_character('\n');
+        words.add_character('a');
+        assert_eq!(words.number_of_chars(), 6);
+        // The word "a" keeps its token while the character gets a new one
+        assert_eq!(words.detokenize(&words.tokenize("a")), Ok("a".to_string()));
+        assert_ne!(words.tokenize("a"), "a");
+        let encoded = words.encode("a\n").unwrap();
+        assert_eq!(encoded.shape(), (6, 2));
+        assert_eq!(words.decode_char(&encoded.column(1)), Ok('\n'));
+        assert_eq!(words.detokenize("a"), Ok("a".to_string()));
+        assert!(matches!(words.encode("x"), Err(DataSetError::Encoding('x'))));
+    }
+}